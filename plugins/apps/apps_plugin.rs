@@ -1,91 +1,111 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
 use app_finder::{AppCommon, AppFinder};
+use dossier_plugin_sdk::matcher::fuzzy_match;
+use dossier_plugin_sdk::{
+    export_plugin, ActionOutcome, HostContext, Plugin, PluginAction, PluginResult,
+    PluginSearchResult, PluginTrait, SearchContext,
+};
 use std::process::Command;
 use std::sync::OnceLock;
 use tokio::task;
 
 static APPS_CACHE: OnceLock<Vec<app_finder::App>> = OnceLock::new();
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "apps".to_string(),
-        name: "Applications".to_string(),
-        description: "Launch applications".to_string(),
-        prefix: "app".to_string(),
-        icon: "🚀".to_string(),
-        config: None,
+struct AppsPlugin;
+
+export_plugin!(AppsPlugin);
+
+impl PluginTrait for AppsPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "apps".to_string(),
+            name: "Applications".to_string(),
+            description: "Launch applications".to_string(),
+            prefix: "app".to_string(),
+            icon: "🚀".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
-}
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    let apps = APPS_CACHE.get_or_init(|| AppFinder::list());
-    let filtered_apps: Vec<_> = apps
-        .iter()
-        .filter(|app| {
-            if query.is_empty() {
-                true
-            } else {
-                app.name.to_lowercase().contains(&query.to_lowercase())
-            }
-        })
-        .take(20)
-        .collect();
+    // Listing the installed apps takes a while, done at startup instead of
+    // on the first search
+    fn init(&self, _context: &HostContext) {
+        APPS_CACHE.get_or_init(AppFinder::list);
+    }
 
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(async {
-        let tasks: Vec<_> = filtered_apps
-            .into_iter()
-            .map(|app| {
-                let app_clone = app.clone();
-                task::spawn_blocking(move || {
-                    let icon = app_clone
-                        .get_app_icon_base64(32)
-                        .ok()
-                        .unwrap_or_else(|| "🚀".to_string());
+    fn search(&self, query: &str) -> PluginSearchResult {
+        let context = SearchContext::none();
+        context.finish(self.search_with_context(query, &context))
+    }
 
-                    PluginResult {
-                        id: app_clone.path.to_string(),
-                        title: app_clone.name.clone(),
-                        subtitle: Some("Application".to_string()),
-                        icon: Some(icon),
-                        actions: Some(vec![PluginAction {
-                            id: "open".to_string(),
-                            label: "Open".to_string(),
-                            shortcut: Some("Enter".to_string()),
-                        }]),
-                    }
-                })
-            })
+    // Extracting icons is slow, so every app is shown as soon as its own
+    // icon is ready instead of after all of them
+    fn search_with_context(&self, query: &str, context: &SearchContext) -> PluginSearchResult {
+        let apps = APPS_CACHE.get_or_init(|| AppFinder::list());
+        let mut filtered_apps: Vec<_> = apps
+            .iter()
+            .filter_map(|app| Some((app, fuzzy_match(query, &app.name)?)))
             .collect();
+        filtered_apps.sort_by_key(|(_, m)| std::cmp::Reverse(m.score));
+        filtered_apps.truncate(20);
 
-        let mut results = Vec::new();
-        for task in tasks {
-            if let Ok(result) = task.await {
-                results.push(result);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let tasks: Vec<_> = filtered_apps
+                .into_iter()
+                .map(|(app, m)| {
+                    let app_clone = app.clone();
+                    task::spawn_blocking(move || {
+                        let icon = app_clone
+                            .get_app_icon_base64(32)
+                            .ok()
+                            .unwrap_or_else(|| "🚀".to_string());
+
+                        PluginResult {
+                            id: app_clone.path.to_string(),
+                            title: app_clone.name.clone(),
+                            subtitle: Some("Application".to_string()),
+                            icon: Some(icon),
+                            actions: Some(vec![PluginAction {
+                                id: "open".to_string(),
+                                label: "Open".to_string(),
+                                shortcut: Some("Enter".to_string()),
+                                dangerous: false,
+                                confirmation: None,
+                            }]),
+                            score: Some(m.score),
+                            highlights: Some(m.ranges),
+                        }
+                    })
+                })
+                .collect();
+
+            for task in tasks {
+                if context.is_cancelled() {
+                    break;
+                }
+                if let Ok(result) = task.await {
+                    context.emit(vec![result]);
+                }
             }
-        }
-        PluginSearchResult::Results(results)
-    })
-}
+            PluginSearchResult::Results(vec![])
+        })
+    }
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(result_id: String, action_id: String) -> Result<String, String> {
-    match action_id.as_str() {
-        "open" => {
-            #[cfg(target_os = "windows")]
-            {
-                use std::os::windows::process::CommandExt;
-                Command::new(&result_id)
-                    .creation_flags(0x08000000)
-                    .spawn()
-                    .map_err(|e| format!("Failed to open app: {}", e))?;
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "open" => {
+                #[cfg(target_os = "windows")]
+                {
+                    use std::os::windows::process::CommandExt;
+                    Command::new(result_id)
+                        .creation_flags(0x08000000)
+                        .spawn()
+                        .map_err(|e| format!("Failed to open app: {}", e))?;
+                }
+                Ok(ActionOutcome::Close)
             }
-            Ok("App launched".to_string())
+            _ => Err("Unknown action".to_string()),
         }
-        _ => Err("Unknown action".to_string()),
     }
-}
\ No newline at end of file
+}
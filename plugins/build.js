@@ -19,13 +19,40 @@ function ensureDir(dir) {
 	}
 }
 
+// Only cdylib crates are plugins, library crates such as the SDK are skipped.
+function isPluginCrate(dir) {
+	const manifest = path.join(dir, 'Cargo.toml');
+	return fs.existsSync(manifest) && fs.readFileSync(manifest, 'utf8').includes('cdylib');
+}
+
+// Plugins with a plugin.toml are installed in a directory of their own,
+// older ones next to each other in the plugins directory.
+function hasManifest(pluginDir) {
+	return fs.existsSync(path.join(pluginDir, 'plugin.toml'));
+}
+
+function pluginDestDir(pluginDir) {
+	return hasManifest(pluginDir) ? path.join(BUILD_DIR, path.basename(pluginDir)) : BUILD_DIR;
+}
+
+function copyManifest(pluginDir) {
+	if (hasManifest(pluginDir)) {
+		fs.copyFileSync(path.join(pluginDir, 'plugin.toml'), path.join(pluginDestDir(pluginDir), 'plugin.toml'));
+	}
+}
+
+// A copy installed by a previous build without manifest would be loaded twice
+function removeFlatCopy(destDir, file) {
+	const flat = path.join(BUILD_DIR, path.basename(file));
+	if (destDir !== BUILD_DIR && fs.existsSync(flat)) {
+		fs.rmSync(flat);
+	}
+}
+
 function getPluginDirs() {
 	return fs
 		.readdirSync(PLUGINS_DIR, { withFileTypes: true })
-		.filter(
-			(dirent) =>
-				dirent.isDirectory() && fs.existsSync(path.join(PLUGINS_DIR, dirent.name, 'Cargo.toml'))
-		)
+		.filter((dirent) => dirent.isDirectory() && isPluginCrate(path.join(PLUGINS_DIR, dirent.name)))
 		.map((dirent) => path.join(PLUGINS_DIR, dirent.name));
 }
 
@@ -53,11 +80,15 @@ function buildPlugin(pluginDir) {
 				libFiles.map((f) => path.basename(f))
 			);
 
+			const destDir = pluginDestDir(pluginDir);
+			ensureDir(destDir);
 			libFiles.forEach((file) => {
-				const dest = path.join(BUILD_DIR, path.basename(file));
+				const dest = path.join(destDir, path.basename(file));
 				fs.copyFileSync(file, dest);
+				removeFlatCopy(destDir, file);
 				copied = true;
 			});
+			copyManifest(pluginDir);
 
 			if (copied) {
 				console.log(`[${path.basename(pluginDir)}] ✓ Built and copied to ${BUILD_DIR}`);
@@ -89,7 +120,12 @@ function watchFiles() {
 	ensureDir(BUILD_DIR);
 
 	const pluginDirs = getPluginDirs();
-	const watchPaths = pluginDirs.map((dir) => `${dir}/*.rs`);
+	const sdkDir = path.join(PLUGINS_DIR, 'sdk');
+	const watchPaths = [
+		...pluginDirs.map((dir) => `${dir}/*.rs`),
+		...pluginDirs.map((dir) => `${dir}/plugin.toml`),
+		`${sdkDir}/src/*.rs`
+	];
 
 	const watcher = chokidar.watch(watchPaths, {
 		persistent: true
@@ -98,12 +134,26 @@ function watchFiles() {
 	const debounce = new Map();
 
 	watcher.on('change', (filePath) => {
+		// Every plugin links the SDK, rebuild all of them when it changes
+		if (filePath.startsWith(sdkDir)) {
+			pluginDirs.forEach((dir) => buildPlugin(dir));
+			return;
+		}
+
 		const pluginDir = path.dirname(filePath);
 
 		if (!fs.existsSync(path.join(pluginDir, 'Cargo.toml'))) {
 			return;
 		}
 
+		// Manifest edits only need to be installed, Dossier reloads the plugin
+		if (path.basename(filePath) === 'plugin.toml') {
+			ensureDir(pluginDestDir(pluginDir));
+			copyManifest(pluginDir);
+			console.log(`[${path.basename(pluginDir)}] ✓ Manifest updated`);
+			return;
+		}
+
 		// Debounce builds
 		if (debounce.has(pluginDir)) {
 			clearTimeout(debounce.get(pluginDir));
@@ -1,69 +1,140 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
+use dossier_plugin_sdk::matcher::fuzzy_match;
+use dossier_plugin_sdk::services::{self, LogLevel};
+use dossier_plugin_sdk::{
+    export_plugin, ActionOutcome, ConfigField, ConfigFieldKind, ConfigValues, HostContext, Plugin,
+    PluginAction, PluginConfig, PluginResult, PluginSearchResult, PluginTrait,
+};
 
-use arboard::Clipboard;
 use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cmp::Reverse;
 use std::collections::VecDeque;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ClipboardEntry {
     content: String,
     timestamp: DateTime<Local>,
 }
 
-static CLIPBOARD_HISTORY: Mutex<VecDeque<ClipboardEntry>> = Mutex::new(VecDeque::new());
-const MAX_HISTORY: usize = 100;
+// Key of the history in the plugin's storage
+const HISTORY_KEY: &str = "history";
+static CLIPBOARD_HISTORY: OnceLock<Mutex<VecDeque<ClipboardEntry>>> = OnceLock::new();
+const DEFAULT_HISTORY_SIZE: usize = 100;
+// Characters of the content shown in a title
+const TITLE_LEN: usize = 50;
+// Entries kept, from the historySize setting
+static HISTORY_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_HISTORY_SIZE);
+
+/// The history, loaded from storage the first time it is needed.
+fn history() -> &'static Mutex<VecDeque<ClipboardEntry>> {
+    CLIPBOARD_HISTORY.get_or_init(|| {
+        let stored = services::host().storage_get(HISTORY_KEY);
+        let mut history: VecDeque<ClipboardEntry> = match stored {
+            Ok(history) => history.unwrap_or_default(),
+            Err(e) => {
+                log_error("Failed to load history", &e);
+                VecDeque::new()
+            }
+        };
+        history.truncate(HISTORY_SIZE.load(Ordering::Relaxed));
+        Mutex::new(history)
+    })
+}
+
+fn save_history(history: &VecDeque<ClipboardEntry>) {
+    if let Err(e) = services::host().storage_set(HISTORY_KEY, history) {
+        log_error("Failed to save history", &e);
+    }
+}
+
+fn log_error(context: &str, error: &str) {
+    services::host().log(LogLevel::Error, &format!("{}: {}", context, error));
+}
 
 fn add_to_history(content: String) {
-    if let Ok(mut history) = CLIPBOARD_HISTORY.lock() {
+    if let Ok(mut history) = history().lock() {
         // Don't add if it's already the most recent entry
-        if history.front().map_or(true, |entry| entry.content != content) {
+        if history
+            .front()
+            .map_or(true, |entry| entry.content != content)
+        {
             let entry = ClipboardEntry {
                 content,
                 timestamp: Local::now(),
             };
             history.push_front(entry);
-            if history.len() > MAX_HISTORY {
-                history.pop_back();
-            }
+            history.truncate(HISTORY_SIZE.load(Ordering::Relaxed));
+            save_history(&history);
         }
     }
 }
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "clipboard".to_string(),
-        name: "Clipboard".to_string(),
-        description: "Manage clipboard history and operations".to_string(),
-        prefix: "c".to_string(),
-        icon: "📋".to_string(),
-        config: None,
+struct ClipboardPlugin;
+
+export_plugin!(ClipboardPlugin);
+
+impl PluginTrait for ClipboardPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "clipboard".to_string(),
+            name: "Clipboard".to_string(),
+            description: "Manage clipboard history and operations".to_string(),
+            prefix: "c".to_string(),
+            icon: "📋".to_string(),
+            config: Some(PluginConfig {
+                fields: vec![ConfigField {
+                    key: "historySize".to_string(),
+                    label: "History size".to_string(),
+                    description: Some(
+                        "Copied texts remembered, oldest ones are forgotten first".to_string(),
+                    ),
+                    kind: ConfigFieldKind::Integer {
+                        min: Some(1),
+                        max: Some(1000),
+                    },
+                    default: json!(DEFAULT_HISTORY_SIZE),
+                }],
+            }),
+            capabilities: Vec::new(),
+        }
     }
-}
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    let mut results = Vec::new();
-    
-    // Auto-add current clipboard to history if not empty
-    if let Ok(mut clipboard) = Clipboard::new() {
-        if let Ok(current) = clipboard.get_text() {
-            if !current.trim().is_empty() {
-                add_to_history(current.clone());
+    fn configure(&self, values: &ConfigValues) {
+        if let Some(size) = values.get::<usize>("historySize") {
+            HISTORY_SIZE.store(size, Ordering::Relaxed);
+            if let Ok(mut history) = history().lock() {
+                if history.len() > size {
+                    history.truncate(size);
+                    save_history(&history);
+                }
             }
         }
     }
 
-    // Add current clipboard content
-    if let Ok(mut clipboard) = Clipboard::new() {
-        if let Ok(current) = clipboard.get_text() {
-            if query.is_empty() || current.to_lowercase().contains(&query.to_lowercase()) {
+    fn init(&self, _: &HostContext) {
+        history();
+    }
+
+    fn search(&self, query: &str) -> PluginSearchResult {
+        let mut results = Vec::new();
+
+        let current = services::host().read_clipboard().ok();
+
+        // Auto-add current clipboard to history if not empty
+        if let Some(current) = current.as_ref().filter(|c| !c.trim().is_empty()) {
+            add_to_history(current.clone());
+        }
+
+        // Add current clipboard content
+        if let Some(current) = current {
+            if let Some(m) = fuzzy_match(query, &current) {
+                let (title, highlights) = title_of("📋", &current, &m.ranges);
                 results.push(PluginResult {
                     id: current.clone(),
-                    title: format!("📋 {}", truncate(&current, 50)),
+                    title,
                     subtitle: Some("Current clipboard".to_string()),
                     icon: None,
                     actions: Some(vec![
@@ -71,77 +142,87 @@ pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
                             id: "copy".to_string(),
                             label: "Copy".to_string(),
                             shortcut: Some("Enter".to_string()),
+                            dangerous: false,
+                            confirmation: None,
                         },
                         PluginAction {
                             id: "clear".to_string(),
                             label: "Clear".to_string(),
                             shortcut: Some("Ctrl+D".to_string()),
+                            dangerous: false,
+                            confirmation: None,
                         },
                     ]),
+                    score: Some(m.score),
+                    highlights: Some(highlights),
                 });
             }
         }
-    }
 
-    // Add history entries
-    if let Ok(history) = CLIPBOARD_HISTORY.lock() {
-        for entry in history.iter() {
-            if query.is_empty() || entry.content.to_lowercase().contains(&query.to_lowercase()) {
-                results.push(PluginResult {
-                    id: entry.content.clone(),
-                    title: format!("🕒 {}", truncate(&entry.content, 50)),
-                    subtitle: Some(format!("Copied {}", format_time_ago(&entry.timestamp))),
-                    icon: None,
-                    actions: Some(vec![PluginAction {
-                        id: "copy".to_string(),
-                        label: "Copy".to_string(),
-                        shortcut: Some("Enter".to_string()),
-                    }]),
-                });
+        // Add history entries
+        if let Ok(history) = history().lock() {
+            for entry in history.iter() {
+                if let Some(m) = fuzzy_match(query, &entry.content) {
+                    let (title, highlights) = title_of("🕒", &entry.content, &m.ranges);
+                    results.push(PluginResult {
+                        id: entry.content.clone(),
+                        title,
+                        subtitle: Some(format!("Copied {}", format_time_ago(&entry.timestamp))),
+                        icon: None,
+                        actions: Some(vec![PluginAction {
+                            id: "copy".to_string(),
+                            label: "Copy".to_string(),
+                            shortcut: Some("Enter".to_string()),
+                            dangerous: false,
+                            confirmation: None,
+                        }]),
+                        score: Some(m.score),
+                        highlights: Some(highlights),
+                    });
+                }
             }
         }
+        // Best matches first, most recent first among equally good ones
+        results.sort_by_key(|result| Reverse(result.score));
+        results.truncate(20);
+        PluginSearchResult::Results(results)
     }
-    results.truncate(20);
-    PluginSearchResult::Results(results)
-}
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(
-    result_id: String,
-    action_id: String,
-) -> Result<String, String> {
-    match action_id.as_str() {
-        "copy" => {
-            match Clipboard::new() {
-                Ok(mut clipboard) => {
-                    match clipboard.set_text(&result_id) {
-                        Ok(_) => {
-                            add_to_history(result_id);
-                            Ok("Copied to clipboard".to_string())
-                        }
-                        Err(e) => Err(format!("Failed to copy: {}", e)),
-                    }
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "copy" => match services::host().write_clipboard(result_id) {
+                Ok(_) => {
+                    add_to_history(result_id.to_string());
+                    Ok(ActionOutcome::Close)
                 }
-                Err(e) => Err(format!("Failed to access clipboard: {}", e)),
-            }
-        }
-        "clear" => match Clipboard::new() {
-            Ok(mut clipboard) => match clipboard.clear() {
-                Ok(_) => Ok("Clipboard cleared".to_string()),
+                Err(e) => Err(format!("Failed to copy: {}", e)),
+            },
+            "clear" => match services::host().write_clipboard("") {
+                Ok(_) => Ok(ActionOutcome::Refresh),
                 Err(e) => Err(format!("Failed to clear clipboard: {}", e)),
             },
-            Err(e) => Err(format!("Failed to access clipboard: {}", e)),
-        },
-        _ => Err("Unknown action".to_string()),
+            _ => Err("Unknown action".to_string()),
+        }
     }
 }
 
-fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
-        s.to_string()
+/// Title showing `content` after `icon`, cut to [`TITLE_LEN`] characters,
+/// with the `ranges` matched in `content` moved to where they are in it.
+fn title_of(icon: &str, content: &str, ranges: &[(usize, usize)]) -> (String, Vec<(usize, usize)>) {
+    let len = content.chars().count();
+    let (visible, ellipsis) = if len <= TITLE_LEN {
+        (len, "")
     } else {
-        format!("{}...", &s[..max_len.saturating_sub(3)])
-    }
+        (TITLE_LEN - 3, "...")
+    };
+    let offset = icon.chars().count() + 1;
+    let highlights = ranges
+        .iter()
+        .filter(|(start, _)| *start < visible)
+        .map(|&(start, end)| (start + offset, end.min(visible) + offset))
+        .collect();
+    let shown: String = content.chars().take(visible).collect();
+    (format!("{} {}{}", icon, shown, ellipsis), highlights)
 }
 
 fn format_time_ago(timestamp: &DateTime<Local>) -> String {
@@ -1,7 +1,7 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use arboard::Clipboard;
-use plugins::{Plugin, PluginHtmlResult, PluginSearchResult};
+use dossier_plugin_sdk::{
+    export_plugin, services, ActionOutcome, Plugin, PluginHtmlResult, PluginSearchResult,
+    PluginTrait,
+};
 
 #[derive(Debug, Clone)]
 struct Color {
@@ -308,32 +308,47 @@ fn generate_color_html(color: &Color, original_input: &str) -> String {
     html
 }
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "color".to_string(),
-        name: "Color Converter".to_string(),
-        description: "Convert and preview colors in different formats".to_string(),
-        prefix: "color".to_string(),
-        icon: "🎨".to_string(),
-        config: None,
+struct ColorPlugin;
+
+export_plugin!(ColorPlugin);
+
+impl PluginTrait for ColorPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "color".to_string(),
+            name: "Color Converter".to_string(),
+            description: "Convert and preview colors in different formats".to_string(),
+            prefix: "color".to_string(),
+            icon: "🎨".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
-}
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    if query.is_empty() {
-        let html = generate_example_colors();
-        return PluginSearchResult::Html(PluginHtmlResult { html });
+    fn search(&self, query: &str) -> PluginSearchResult {
+        if query.is_empty() {
+            let html = generate_example_colors();
+            return PluginSearchResult::Html(PluginHtmlResult { html });
+        }
+
+        if let Some(color) = parse_color(query) {
+            let html = generate_color_html(&color, query);
+            PluginSearchResult::Html(PluginHtmlResult { html })
+        } else {
+            // Show format examples if no valid color detected
+            let html = generate_format_help(query);
+            PluginSearchResult::Html(PluginHtmlResult { html })
+        }
     }
 
-    if let Some(color) = parse_color(&query) {
-        let html = generate_color_html(&color, &query);
-        PluginSearchResult::Html(PluginHtmlResult { html })
-    } else {
-        // Show format examples if no valid color detected
-        let html = generate_format_help(&query);
-        PluginSearchResult::Html(PluginHtmlResult { html })
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "copy" => services::host()
+                .write_clipboard(result_id)
+                .map(|_| ActionOutcome::Close)
+                .map_err(|e| format!("Failed to copy: {}", e)),
+            _ => Err("Unknown action".to_string()),
+        }
     }
 }
 
@@ -401,20 +416,3 @@ fn generate_format_help(query: &str) -> String {
 
     html
 }
-
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(
-    result_id: String,
-    action_id: String,
-) -> Result<String, String> {
-    match action_id.as_str() {
-        "copy" => match Clipboard::new() {
-            Ok(mut clipboard) => match clipboard.set_text(&result_id) {
-                Ok(_) => Ok("Copied to clipboard".to_string()),
-                Err(e) => Err(format!("Failed to copy: {}", e)),
-            },
-            Err(e) => Err(format!("Failed to access clipboard: {}", e)),
-        },
-        _ => Err("Unknown action".to_string()),
-    }
-}
@@ -1,8 +1,9 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginHtmlResult, PluginResult, PluginSearchResult};
+use dossier_plugin_sdk::services::{self, LogLevel};
+use dossier_plugin_sdk::{
+    export_plugin, ActionOutcome, HostContext, Plugin, PluginHtmlResult, PluginSearchResult,
+    PluginTrait,
+};
 
-use arboard::Clipboard;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
@@ -28,115 +29,138 @@ struct EmojiIndex {
 /// A global, lazily-initialized static variable for our emoji database.
 /// The code inside `Lazy::new` will run only once, the first time EMOJI_DB is accessed.
 static EMOJI_DB: Lazy<EmojiIndex> = Lazy::new(|| {
-    println!("[Emoji Plugin] Initializing index...");
+    services::host().log(LogLevel::Debug, "Initializing index...");
 
     let mut index: HashMap<String, Vec<String>> = HashMap::new();
     let mut emojis = HashMap::new();
     let url = "https://unpkg.com/unicode-emoji-json/data-by-emoji.json";
 
-    match reqwest::blocking::get(url) {
-        Ok(resp) => match resp.json::<HashMap<String, Emoji>>() {
-            Ok(data) => {
-                for (emoji_char, emoji_data) in data {
-                    emojis.insert(emoji_char.clone(), emoji_data.clone());
-                    let full_text =
-                        format!("{} {}", emoji_data.name, emoji_data.slug).to_lowercase();
-                    if full_text.chars().count() >= NGRAM_SIZE {
-                        let chars: Vec<char> = full_text.chars().collect();
-                        for i in 0..=(chars.len() - NGRAM_SIZE) {
-                            let ngram: String = chars[i..i + NGRAM_SIZE].iter().collect();
-                            index.entry(ngram).or_default().push(emoji_char.clone());
-                        }
+    let data = services::host()
+        .http_get(url)
+        .map(|body| serde_json::from_str::<HashMap<String, Emoji>>(&body));
+    match data {
+        Ok(Ok(data)) => {
+            for (emoji_char, emoji_data) in data {
+                emojis.insert(emoji_char.clone(), emoji_data.clone());
+                let full_text = format!("{} {}", emoji_data.name, emoji_data.slug).to_lowercase();
+                if full_text.chars().count() >= NGRAM_SIZE {
+                    let chars: Vec<char> = full_text.chars().collect();
+                    for i in 0..=(chars.len() - NGRAM_SIZE) {
+                        let ngram: String = chars[i..i + NGRAM_SIZE].iter().collect();
+                        index.entry(ngram).or_default().push(emoji_char.clone());
                     }
                 }
             }
-            Err(e) => println!("[Emoji Plugin] JSON parse error: {}", e),
-        },
-        Err(e) => println!("[Emoji Plugin] Network error: {}", e),
+        }
+        Ok(Err(e)) => services::host().log(LogLevel::Error, &format!("JSON parse error: {}", e)),
+        Err(e) => services::host().log(LogLevel::Error, &format!("Network error: {}", e)),
     }
 
-    println!("[Emoji Plugin] Index loaded with {} emojis.", emojis.len());
+    services::host().log(
+        LogLevel::Info,
+        &format!("Index loaded with {} emojis.", emojis.len()),
+    );
     EmojiIndex { emojis, index }
 });
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "emojis".to_string(),
-        name: "Emojis".to_string(),
-        description: "Search for emojis by name or shortcode".to_string(),
-        prefix: "e".to_string(),
-        icon: "😀".to_string(),
-        config: None,
-    }
-}
+struct EmojiPlugin;
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    let query = query.to_lowercase();
-
-    if query.is_empty() {
-        // Show popular emojis when no query
-        let popular_emojis = [
-            "😀", "😂", "😍", "🥰", "😊", "😎", "🤔", "😢", "😡", "🤯", "👍", "👎", "❤️", "🔥",
-            "💯", "🎉", "🚀", "⭐", "🌟", "💡",
-        ];
-        let emoji_vec: Vec<(String, String)> = popular_emojis
-            .iter()
-            .map(|&e| (e.to_string(), format!("Popular emoji: {}", e)))
-            .collect();
-        let html = generate_emoji_grid(&emoji_vec);
-        return PluginSearchResult::Html(PluginHtmlResult { html });
-    }
+export_plugin!(EmojiPlugin);
 
-    // Don't bother searching if the query is too short for our n-gram index.
-    if query.chars().count() < NGRAM_SIZE {
-        return PluginSearchResult::Results(vec![]);
+impl PluginTrait for EmojiPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "emojis".to_string(),
+            name: "Emojis".to_string(),
+            description: "Search for emojis by name or shortcode".to_string(),
+            prefix: "e".to_string(),
+            icon: "😀".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
 
-    let mut query_ngrams = Vec::new();
-    let query_chars: Vec<char> = query.chars().collect();
-    for i in 0..=(query_chars.len() - NGRAM_SIZE) {
-        let ngram: String = query_chars[i..i + NGRAM_SIZE].iter().collect();
-        query_ngrams.push(ngram);
+    // Downloads the database at startup instead of on the first search
+    fn init(&self, _context: &HostContext) {
+        Lazy::force(&EMOJI_DB);
     }
 
-    // Step 2: Find candidate emojis by intersecting results from all n-grams.
-    // This is more accurate than just using the first n-gram.
-    let mut candidate_sets = query_ngrams.into_iter().map(|ngram| {
-        EMOJI_DB
-            .index
-            .get(&ngram)
-            .map(|v| v.iter().collect::<HashSet<_>>())
-            .unwrap_or_default()
-    });
-
-    // Take the first set as the base for our intersection.
-    let Some(mut final_candidates_set) = candidate_sets.next() else {
-        return PluginSearchResult::Results(vec![]);
-    };
-
-    // Intersect with the rest of the sets.
-    for next_set in candidate_sets {
-        final_candidates_set.retain(|item| next_set.contains(item));
-    }
+    fn search(&self, query: &str) -> PluginSearchResult {
+        let query = query.to_lowercase();
+
+        if query.is_empty() {
+            // Show popular emojis when no query
+            let popular_emojis = [
+                "😀", "😂", "😍", "🥰", "😊", "😎", "🤔", "😢", "😡", "🤯", "👍", "👎", "❤️", "🔥",
+                "💯", "🎉", "🚀", "⭐", "🌟", "💡",
+            ];
+            let emoji_vec: Vec<(String, String)> = popular_emojis
+                .iter()
+                .map(|&e| (e.to_string(), format!("Popular emoji: {}", e)))
+                .collect();
+            let html = generate_emoji_grid(&emoji_vec);
+            return PluginSearchResult::Html(PluginHtmlResult { html });
+        }
+
+        // Don't bother searching if the query is too short for our n-gram index.
+        if query.chars().count() < NGRAM_SIZE {
+            return PluginSearchResult::Results(vec![]);
+        }
+
+        let mut query_ngrams = Vec::new();
+        let query_chars: Vec<char> = query.chars().collect();
+        for i in 0..=(query_chars.len() - NGRAM_SIZE) {
+            let ngram: String = query_chars[i..i + NGRAM_SIZE].iter().collect();
+            query_ngrams.push(ngram);
+        }
 
-    let mut final_emojis = Vec::new();
-    for emoji_char in final_candidates_set {
-        if let Some(emoji_data) = EMOJI_DB.emojis.get(emoji_char) {
-            let full_text = format!("{} {}", emoji_data.name, emoji_data.slug).to_lowercase();
-            if full_text.contains(&query) {
-                final_emojis.push((emoji_char.clone(), emoji_data.name.clone()));
+        // Step 2: Find candidate emojis by intersecting results from all n-grams.
+        // This is more accurate than just using the first n-gram.
+        let mut candidate_sets = query_ngrams.into_iter().map(|ngram| {
+            EMOJI_DB
+                .index
+                .get(&ngram)
+                .map(|v| v.iter().collect::<HashSet<_>>())
+                .unwrap_or_default()
+        });
+
+        // Take the first set as the base for our intersection.
+        let Some(mut final_candidates_set) = candidate_sets.next() else {
+            return PluginSearchResult::Results(vec![]);
+        };
+
+        // Intersect with the rest of the sets.
+        for next_set in candidate_sets {
+            final_candidates_set.retain(|item| next_set.contains(item));
+        }
+
+        let mut final_emojis = Vec::new();
+        for emoji_char in final_candidates_set {
+            if let Some(emoji_data) = EMOJI_DB.emojis.get(emoji_char) {
+                let full_text = format!("{} {}", emoji_data.name, emoji_data.slug).to_lowercase();
+                if full_text.contains(&query) {
+                    final_emojis.push((emoji_char.clone(), emoji_data.name.clone()));
+                }
             }
         }
-    }
 
-    if final_emojis.is_empty() {
-        return PluginSearchResult::Results(vec![]);
+        if final_emojis.is_empty() {
+            return PluginSearchResult::Results(vec![]);
+        }
+
+        let html = generate_emoji_grid(&final_emojis);
+        PluginSearchResult::Html(PluginHtmlResult { html })
     }
 
-    let html = generate_emoji_grid(&final_emojis);
-    PluginSearchResult::Html(PluginHtmlResult { html })
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "copy" => services::host()
+                .write_clipboard(result_id)
+                .map(|_| ActionOutcome::Close)
+                .map_err(|e| format!("Failed to copy: {}", e)),
+            _ => Err("Unknown action".to_string()),
+        }
+    }
 }
 
 fn generate_emoji_grid(emojis: &[(String, String)]) -> String {
@@ -194,20 +218,3 @@ fn generate_emoji_grid(emojis: &[(String, String)]) -> String {
 
     html
 }
-
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(
-    result_id: String,
-    action_id: String,
-) -> Result<String, String> {
-    match action_id.as_str() {
-        "copy" => match Clipboard::new() {
-            Ok(mut clipboard) => match clipboard.set_text(&result_id) {
-                Ok(_) => Ok("Copied to clipboard".to_string()),
-                Err(e) => Err(format!("Failed to copy: {}", e)),
-            },
-            Err(e) => Err(format!("Failed to access clipboard: {}", e)),
-        },
-        _ => Err("Unknown action".to_string()),
-    }
-}
@@ -0,0 +1,82 @@
+"""Example stdio plugin: evaluates arithmetic typed after the `=` prefix.
+
+Dossier starts this script with the plugin directory as working directory and
+sends one JSON-RPC request per line on stdin; each response goes on one line
+of stdout. Anything meant for humans must be written to stderr.
+"""
+
+import ast
+import json
+import operator
+import sys
+
+OPERATORS = {
+    ast.Add: operator.add,
+    ast.Sub: operator.sub,
+    ast.Mult: operator.mul,
+    ast.Div: operator.truediv,
+    ast.Pow: operator.pow,
+    ast.Mod: operator.mod,
+    ast.USub: operator.neg,
+}
+
+
+def evaluate(node):
+    if isinstance(node, ast.Expression):
+        return evaluate(node.body)
+    if isinstance(node, ast.Constant) and isinstance(node.value, (int, float)):
+        return node.value
+    if isinstance(node, ast.BinOp) and type(node.op) in OPERATORS:
+        return OPERATORS[type(node.op)](evaluate(node.left), evaluate(node.right))
+    if isinstance(node, ast.UnaryOp) and type(node.op) in OPERATORS:
+        return OPERATORS[type(node.op)](evaluate(node.operand))
+    raise ValueError("unsupported expression")
+
+
+def info(_params):
+    return {
+        "id": "python-calc",
+        "name": "Calculator",
+        "description": "Evaluate arithmetic expressions",
+        "prefix": "=",
+        "icon": "🧮",
+    }
+
+
+def search(params):
+    query = params.get("query", "").strip()
+    try:
+        value = evaluate(ast.parse(query, mode="eval"))
+    except (SyntaxError, ValueError, ZeroDivisionError, OverflowError):
+        return []
+    return [
+        {
+            "id": str(value),
+            "title": str(value),
+            "subtitle": query,
+            "icon": "🧮",
+            "actions": [{"id": "show", "label": "Show result"}],
+        }
+    ]
+
+
+def execute(params):
+    print(f"executing {params['action_id']}", file=sys.stderr)
+    return {"type": "toast", "message": f"Result: {params['result_id']}"}
+
+
+METHODS = {"info": info, "search": search, "execute": execute}
+
+
+for line in sys.stdin:
+    if not line.strip():
+        continue
+    request = json.loads(line)
+    if "id" not in request:
+        continue  # notifications such as cancel need no reply
+    response = {"jsonrpc": "2.0", "id": request["id"]}
+    try:
+        response["result"] = METHODS[request["method"]](request.get("params") or {})
+    except Exception as e:  # reported to Dossier instead of killing the plugin
+        response["error"] = {"code": -32000, "message": str(e)}
+    print(json.dumps(response), flush=True)
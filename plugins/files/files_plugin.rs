@@ -1,6 +1,7 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
+use dossier_plugin_sdk::{
+    export_plugin, ActionOutcome, Plugin, PluginAction, PluginResult, PluginSearchResult,
+    PluginTrait,
+};
 use std::process::Command;
 
 #[cfg(target_os = "windows")]
@@ -61,94 +62,101 @@ fn search_everything(query: &str) -> Vec<SearchResult> {
     results
 }
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "files".to_string(),
-        name: "Files".to_string(),
-        description: "Search files with Everything".to_string(),
-        prefix: "f".to_string(),
-        icon: "📁".to_string(),
-        config: None,
-    }
-}
+struct FilesPlugin;
+
+export_plugin!(FilesPlugin);
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    if query.is_empty() {
-        return PluginSearchResult::Results(vec![]);
+impl PluginTrait for FilesPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "files".to_string(),
+            name: "Files".to_string(),
+            description: "Search files with Everything".to_string(),
+            prefix: "f".to_string(),
+            icon: "📁".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
 
-    let results = search_everything(&query);
-
-    let plugin_results = results
-        .iter()
-        .map(|result| {
-            let filename = std::path::Path::new(&result.filepath)
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-
-            let size_str = if result.size > 1024 * 1024 {
-                format!("{:.1} MB", result.size as f64 / (1024.0 * 1024.0))
-            } else if result.size > 1024 {
-                format!("{:.1} KB", result.size as f64 / 1024.0)
-            } else {
-                format!("{} B", result.size)
-            };
-
-            PluginResult {
-                id: result.filepath.clone(),
-                title: filename,
-                subtitle: Some(format!("{} • {}", result.filepath, size_str)),
-                icon: Some("📄".to_string()),
-                actions: Some(vec![
-                    PluginAction {
-                        id: "open".to_string(),
-                        label: "Open".to_string(),
-                        shortcut: Some("Enter".to_string()),
-                    },
-                    PluginAction {
-                        id: "open_folder".to_string(),
-                        label: "Open Folder".to_string(),
-                        shortcut: Some("Ctrl+O".to_string()),
-                    },
-                ]),
-            }
-        })
-        .collect();
+    fn search(&self, query: &str) -> PluginSearchResult {
+        if query.is_empty() {
+            return PluginSearchResult::Results(vec![]);
+        }
 
-    PluginSearchResult::Results(plugin_results)
-}
+        let results = search_everything(query);
+
+        let plugin_results = results
+            .iter()
+            .map(|result| {
+                let filename = std::path::Path::new(&result.filepath)
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+
+                let size_str = if result.size > 1024 * 1024 {
+                    format!("{:.1} MB", result.size as f64 / (1024.0 * 1024.0))
+                } else if result.size > 1024 {
+                    format!("{:.1} KB", result.size as f64 / 1024.0)
+                } else {
+                    format!("{} B", result.size)
+                };
+
+                PluginResult {
+                    id: result.filepath.clone(),
+                    title: filename,
+                    subtitle: Some(format!("{} • {}", result.filepath, size_str)),
+                    icon: Some("📄".to_string()),
+                    actions: Some(vec![
+                        PluginAction {
+                            id: "open".to_string(),
+                            label: "Open".to_string(),
+                            shortcut: Some("Enter".to_string()),
+                            dangerous: false,
+                            confirmation: None,
+                        },
+                        PluginAction {
+                            id: "open_folder".to_string(),
+                            label: "Open Folder".to_string(),
+                            shortcut: Some("Ctrl+O".to_string()),
+                            dangerous: false,
+                            confirmation: None,
+                        },
+                    ]),
+                    score: None,
+                    highlights: None,
+                }
+            })
+            .collect();
+
+        PluginSearchResult::Results(plugin_results)
+    }
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(
-    result_id: String,
-    action_id: String,
-) -> Result<String, String> {
-    match action_id.as_str() {
-        "open" => {
-            #[cfg(target_os = "windows")]
-            {
-                Command::new("cmd")
-                    .args(["/c", "start", "", &result_id])
-                    .spawn()
-                    .map_err(|e| format!("Failed to open file: {}", e))?;
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "open" => {
+                #[cfg(target_os = "windows")]
+                {
+                    Command::new("cmd")
+                        .args(["/c", "start", "", result_id])
+                        .spawn()
+                        .map_err(|e| format!("Failed to open file: {}", e))?;
+                }
+                Ok(ActionOutcome::Close)
             }
-            Ok("File opened".to_string())
-        }
-        "open_folder" => {
-            #[cfg(target_os = "windows")]
-            {
-                Command::new("explorer")
-                    .arg("/select,")
-                    .arg(&result_id)
-                    .spawn()
-                    .map_err(|e| format!("Failed to open folder: {}", e))?;
+            "open_folder" => {
+                #[cfg(target_os = "windows")]
+                {
+                    Command::new("explorer")
+                        .arg("/select,")
+                        .arg(result_id)
+                        .spawn()
+                        .map_err(|e| format!("Failed to open folder: {}", e))?;
+                }
+                Ok(ActionOutcome::Close)
             }
-            Ok("Folder opened".to_string())
+            _ => Err("Unknown action".to_string()),
         }
-        _ => Err("Unknown action".to_string()),
     }
 }
@@ -1,49 +1,87 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
-
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "google".to_string(),
-        name: "Google Search".to_string(),
-        description: "Search Google".to_string(),
-        prefix: "g".to_string(),
-        icon: "🔍".to_string(),
-        config: None,
-    }
+use dossier_plugin_sdk::{
+    export_plugin, services, ActionOutcome, ConfigField, ConfigFieldKind, ConfigValues, Plugin,
+    PluginAction, PluginConfig, PluginResult, PluginSearchResult, PluginTrait,
+};
+use serde_json::json;
+use std::sync::RwLock;
+
+// `{query}` is replaced by the encoded query
+const DEFAULT_SEARCH_URL: &str = "https://www.google.com/search?q={query}";
+
+struct GooglePlugin {
+    search_url: RwLock<String>,
 }
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    if query.is_empty() {
-        return PluginSearchResult::Results(vec![]);
+export_plugin!(GooglePlugin {
+    search_url: RwLock::new(DEFAULT_SEARCH_URL.to_string()),
+});
+
+impl PluginTrait for GooglePlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "google".to_string(),
+            name: "Google Search".to_string(),
+            description: "Search Google".to_string(),
+            prefix: "g".to_string(),
+            icon: "🔍".to_string(),
+            config: Some(PluginConfig {
+                fields: vec![ConfigField {
+                    key: "searchUrl".to_string(),
+                    label: "Search URL".to_string(),
+                    description: Some(
+                        "Opened with {query} replaced by the query, change it to use another search engine".to_string(),
+                    ),
+                    kind: ConfigFieldKind::String,
+                    default: json!(DEFAULT_SEARCH_URL),
+                }],
+            }),
+            capabilities: Vec::new(),
+        }
     }
 
-    PluginSearchResult::Results(vec![PluginResult {
-        id: query.clone(),
-        title: format!("Search Google for '{}'", query),
-        subtitle: Some("Open in browser".to_string()),
-        icon: Some("🔍".to_string()),
-        actions: Some(vec![PluginAction {
-            id: "search".to_string(),
-            label: "Search".to_string(),
-            shortcut: Some("Enter".to_string()),
-        }]),
-    }])
-}
+    fn configure(&self, values: &ConfigValues) {
+        if let Some(search_url) = values.get::<String>("searchUrl") {
+            *self.search_url.write().unwrap() = search_url;
+        }
+    }
+
+    fn search(&self, query: &str) -> PluginSearchResult {
+        if query.is_empty() {
+            return PluginSearchResult::Results(vec![]);
+        }
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(result_id: String, action_id: String) -> Result<String, String> {
-    match action_id.as_str() {
-        "search" => {
-            let encoded_query = urlencoding::encode(&result_id);
-            let url = format!("https://www.google.com/search?q={}", encoded_query);
-            if let Err(e) = opener::open(&url) {
-                return Err(format!("Failed to open URL: {}", e));
+        PluginSearchResult::Results(vec![PluginResult {
+            id: query.to_string(),
+            title: format!("Search Google for '{}'", query),
+            subtitle: Some("Open in browser".to_string()),
+            icon: Some("🔍".to_string()),
+            actions: Some(vec![PluginAction {
+                id: "search".to_string(),
+                label: "Search".to_string(),
+                shortcut: Some("Enter".to_string()),
+                dangerous: false,
+                confirmation: None,
+            }]),
+            score: None,
+            highlights: None,
+        }])
+    }
+
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "search" => {
+                let encoded_query = urlencoding::encode(result_id);
+                let url = self
+                    .search_url
+                    .read()
+                    .unwrap()
+                    .replace("{query}", &encoded_query);
+                services::host()
+                    .open(&url)
+                    .map(|_| ActionOutcome::Close)
+                    .map_err(|e| format!("Failed to open URL: {}", e))
             }
-            Ok("Opened Google search".to_string())
+            _ => Err("Unknown action".to_string()),
         }
-        _ => Err("Unknown action".to_string()),
     }
-}
\ No newline at end of file
+}
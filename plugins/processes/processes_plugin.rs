@@ -1,75 +1,89 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
+use dossier_plugin_sdk::matcher::fuzzy_match;
+use dossier_plugin_sdk::{
+    export_plugin, ActionOutcome, Plugin, PluginAction, PluginResult, PluginSearchResult,
+    PluginTrait,
+};
 use sysinfo::{ProcessesToUpdate, System};
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "processes".to_string(),
-        name: "System Processes".to_string(),
-        description: "View and manage system processes".to_string(),
-        prefix: "ps".to_string(),
-        icon: "⚙️".to_string(),
-        config: None,
+struct ProcessesPlugin;
+
+export_plugin!(ProcessesPlugin);
+
+impl PluginTrait for ProcessesPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "processes".to_string(),
+            name: "System Processes".to_string(),
+            description: "View and manage system processes".to_string(),
+            prefix: "ps".to_string(),
+            icon: "⚙️".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
-}
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    let mut sys = System::new_all();
-    sys.refresh_processes(ProcessesToUpdate::All, true);
+    fn search(&self, query: &str) -> PluginSearchResult {
+        let mut sys = System::new_all();
+        sys.refresh_processes(ProcessesToUpdate::All, true);
 
-    let mut results: Vec<PluginResult> = Vec::new();
-    let query_lower = query.to_lowercase();
+        let mut results: Vec<PluginResult> = Vec::new();
 
-    for (pid, process) in sys.processes() {
-        let process_name = process.name().to_string_lossy().to_string();
+        for (pid, process) in sys.processes() {
+            let process_name = process.name().to_string_lossy().to_string();
 
-        if !query.is_empty() && !process_name.to_lowercase().contains(&query_lower) {
-            continue;
-        }
+            let Some(m) = fuzzy_match(query, &process_name) else {
+                continue;
+            };
 
-        results.push(PluginResult {
-            id: pid.to_string(),
-            title: process_name,
-            subtitle: Some(format!(
-                "PID: {} • CPU: {:.1}% • Memory: {} KB",
-                pid,
-                process.cpu_usage(),
-                process.memory() / 1024
-            )),
-            icon: None,
-            actions: Some(vec![PluginAction {
-                id: "kill".to_string(),
-                label: "Kill Process".to_string(),
-                shortcut: Some("Ctrl+K".to_string()),
-            }]),
-        });
+            let confirmation = format!(
+                "Kill {} (PID {})? Unsaved work will be lost.",
+                process_name, pid
+            );
+            results.push(PluginResult {
+                id: pid.to_string(),
+                title: process_name,
+                subtitle: Some(format!(
+                    "PID: {} • CPU: {:.1}% • Memory: {} KB",
+                    pid,
+                    process.cpu_usage(),
+                    process.memory() / 1024
+                )),
+                icon: None,
+                actions: Some(vec![PluginAction {
+                    id: "kill".to_string(),
+                    label: "Kill Process".to_string(),
+                    shortcut: Some("Ctrl+K".to_string()),
+                    dangerous: true,
+                    confirmation: Some(confirmation),
+                }]),
+                score: Some(m.score),
+                highlights: Some(m.ranges),
+            });
+        }
+        results.sort_by_key(|result| std::cmp::Reverse(result.score));
+        PluginSearchResult::Results(results)
     }
-    PluginSearchResult::Results(results)
-}
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(result_id: String, action_id: String) -> Result<String, String> {
-    match action_id.as_str() {
-        "kill" => {
-            if let Ok(pid) = result_id.parse::<u32>() {
-                let mut sys = System::new();
-                sys.refresh_processes(ProcessesToUpdate::All, true);
-                if let Some(process) = sys.process(sysinfo::Pid::from(pid as usize)) {
-                    if process.kill() {
-                        Ok("Process killed successfully".to_string())
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "kill" => {
+                if let Ok(pid) = result_id.parse::<u32>() {
+                    let mut sys = System::new();
+                    sys.refresh_processes(ProcessesToUpdate::All, true);
+                    if let Some(process) = sys.process(sysinfo::Pid::from(pid as usize)) {
+                        if process.kill() {
+                            Ok(ActionOutcome::Refresh)
+                        } else {
+                            Err("Failed to kill process".to_string())
+                        }
                     } else {
-                        Err("Failed to kill process".to_string())
+                        Err("Process not found".to_string())
                     }
                 } else {
-                    Err("Process not found".to_string())
+                    Err("Invalid process ID".to_string())
                 }
-            } else {
-                Err("Invalid process ID".to_string())
             }
+            _ => Err("Unknown action".to_string()),
         }
-        _ => Err("Unknown action".to_string()),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,451 @@
+//! The C ABI spoken between the host and plugin libraries.
+//!
+//! Every value crossing the library boundary is a NUL-terminated UTF-8 JSON
+//! string. Strings returned by a plugin are owned by the plugin and must be
+//! handed back through `dossier_plugin_free_string`, so host and plugin never
+//! free each other's allocations. Plugin authors normally only need
+//! [`export_plugin!`](crate::export_plugin).
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::mem::offset_of;
+use std::panic::{catch_unwind, UnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Version of the plugin ABI. Bumped whenever a symbol signature or payload
+/// layout changes incompatibly; the host refuses libraries reporting another
+/// version.
+pub const ABI_VERSION: u32 = 1;
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"dossier_plugin_abi_version";
+pub const INFO_SYMBOL: &[u8] = b"dossier_plugin_info";
+pub const SEARCH_SYMBOL: &[u8] = b"dossier_plugin_search";
+pub const EXECUTE_SYMBOL: &[u8] = b"dossier_plugin_execute";
+pub const FREE_STRING_SYMBOL: &[u8] = b"dossier_plugin_free_string";
+/// Optional: preferred over `dossier_plugin_search` when exported, so
+/// libraries built before it existed keep loading.
+pub const SEARCH_WITH_CONTEXT_SYMBOL: &[u8] = b"dossier_plugin_search_with_context";
+/// Optional: only called for plugins declaring a
+/// [`PluginConfig`](crate::PluginConfig).
+pub const CONFIGURE_SYMBOL: &[u8] = b"dossier_plugin_configure";
+/// Optional lifecycle hooks, see [`PluginTrait`](crate::PluginTrait).
+pub const INIT_SYMBOL: &[u8] = b"dossier_plugin_init";
+pub const PREFIX_ACTIVATE_SYMBOL: &[u8] = b"dossier_plugin_on_prefix_activate";
+pub const WINDOW_SHOWN_SYMBOL: &[u8] = b"dossier_plugin_on_window_shown";
+pub const SHUTDOWN_SYMBOL: &[u8] = b"dossier_plugin_shutdown";
+/// Optional: receives the [`RawHostServices`] right after the ABI version
+/// is checked, before any other call.
+pub const SET_HOST_SYMBOL: &[u8] = b"dossier_plugin_set_host";
+
+pub type AbiVersionFn = unsafe extern "C" fn() -> u32;
+/// Returns the JSON encoded [`Plugin`](crate::Plugin).
+pub type InfoFn = unsafe extern "C" fn() -> *mut c_char;
+/// Takes the query and returns the JSON encoded
+/// [`PluginSearchResult`](crate::PluginSearchResult).
+pub type SearchFn = unsafe extern "C" fn(query: *const c_char) -> *mut c_char;
+/// Like [`SearchFn`], with the state of the query. The context is only valid
+/// for the duration of the call.
+pub type SearchWithContextFn =
+    unsafe extern "C" fn(query: *const c_char, context: *const RawSearchContext) -> *mut c_char;
+/// Takes the result and action ids and returns `{"Ok": outcome}` with a
+/// JSON encoded [`ActionOutcome`](crate::ActionOutcome) or `{"Err": message}`.
+pub type ExecuteFn =
+    unsafe extern "C" fn(result_id: *const c_char, action_id: *const c_char) -> *mut c_char;
+pub type FreeStringFn = unsafe extern "C" fn(ptr: *mut c_char);
+/// Takes the JSON encoded [`ConfigValues`](crate::ConfigValues).
+pub type ConfigureFn = unsafe extern "C" fn(values: *const c_char);
+/// Takes the JSON encoded [`HostContext`](crate::HostContext).
+pub type InitFn = unsafe extern "C" fn(context: *const c_char);
+/// A lifecycle hook without arguments.
+pub type HookFn = unsafe extern "C" fn();
+
+pub type SetHostFn = unsafe extern "C" fn(services: *const RawHostServices);
+/// Runs the host service `method` with its JSON encoded params and returns
+/// `{"Ok": value}` or `{"Err": message}`, to be released with the
+/// `free_string` of the same [`RawHostServices`].
+pub type HostCallFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    method: *const c_char,
+    params: *const c_char,
+) -> *mut c_char;
+
+/// Services of the host, see [`services`](crate::services). Fields are only
+/// ever appended; `size` tells the plugin which ones the host sent.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawHostServices {
+    pub size: usize,
+    pub call: HostCallFn,
+    pub free_string: FreeStringFn,
+    /// Passed back to `call`, identifies the plugin to the host.
+    pub data: *mut c_void,
+}
+
+impl RawHostServices {
+    pub fn new(call: HostCallFn, free_string: FreeStringFn, data: *mut c_void) -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            call,
+            free_string,
+            data,
+        }
+    }
+
+    /// Whether the host sent every field this version of the SDK uses.
+    pub fn is_complete(&self) -> bool {
+        self.size >= std::mem::size_of::<Self>()
+    }
+}
+
+/// Receives a JSON array of [`PluginResult`](crate::PluginResult)s found so
+/// far, which the host copies before returning.
+pub type EmitFn = unsafe extern "C" fn(data: *mut c_void, batch: *const c_char);
+
+/// Per-query state passed to `dossier_plugin_search_with_context`. Fields
+/// are only ever appended; `size` tells the plugin which ones the host sent.
+#[repr(C)]
+pub struct RawSearchContext {
+    pub size: usize,
+    /// Set by the host once the query is superseded or runs out of time.
+    pub cancelled: *const AtomicBool,
+    /// Streams batches of results to the host; may be called from any thread
+    /// until the search returns. `None` if the host does not stream results.
+    pub emit: Option<EmitFn>,
+    pub emit_data: *mut c_void,
+}
+
+impl RawSearchContext {
+    pub fn new(cancelled: &AtomicBool) -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            cancelled,
+            emit: None,
+            emit_data: std::ptr::null_mut(),
+        }
+    }
+
+    pub fn with_emit(mut self, emit: EmitFn, data: *mut c_void) -> Self {
+        self.emit = Some(emit);
+        self.emit_data = data;
+        self
+    }
+
+    fn has<T>(&self, offset: usize) -> bool {
+        self.size >= offset + std::mem::size_of::<T>()
+    }
+}
+
+struct Emitter {
+    emit: EmitFn,
+    data: *mut c_void,
+}
+
+// The host guarantees `emit` can be called from any thread during the search
+unsafe impl Sync for Emitter {}
+
+impl Emitter {
+    fn send(&self, results: Vec<crate::PluginResult>) {
+        let json = serde_json::to_string(&results).unwrap_or_default();
+        let json = to_c_string(&json);
+        unsafe { (self.emit)(self.data, json.as_ptr()) }
+    }
+}
+
+/// Runs `f` with the [`SearchContext`](crate::SearchContext) the host sent.
+///
+/// # Safety
+/// `context` must be null or point to a [`RawSearchContext`] that stays
+/// valid until `f` returns.
+pub unsafe fn with_search_context<R>(
+    context: *const RawSearchContext,
+    f: impl FnOnce(&crate::SearchContext) -> R,
+) -> R {
+    let context = context.as_ref();
+    let flag = context
+        .filter(|c| c.has::<*const AtomicBool>(offset_of!(RawSearchContext, cancelled)))
+        .and_then(|c| c.cancelled.as_ref());
+    let emitter = context
+        .filter(|c| c.has::<*mut c_void>(offset_of!(RawSearchContext, emit_data)))
+        .and_then(|c| {
+            c.emit.map(|emit| Emitter {
+                emit,
+                data: c.emit_data,
+            })
+        });
+
+    let cancelled = || flag.is_some_and(|flag| flag.load(Ordering::Relaxed));
+    let sink = emitter.map(|emitter| move |results| emitter.send(results));
+    let sink = sink
+        .as_ref()
+        .map(|sink| sink as &(dyn Fn(Vec<crate::PluginResult>) + Sync));
+    let context = crate::SearchContext::new(&cancelled, sink);
+    f(&context)
+}
+
+/// Converts `value` into a C string, dropping interior NULs rather than failing.
+pub fn to_c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', "")).unwrap_or_default()
+}
+
+/// Serializes `value` into a JSON C string owned by the calling library.
+pub fn to_raw_json<T: serde::Serialize>(value: &T) -> *mut c_char {
+    let json = serde_json::to_string(value).unwrap_or_default();
+    to_c_string(&json).into_raw()
+}
+
+/// # Safety
+/// `ptr` must be null or a valid NUL-terminated string.
+pub unsafe fn from_raw_str(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// # Safety
+/// `ptr` must be null or a string previously returned by [`to_raw_json`].
+pub unsafe fn free_raw(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        drop(CString::from_raw(ptr));
+    }
+}
+
+/// Copies a plugin-owned string and releases the original through the
+/// plugin's own `free`.
+///
+/// # Safety
+/// `ptr` must be null or a string returned by the library that exported `free`.
+pub unsafe fn take_plugin_string(ptr: *mut c_char, free: FreeStringFn) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let value = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    free(ptr);
+    Some(value)
+}
+
+/// Hands the JSON encoded `values` to the plugin's
+/// [`configure`](crate::PluginTrait::configure).
+pub fn configure(plugin: &dyn crate::PluginTrait, values: &str) {
+    match serde_json::from_str(values) {
+        Ok(values) => guard(
+            std::panic::AssertUnwindSafe(|| plugin.configure(&values)),
+            || eprintln!("Plugin panicked while being configured"),
+        ),
+        Err(e) => eprintln!("Ignoring malformed plugin settings: {}", e),
+    }
+}
+
+/// Hands the JSON encoded `context` to the plugin's
+/// [`init`](crate::PluginTrait::init).
+pub fn init(plugin: &dyn crate::PluginTrait, context: &str) {
+    match serde_json::from_str(context) {
+        Ok(context) => hook(|| plugin.init(&context), "initialized"),
+        Err(e) => eprintln!("Ignoring malformed host context: {}", e),
+    }
+}
+
+/// Runs a lifecycle hook, logging instead of unwinding if it panics.
+pub fn hook(f: impl FnOnce(), during: &str) {
+    guard(std::panic::AssertUnwindSafe(f), || {
+        eprintln!("Plugin panicked while being {}", during)
+    })
+}
+
+/// Unwinding across an `extern "C"` boundary aborts the host, so panics are
+/// turned into `fallback` instead.
+pub fn guard<T>(f: impl FnOnce() -> T + UnwindSafe, fallback: impl FnOnce() -> T) -> T {
+    catch_unwind(f).unwrap_or_else(|_| fallback())
+}
+
+/// Exports the C ABI entry points for a [`PluginTrait`](crate::PluginTrait)
+/// implementation, or the [`wasm`](crate::wasm) entry points when building
+/// for a WebAssembly target. The expression is evaluated once, on first use.
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:expr) => {
+        fn __dossier_plugin() -> &'static dyn $crate::PluginTrait {
+            static PLUGIN: std::sync::OnceLock<Box<dyn $crate::PluginTrait>> =
+                std::sync::OnceLock::new();
+            PLUGIN.get_or_init(|| Box::new($plugin)).as_ref()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn dossier_plugin_abi_version() -> u32 {
+            $crate::abi::ABI_VERSION
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub extern "C" fn dossier_plugin_info() -> *mut std::ffi::c_char {
+            $crate::abi::guard(
+                || $crate::abi::to_raw_json(&__dossier_plugin().get_info()),
+                std::ptr::null_mut,
+            )
+        }
+
+        /// # Safety
+        /// `query` must be a valid NUL-terminated string.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_search(
+            query: *const std::ffi::c_char,
+        ) -> *mut std::ffi::c_char {
+            dossier_plugin_search_with_context(query, std::ptr::null())
+        }
+
+        /// # Safety
+        /// `query` must be a valid NUL-terminated string and `context` null
+        /// or valid for the duration of the call.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_search_with_context(
+            query: *const std::ffi::c_char,
+            context: *const $crate::abi::RawSearchContext,
+        ) -> *mut std::ffi::c_char {
+            let query = $crate::abi::from_raw_str(query);
+            let result = $crate::abi::with_search_context(context, |context| {
+                $crate::abi::guard(
+                    std::panic::AssertUnwindSafe(|| {
+                        context.finish(__dossier_plugin().search_with_context(&query, context))
+                    }),
+                    || $crate::PluginSearchResult::Results(vec![]),
+                )
+            });
+            $crate::abi::to_raw_json(&result)
+        }
+
+        /// # Safety
+        /// `result_id` and `action_id` must be valid NUL-terminated strings.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_execute(
+            result_id: *const std::ffi::c_char,
+            action_id: *const std::ffi::c_char,
+        ) -> *mut std::ffi::c_char {
+            let result_id = $crate::abi::from_raw_str(result_id);
+            let action_id = $crate::abi::from_raw_str(action_id);
+            let result: Result<$crate::ActionOutcome, String> = $crate::abi::guard(
+                || __dossier_plugin().execute_action(&result_id, &action_id),
+                || Err("Plugin panicked while executing the action".to_string()),
+            );
+            $crate::abi::to_raw_json(&result)
+        }
+
+        /// # Safety
+        /// `values` must be a valid NUL-terminated string.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_configure(values: *const std::ffi::c_char) {
+            let values = $crate::abi::from_raw_str(values);
+            $crate::abi::configure(__dossier_plugin(), &values)
+        }
+
+        /// # Safety
+        /// `services` must be null or valid while the library is loaded.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_set_host(
+            services: *const $crate::abi::RawHostServices,
+        ) {
+            $crate::services::set_host(services)
+        }
+
+        /// # Safety
+        /// `context` must be a valid NUL-terminated string.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_init(context: *const std::ffi::c_char) {
+            let context = $crate::abi::from_raw_str(context);
+            $crate::abi::init(__dossier_plugin(), &context)
+        }
+
+        #[no_mangle]
+        pub extern "C" fn dossier_plugin_on_prefix_activate() {
+            $crate::abi::hook(|| __dossier_plugin().on_prefix_activate(), "activated")
+        }
+
+        #[no_mangle]
+        pub extern "C" fn dossier_plugin_on_window_shown() {
+            $crate::abi::hook(|| __dossier_plugin().on_window_shown(), "shown")
+        }
+
+        #[no_mangle]
+        pub extern "C" fn dossier_plugin_shutdown() {
+            $crate::abi::hook(|| __dossier_plugin().shutdown(), "shut down")
+        }
+
+        /// # Safety
+        /// `ptr` must be null or a string returned by one of the functions above.
+        #[cfg(not(target_family = "wasm"))]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_free_string(ptr: *mut std::ffi::c_char) {
+            $crate::abi::free_raw(ptr)
+        }
+
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub extern "C" fn dossier_alloc(len: u32) -> u32 {
+            $crate::wasm::alloc(len)
+        }
+
+        /// # Safety
+        /// `ptr` and `len` must describe a buffer handed out by this module.
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_free(ptr: u32, len: u32) {
+            $crate::wasm::free(ptr, len)
+        }
+
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub extern "C" fn dossier_plugin_info() -> u64 {
+            $crate::wasm::to_packed_json(&__dossier_plugin().get_info())
+        }
+
+        /// # Safety
+        /// `ptr` and `len` must describe the query copied in by the host.
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_search(ptr: u32, len: u32) -> u64 {
+            let query = $crate::wasm::read_str(ptr, len);
+            let result = $crate::wasm::with_search_context(|context| {
+                context.finish(__dossier_plugin().search_with_context(&query, context))
+            });
+            $crate::wasm::to_packed_json(&result)
+        }
+
+        /// # Safety
+        /// The pointers and lengths must describe the ids copied in by the host.
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_execute(
+            result_ptr: u32,
+            result_len: u32,
+            action_ptr: u32,
+            action_len: u32,
+        ) -> u64 {
+            let result_id = $crate::wasm::read_str(result_ptr, result_len);
+            let action_id = $crate::wasm::read_str(action_ptr, action_len);
+            let result = __dossier_plugin().execute_action(&result_id, &action_id);
+            $crate::wasm::to_packed_json(&result)
+        }
+
+        /// # Safety
+        /// `ptr` and `len` must describe the settings copied in by the host.
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_configure(ptr: u32, len: u32) {
+            let values = $crate::wasm::read_str(ptr, len);
+            $crate::abi::configure(__dossier_plugin(), &values)
+        }
+
+        /// # Safety
+        /// `ptr` and `len` must describe the context copied in by the host.
+        #[cfg(target_family = "wasm")]
+        #[no_mangle]
+        pub unsafe extern "C" fn dossier_plugin_init(ptr: u32, len: u32) {
+            let context = $crate::wasm::read_str(ptr, len);
+            $crate::abi::init(__dossier_plugin(), &context)
+        }
+    };
+}
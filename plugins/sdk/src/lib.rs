@@ -0,0 +1,418 @@
+//! Everything needed to build a Dossier plugin.
+//!
+//! Implement [`PluginTrait`] for a type and hand it to [`export_plugin!`],
+//! which generates the C ABI entry points the host loads:
+//!
+//! ```ignore
+//! use dossier_plugin_sdk::{export_plugin, ActionOutcome, Plugin, PluginSearchResult, PluginTrait};
+//!
+//! struct Hello;
+//!
+//! impl PluginTrait for Hello {
+//!     fn get_info(&self) -> Plugin { /* ... */ }
+//!     fn search(&self, query: &str) -> PluginSearchResult { /* ... */ }
+//!     fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> { /* ... */ }
+//! }
+//!
+//! export_plugin!(Hello);
+//! ```
+//!
+//! The same crate builds a sandboxed WebAssembly plugin when compiled for
+//! `wasm32-wasip1`; see the [`wasm`] module.
+
+pub mod abi;
+pub mod matcher;
+pub mod services;
+pub mod wasm;
+
+pub use services::HostServices;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Settings a plugin can be configured with, declared in its [`Plugin`]
+/// info. The settings window shows them as a form, the host stores their
+/// values in `settings.json` and hands them to [`PluginTrait::configure`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct PluginConfig {
+    #[serde(default)]
+    pub fields: Vec<ConfigField>,
+}
+
+/// One setting of a plugin.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ConfigField {
+    /// Name of the value in [`ConfigValues`].
+    pub key: String,
+    pub label: String,
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub kind: ConfigFieldKind,
+    /// Used until the user picks another value.
+    pub default: serde_json::Value,
+}
+
+/// Type of a [`ConfigField`] and the values it accepts.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ConfigFieldKind {
+    String,
+    Integer {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Number {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Boolean,
+    /// One of `options`.
+    Enum {
+        options: Vec<String>,
+    },
+}
+
+impl ConfigField {
+    /// Whether `value` is acceptable for this field.
+    pub fn check(&self, value: &serde_json::Value) -> Result<(), String> {
+        fn in_range<T: PartialOrd + std::fmt::Display>(
+            value: T,
+            min: Option<T>,
+            max: Option<T>,
+        ) -> Result<(), String> {
+            match (min, max) {
+                (Some(min), _) if value < min => Err(format!("{} is less than {}", value, min)),
+                (_, Some(max)) if value > max => Err(format!("{} is more than {}", value, max)),
+                _ => Ok(()),
+            }
+        }
+
+        let invalid = |expected: &str| Err(format!("{} is not {}", value, expected));
+        match &self.kind {
+            ConfigFieldKind::String if value.is_string() => Ok(()),
+            ConfigFieldKind::String => invalid("a string"),
+            ConfigFieldKind::Integer { min, max } => match value.as_i64() {
+                Some(value) => in_range(value, *min, *max),
+                None => invalid("an integer"),
+            },
+            ConfigFieldKind::Number { min, max } => match value.as_f64() {
+                Some(value) => in_range(value, *min, *max),
+                None => invalid("a number"),
+            },
+            ConfigFieldKind::Boolean if value.is_boolean() => Ok(()),
+            ConfigFieldKind::Boolean => invalid("true or false"),
+            ConfigFieldKind::Enum { options } => match value.as_str() {
+                Some(option) if options.iter().any(|o| o == option) => Ok(()),
+                _ => invalid(&format!("one of {}", options.join(", "))),
+            },
+        }
+    }
+}
+
+impl PluginConfig {
+    /// The value of every field, taken from `stored` when acceptable and
+    /// from the field's default otherwise, with what was wrong with the
+    /// stored values that were not.
+    pub fn resolve(
+        &self,
+        stored: &serde_json::Map<String, serde_json::Value>,
+    ) -> (ConfigValues, Vec<String>) {
+        let mut values = HashMap::new();
+        let mut problems = Vec::new();
+        for field in &self.fields {
+            let value = match stored.get(&field.key) {
+                Some(value) => match field.check(value) {
+                    Ok(()) => value.clone(),
+                    Err(e) => {
+                        problems.push(format!("{}: {}", field.key, e));
+                        field.default.clone()
+                    }
+                },
+                None => field.default.clone(),
+            };
+            values.insert(field.key.clone(), value);
+        }
+        (ConfigValues(values), problems)
+    }
+}
+
+/// Values of the settings a plugin declared in its [`PluginConfig`], keyed
+/// by [`ConfigField::key`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct ConfigValues(pub HashMap<String, serde_json::Value>);
+
+impl ConfigValues {
+    /// The value of `key`, or `None` if it is missing or not a `T`.
+    ///
+    /// ```
+    /// # use dossier_plugin_sdk::ConfigValues;
+    /// let values: ConfigValues = serde_json::from_str(r#"{"maxEntries": 50}"#).unwrap();
+    /// assert_eq!(values.get::<usize>("maxEntries"), Some(50));
+    /// assert_eq!(values.get::<String>("maxEntries"), None);
+    /// ```
+    pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        serde_json::from_value(self.0.get(key)?.clone()).ok()
+    }
+}
+
+/// What the host tells a plugin about itself in [`PluginTrait::init`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct HostContext {
+    /// Version of the Dossier build that loaded the plugin.
+    pub host_version: String,
+    /// A directory reserved for the plugin's own files, created by the
+    /// host. Sandboxed plugins can only reach it when granted.
+    pub data_dir: Option<std::path::PathBuf>,
+}
+
+impl HostContext {
+    /// The host's services, such as the clipboard, storage and HTTP.
+    pub fn services(&self) -> HostServices {
+        services::host()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PluginAction {
+    pub id: String,
+    pub label: String,
+    pub shortcut: Option<String>,
+    /// Makes the host ask the user before running the action, for actions
+    /// that cannot be undone such as killing a process. Users can turn the
+    /// question off per action in the settings.
+    #[serde(default)]
+    pub dangerous: bool,
+    /// The question asked before a dangerous action runs, instead of a
+    /// generic one.
+    pub confirmation: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PluginResult {
+    pub id: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub icon: Option<String>,
+    pub actions: Option<Vec<PluginAction>>,
+    /// How well the result matches the query, from [`matcher::fuzzy_match`].
+    /// Results without one are scored by their title when ranked.
+    pub score: Option<u32>,
+    /// Matched parts of the title as `(start, end)` character ranges.
+    pub highlights: Option<Vec<(usize, usize)>>,
+}
+
+/// A page shown in place of the results. The bar renders it in a sandboxed
+/// frame: scripts and event handler attributes do not run, and only inline
+/// styles and `data:` images and fonts are loaded.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PluginHtmlResult {
+    pub html: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum PluginSearchResult {
+    Results(Vec<PluginResult>),
+    Html(PluginHtmlResult),
+}
+
+/// What the bar does once an action has run.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ActionOutcome {
+    /// Hides the bar, as after launching an app.
+    Close,
+    /// Leaves the bar as it is.
+    KeepOpen,
+    /// Replaces the typed text, which is then searched.
+    SetQuery { query: String },
+    /// Searches the current query again, so the list reflects what the
+    /// action changed.
+    Refresh,
+    /// Shows a short message in the bar, which stays open.
+    Toast { message: String },
+    /// Shows `results` in place of the search results until Escape goes
+    /// back. Their actions are run by the same plugin.
+    PushResults { results: Vec<PluginResult> },
+    /// Shows a page of HTML until Escape goes back, sandboxed like a
+    /// [`PluginHtmlResult`]: scripts do not run, so links and buttons on it
+    /// do nothing and further steps belong in actions.
+    ShowDetail { html: String },
+    /// Closes the bar like [`Close`](Self::Close), reporting what was done.
+    /// Plugins built before outcomes existed returned only messages.
+    #[serde(untagged)]
+    Message(String),
+}
+
+/// Something a plugin needs beyond computing results, declared in its
+/// [`Plugin`] info or manifest. The user is asked to allow it before the
+/// plugin is loaded, and [host services](services) needing a capability
+/// fail unless it was allowed.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    Clipboard,
+    OpenUrl,
+    Network,
+    FilesystemRead,
+    FilesystemWrite,
+    SpawnProcess,
+    KillProcess,
+    Notifications,
+}
+
+impl Capability {
+    /// The name used in manifests and settings, such as `open-url`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Clipboard => "clipboard",
+            Capability::OpenUrl => "open-url",
+            Capability::Network => "network",
+            Capability::FilesystemRead => "filesystem-read",
+            Capability::FilesystemWrite => "filesystem-write",
+            Capability::SpawnProcess => "spawn-process",
+            Capability::KillProcess => "kill-process",
+            Capability::Notifications => "notifications",
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Plugin {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub prefix: String,
+    pub icon: String,
+    /// Settings the plugin can be configured with, if any.
+    pub config: Option<PluginConfig>,
+    /// What the plugin needs to be allowed to do, on top of what its
+    /// manifest declares.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
+}
+
+/// Receives the batches a search emits while it is still running.
+pub type ResultSink<'a> = Box<dyn Fn(Vec<PluginResult>) + Send + Sync + 'a>;
+
+/// State of the query being searched, passed to
+/// [`PluginTrait::search_with_context`].
+pub struct SearchContext<'a> {
+    cancelled: Box<dyn Fn() -> bool + Send + Sync + 'a>,
+    sink: Option<ResultSink<'a>>,
+    // Batches emitted while the host does not stream results
+    buffered: Mutex<Vec<PluginResult>>,
+}
+
+impl<'a> SearchContext<'a> {
+    pub fn new(
+        cancelled: &'a (dyn Fn() -> bool + Sync),
+        sink: Option<&'a (dyn Fn(Vec<PluginResult>) + Sync)>,
+    ) -> Self {
+        Self::owning(
+            Box::new(cancelled),
+            sink.map(|sink| Box::new(sink) as ResultSink),
+        )
+    }
+
+    /// A context owning its callbacks, for a host that keeps it for as long
+    /// as the search runs.
+    pub fn owning(
+        cancelled: Box<dyn Fn() -> bool + Send + Sync + 'a>,
+        sink: Option<ResultSink<'a>>,
+    ) -> Self {
+        Self {
+            cancelled,
+            sink,
+            buffered: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A context for a query that is never cancelled and not streamed.
+    pub fn none() -> SearchContext<'static> {
+        fn never() -> bool {
+            false
+        }
+        SearchContext::new(&never, None)
+    }
+
+    /// Whether the user has typed on or the search ran out of time. Anything
+    /// returned after that is thrown away, so long searches should check this
+    /// between steps and return early.
+    pub fn is_cancelled(&self) -> bool {
+        (self.cancelled)()
+    }
+
+    /// Shows `results` right away, ahead of whatever the search returns once
+    /// it completes. May be called any number of times and from any thread.
+    pub fn emit(&self, results: Vec<PluginResult>) {
+        if results.is_empty() || self.is_cancelled() {
+            return;
+        }
+        match &self.sink {
+            Some(sink) => sink(results),
+            None => self.buffered.lock().unwrap().extend(results),
+        }
+    }
+
+    /// Puts the batches that could not be streamed in front of the final
+    /// result. Called by [`export_plugin!`] once the search returns.
+    pub fn finish(&self, result: PluginSearchResult) -> PluginSearchResult {
+        let mut buffered = std::mem::take(&mut *self.buffered.lock().unwrap());
+        match result {
+            PluginSearchResult::Results(results) if !buffered.is_empty() => {
+                buffered.extend(results);
+                PluginSearchResult::Results(buffered)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Behaviour of a plugin. Calls may arrive from several host threads at
+/// once, so implementations must be `Send + Sync`.
+pub trait PluginTrait: Send + Sync + 'static {
+    fn get_info(&self) -> Plugin;
+    fn search(&self, query: &str) -> PluginSearchResult;
+    /// Called by the host instead of [`search`](Self::search); override it
+    /// to stop slow searches once they are no longer wanted, or to
+    /// [`emit`](SearchContext::emit) the results found so far.
+    fn search_with_context(&self, query: &str, context: &SearchContext) -> PluginSearchResult {
+        let _ = context;
+        self.search(query)
+    }
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String>;
+    /// Receives the values of the settings declared in
+    /// [`Plugin::config`], once the plugin is loaded and again whenever the
+    /// user changes them. Unset and invalid values are replaced by their
+    /// defaults.
+    fn configure(&self, values: &ConfigValues) {
+        let _ = values;
+    }
+    /// Called once on a background thread after the plugin is loaded and
+    /// configured; a good place to warm caches. Searches may arrive before
+    /// it returns.
+    fn init(&self, context: &HostContext) {
+        let _ = context;
+    }
+    /// Called when the user types the plugin's prefix, before the first
+    /// search for it.
+    fn on_prefix_activate(&self) {}
+    /// Called whenever the bar is shown.
+    fn on_window_shown(&self) {}
+    /// Called before the plugin is unloaded or the app exits, to flush
+    /// whatever state it keeps.
+    fn shutdown(&self) {}
+}
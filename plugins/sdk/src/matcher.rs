@@ -0,0 +1,259 @@
+//! Fuzzy matching of a query against result titles, shared by the host (to
+//! rank merged results) and plugins (to filter their own).
+//!
+//! The query matches when its characters appear in the text in order, case
+//! insensitively, so `vsc` matches "Visual Studio Code". Of all the ways the
+//! characters line up, the best scoring one is chosen: matches at the start
+//! of words or camelCase humps and runs of adjacent characters score higher,
+//! characters skipped in between cost a little.
+//!
+//! ```
+//! use dossier_plugin_sdk::matcher::fuzzy_match;
+//!
+//! let m = fuzzy_match("vsc", "Visual Studio Code").unwrap();
+//! assert_eq!(m.ranges, vec![(0, 1), (7, 8), (14, 15)]);
+//! assert!(m.score > fuzzy_match("vsc", "Devious Cat").unwrap().score);
+//! ```
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_CONSECUTIVE: i32 = 8;
+const BONUS_BOUNDARY: i32 = 10;
+const BONUS_CAMEL: i32 = 8;
+const BONUS_START: i32 = 6;
+const PENALTY_GAP: i32 = 2;
+// Characters before the first match matter less than gaps inside the match
+const PENALTY_LEADING: i32 = 1;
+const MAX_LEADING_PENALTY: i32 = 10;
+
+/// Characters of the text that are matched, the rest is ignored. Keeps the
+/// work for long texts such as clipboard entries bounded; titles are far
+/// shorter.
+pub const MAX_TEXT_LEN: usize = 512;
+
+/// How and where a query matched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is better; comparable between texts matched by the same query.
+    pub score: u32,
+    /// Matched parts of the text as `(start, end)` character (not byte)
+    /// indices, `end` exclusive, in order and merged where adjacent.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+fn bonus(text: &[char], index: usize) -> i32 {
+    let current = text[index];
+    let Some(&previous) = index.checked_sub(1).and_then(|i| text.get(i)) else {
+        return BONUS_BOUNDARY + BONUS_START;
+    };
+    if !previous.is_alphanumeric() && current.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if (previous.is_lowercase() && current.is_uppercase())
+        || (previous.is_alphabetic() && current.is_numeric())
+    {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+const NONE: i32 = i32::MIN / 2;
+const NO_POSITION: usize = usize::MAX;
+
+/// A score in a row of the table, with the last matched position leading
+/// to it as an index into the positions recorded so far.
+#[derive(Clone, Copy)]
+struct Cell {
+    score: i32,
+    position: usize,
+}
+
+const EMPTY: Cell = Cell {
+    score: NONE,
+    position: NO_POSITION,
+};
+
+/// Matches `query` against `text`, or returns `None` if some character of the
+/// query does not occur in order. Whitespace in the query is ignored, so
+/// "st co" matches "Studio Code". An empty query matches everything with a
+/// score of 0. Only the first [`MAX_TEXT_LEN`] characters of `text` are
+/// matched.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<Match> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    if query.is_empty() {
+        return Some(Match {
+            score: 0,
+            ranges: Vec::new(),
+        });
+    }
+    let text: Vec<char> = text.chars().take(MAX_TEXT_LEN).collect();
+    let folded: Vec<char> = text.iter().copied().map(fold).collect();
+    let (n, m) = (query.len(), text.len());
+    if n > m {
+        return None;
+    }
+
+    // Row i of the table, computed from row i - 1 alone: matched[j] is the
+    // best score with query[i] matched at text[j], best[j] the best over
+    // k <= j of matched[k] minus the gap up to j, so each cell only looks
+    // at its neighbours. Every position matched is recorded with the one
+    // matched before it, which leaves the path to the best score once the
+    // last row is done.
+    let mut positions: Vec<(usize, usize)> = Vec::new();
+    let (mut previous_matched, mut previous_best) = (vec![EMPTY; m], vec![EMPTY; m]);
+    let (mut matched, mut best) = (vec![EMPTY; m], vec![EMPTY; m]);
+
+    for (i, &wanted) in query.iter().enumerate() {
+        for j in 0..m {
+            matched[j] = EMPTY;
+            if j >= i && folded[j] == wanted {
+                let score = SCORE_MATCH + bonus(&text, j);
+                let found = if i == 0 {
+                    let leading = (PENALTY_LEADING * j as i32).min(MAX_LEADING_PENALTY);
+                    Some((score - leading, NO_POSITION))
+                } else if j > 0 {
+                    let consecutive = previous_matched[j - 1];
+                    let gapped = previous_best[j - 1];
+                    if consecutive.score > NONE
+                        && consecutive.score + BONUS_CONSECUTIVE >= gapped.score
+                    {
+                        let score = score + consecutive.score + BONUS_CONSECUTIVE;
+                        Some((score, consecutive.position))
+                    } else if gapped.score > NONE {
+                        Some((score + gapped.score, gapped.position))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                };
+                if let Some((score, before)) = found {
+                    positions.push((j, before));
+                    matched[j] = Cell {
+                        score,
+                        position: positions.len() - 1,
+                    };
+                }
+            }
+            let carried = match j.checked_sub(1) {
+                Some(k) => Cell {
+                    score: best[k].score.saturating_sub(PENALTY_GAP),
+                    ..best[k]
+                },
+                None => EMPTY,
+            };
+            best[j] = if matched[j].score > NONE && matched[j].score >= carried.score {
+                matched[j]
+            } else {
+                carried
+            };
+        }
+        std::mem::swap(&mut previous_matched, &mut matched);
+        std::mem::swap(&mut previous_best, &mut best);
+    }
+
+    // The earliest of the best scoring ends
+    let end = previous_matched
+        .iter()
+        .filter(|cell| cell.score > NONE)
+        .fold(None::<Cell>, |found, cell| match found {
+            Some(found) if found.score >= cell.score => Some(found),
+            _ => Some(*cell),
+        })?;
+
+    let mut path = Vec::with_capacity(n);
+    let mut position = end.position;
+    while let Some(&(index, before)) = positions.get(position) {
+        path.push(index);
+        position = before;
+    }
+    path.reverse();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for index in path {
+        match ranges.last_mut() {
+            Some((_, range_end)) if *range_end == index => *range_end += 1,
+            _ => ranges.push((index, index + 1)),
+        }
+    }
+
+    Some(Match {
+        // Every matched character adds more than any bonus can take away, so
+        // a match never scores 0 like an empty query does
+        score: end.score.max(1) as u32,
+        ranges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, text: &str) -> u32 {
+        fuzzy_match(query, text).map(|m| m.score).unwrap_or(0)
+    }
+
+    #[test]
+    fn word_starts_score_higher_than_scattered_characters() {
+        assert!(score("vsc", "Visual Studio Code") > score("vsc", "Devious Cat"));
+        assert!(score("fb", "FooBar") > score("fb", "fab"));
+    }
+
+    #[test]
+    fn earlier_matches_score_higher() {
+        assert!(score("term", "Terminal") > score("term", "New terminal"));
+    }
+
+    #[test]
+    fn picks_the_best_of_several_alignments() {
+        // The word start beats the first `c`
+        let m = fuzzy_match("sc", "Source Code").unwrap();
+        assert_eq!(m.ranges, vec![(0, 1), (7, 8)]);
+        // Equal scores go to the earliest match
+        let m = fuzzy_match("code", "codec Code").unwrap();
+        assert_eq!(m.ranges, vec![(0, 4)]);
+    }
+
+    #[test]
+    fn ranges_count_characters_of_multibyte_text() {
+        let m = fuzzy_match("grö", "Größe").unwrap();
+        assert_eq!(m.ranges, vec![(0, 3)]);
+        let m = fuzzy_match("ße", "Größe").unwrap();
+        assert_eq!(m.ranges, vec![(3, 5)]);
+        let m = fuzzy_match("party", "🎉 Party").unwrap();
+        assert_eq!(m.ranges, vec![(2, 7)]);
+        let m = fuzzy_match("cè", "café crème").unwrap();
+        assert_eq!(m.ranges, vec![(0, 1), (7, 8)]);
+    }
+
+    #[test]
+    fn empty_query_matches_everything_with_no_score() {
+        let expected = Some(Match {
+            score: 0,
+            ranges: Vec::new(),
+        });
+        assert_eq!(fuzzy_match("", "anything"), expected);
+        assert_eq!(fuzzy_match("  ", ""), expected);
+    }
+
+    #[test]
+    fn missing_characters_do_not_match() {
+        assert_eq!(fuzzy_match("xyz", "Visual Studio Code"), None);
+        assert_eq!(fuzzy_match("codes", "Code"), None);
+        assert_eq!(fuzzy_match("a", ""), None);
+    }
+
+    #[test]
+    fn text_past_the_limit_is_not_matched() {
+        let text = format!("{}z", "a".repeat(MAX_TEXT_LEN));
+        assert_eq!(fuzzy_match("z", &text), None);
+        assert!(fuzzy_match("a", &text).is_some());
+    }
+}
@@ -0,0 +1,221 @@
+//! Services the host provides to plugins: the clipboard, opening URLs and
+//! files, desktop notifications, logging, key-value storage and HTTP.
+//!
+//! Going through the host instead of linking `arboard`, `opener` or
+//! `reqwest` keeps plugins small, behaves the same on every platform and
+//! honours the user's proxy. The same calls work in native libraries and
+//! WebAssembly modules; sandboxed modules get an error for services they
+//! were not granted.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const CLIPBOARD_READ: &str = "clipboard.read";
+pub const CLIPBOARD_WRITE: &str = "clipboard.write";
+pub const OPEN: &str = "open";
+pub const NOTIFY: &str = "notify";
+pub const LOG: &str = "log";
+pub const STORAGE_GET: &str = "storage.get";
+pub const STORAGE_SET: &str = "storage.set";
+pub const STORAGE_REMOVE: &str = "storage.remove";
+pub const STORAGE_KEYS: &str = "storage.keys";
+pub const STORAGE_CLEAR: &str = "storage.clear";
+pub const HTTP: &str = "http";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+}
+
+/// A response of any status; only failing to reach the server is an error.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Handle to the host's services, handed to plugins with the
+/// [`HostContext`](crate::HostContext) and available anywhere else through
+/// [`host`].
+#[derive(Clone, Copy, Debug)]
+pub struct HostServices(());
+
+/// The host's services, for use outside [`init`](crate::PluginTrait::init).
+/// Calls made before the host set them up fail.
+pub fn host() -> HostServices {
+    HostServices(())
+}
+
+impl HostServices {
+    fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, String> {
+        let reply = backend::call(method, &params.to_string())?;
+        serde_json::from_value(reply).map_err(|e| format!("Malformed reply to {}: {}", method, e))
+    }
+
+    pub fn read_clipboard(&self) -> Result<String, String> {
+        self.call(CLIPBOARD_READ, Value::Null)
+    }
+
+    pub fn write_clipboard(&self, text: &str) -> Result<(), String> {
+        self.call(CLIPBOARD_WRITE, json!({ "text": text }))
+    }
+
+    /// Opens a URL in its default handler, or a file or directory with its
+    /// default application.
+    pub fn open(&self, target: &str) -> Result<(), String> {
+        self.call(OPEN, json!({ "target": target }))
+    }
+
+    /// Shows a desktop notification.
+    pub fn notify(&self, title: &str, body: &str) -> Result<(), String> {
+        self.call(NOTIFY, json!({ "title": title, "body": body }))
+    }
+
+    /// Writes `message` to Dossier's log, tagged with the plugin's id.
+    pub fn log(&self, level: LogLevel, message: &str) {
+        let logged: Result<(), String> =
+            self.call(LOG, json!({ "level": level, "message": message }));
+        if logged.is_err() {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// The value stored under `key`, `None` if there is none.
+    pub fn storage_get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        let value: Option<Value> = self.call(STORAGE_GET, json!({ "key": key }))?;
+        value
+            .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    /// Stores `value` under `key`, where it survives restarts. Fails when
+    /// the plugin's storage would grow past its quota of 1 MiB.
+    pub fn storage_set<T: Serialize>(&self, key: &str, value: &T) -> Result<(), String> {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        self.call(STORAGE_SET, json!({ "key": key, "value": value }))
+    }
+
+    pub fn storage_remove(&self, key: &str) -> Result<(), String> {
+        self.call(STORAGE_REMOVE, json!({ "key": key }))
+    }
+
+    /// The keys that have a value stored.
+    pub fn storage_keys(&self) -> Result<Vec<String>, String> {
+        self.call(STORAGE_KEYS, Value::Null)
+    }
+
+    /// Removes every stored value.
+    pub fn storage_clear(&self) -> Result<(), String> {
+        self.call(STORAGE_CLEAR, Value::Null)
+    }
+
+    pub fn http(&self, request: &HttpRequest) -> Result<HttpResponse, String> {
+        let request = serde_json::to_value(request).map_err(|e| e.to_string())?;
+        self.call(HTTP, request)
+    }
+
+    /// Fetches `url` and returns the body, failing unless the status is 2xx.
+    pub fn http_get(&self, url: &str) -> Result<String, String> {
+        let response = self.http(&HttpRequest::get(url))?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!("{} returned status {}", url, response.status));
+        }
+        Ok(response.body)
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+mod backend {
+    use crate::abi::{self, RawHostServices};
+    use std::sync::OnceLock;
+
+    struct Host(RawHostServices);
+
+    // The host guarantees its services can be called from any thread
+    unsafe impl Send for Host {}
+    unsafe impl Sync for Host {}
+
+    static HOST: OnceLock<Host> = OnceLock::new();
+
+    /// # Safety
+    /// `services` must be null or point to a [`RawHostServices`] whose
+    /// functions and data stay valid while the library is loaded.
+    pub unsafe fn set(services: *const RawHostServices) {
+        if let Some(services) = services.as_ref().filter(|s| s.is_complete()) {
+            let _ = HOST.set(Host(*services));
+        }
+    }
+
+    pub fn call(method: &str, params: &str) -> Result<serde_json::Value, String> {
+        let host = HOST
+            .get()
+            .ok_or("This version of Dossier does not provide host services")?;
+        let method = abi::to_c_string(method);
+        let params = abi::to_c_string(params);
+        let reply = unsafe {
+            let raw = (host.0.call)(host.0.data, method.as_ptr(), params.as_ptr());
+            abi::take_plugin_string(raw, host.0.free_string)
+        };
+        let reply = reply.ok_or("The host did not reply")?;
+        serde_json::from_str(&reply).map_err(|e| e.to_string())?
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub use backend::set as set_host;
+
+#[cfg(target_family = "wasm")]
+mod backend {
+    use crate::wasm::{free, read_str, unpack};
+
+    #[link(wasm_import_module = "dossier")]
+    extern "C" {
+        fn host_call(method_ptr: u32, method_len: u32, params_ptr: u32, params_len: u32) -> u64;
+    }
+
+    pub fn call(method: &str, params: &str) -> Result<serde_json::Value, String> {
+        let (ptr, len) = unpack(unsafe {
+            host_call(
+                method.as_ptr() as u32,
+                method.len() as u32,
+                params.as_ptr() as u32,
+                params.len() as u32,
+            )
+        });
+        let reply = unsafe {
+            let reply = read_str(ptr, len);
+            free(ptr, len);
+            reply
+        };
+        serde_json::from_str(&reply).map_err(|e| e.to_string())?
+    }
+}
@@ -0,0 +1,134 @@
+//! The interface spoken between the host and WebAssembly plugins.
+//!
+//! A WASM plugin exports the same entry point names as a native library, but
+//! strings travel through the module's linear memory: the host copies its
+//! arguments into buffers obtained from `dossier_alloc`, and every string the
+//! plugin returns is a buffer described by a packed `u64` (pointer in the
+//! high half, length in the low half) that the host releases with
+//! `dossier_free`. Calls into the host go through imports of the `dossier`
+//! module, which reply the same way with a JSON `Result<String, String>`.
+//!
+//! A module has no access to the machine beyond what the user granted it:
+//! granted directories are preopened through WASI, everything else is
+//! requested through the [`host`] functions.
+
+pub const HOST_MODULE: &str = "dossier";
+pub const MEMORY_EXPORT: &str = "memory";
+pub const ALLOC_EXPORT: &str = "dossier_alloc";
+pub const FREE_EXPORT: &str = "dossier_free";
+
+pub const CLIPBOARD_READ_IMPORT: &str = "clipboard_read";
+pub const CLIPBOARD_WRITE_IMPORT: &str = "clipboard_write";
+pub const OPEN_URL_IMPORT: &str = "open_url";
+pub const HTTP_GET_IMPORT: &str = "http_get";
+pub const SEARCH_CANCELLED_IMPORT: &str = "search_cancelled";
+pub const EMIT_RESULTS_IMPORT: &str = "emit_results";
+/// Generic entry point of the [`services`](crate::services), replying with
+/// a JSON `Result<Value, String>`.
+pub const HOST_CALL_IMPORT: &str = "host_call";
+
+pub fn pack(ptr: u32, len: u32) -> u64 {
+    ((ptr as u64) << 32) | len as u64
+}
+
+pub fn unpack(packed: u64) -> (u32, u32) {
+    ((packed >> 32) as u32, packed as u32)
+}
+
+#[cfg(target_family = "wasm")]
+pub use guest::*;
+
+#[cfg(target_family = "wasm")]
+mod guest {
+    use super::{pack, unpack};
+
+    pub fn alloc(len: u32) -> u32 {
+        let buffer = vec![0u8; len as usize].into_boxed_slice();
+        Box::into_raw(buffer) as *mut u8 as u32
+    }
+
+    /// # Safety
+    /// `ptr` and `len` must describe a buffer returned by [`alloc`] or
+    /// [`into_packed`].
+    pub unsafe fn free(ptr: u32, len: u32) {
+        let slice = std::ptr::slice_from_raw_parts_mut(ptr as *mut u8, len as usize);
+        drop(Box::from_raw(slice));
+    }
+
+    /// # Safety
+    /// `ptr` and `len` must describe readable memory.
+    pub unsafe fn read_str(ptr: u32, len: u32) -> String {
+        let bytes = std::slice::from_raw_parts(ptr as *const u8, len as usize);
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    /// Hands `bytes` over to the host, which frees it with `dossier_free`.
+    pub fn into_packed(bytes: Vec<u8>) -> u64 {
+        let len = bytes.len() as u32;
+        let ptr = Box::into_raw(bytes.into_boxed_slice()) as *mut u8 as u32;
+        pack(ptr, len)
+    }
+
+    pub fn to_packed_json<T: serde::Serialize>(value: &T) -> u64 {
+        into_packed(serde_json::to_vec(value).unwrap_or_default())
+    }
+
+    /// # Safety
+    /// `packed` must describe a buffer allocated with [`alloc`].
+    unsafe fn take_reply(packed: u64) -> Result<String, String> {
+        let (ptr, len) = unpack(packed);
+        let reply = read_str(ptr, len);
+        free(ptr, len);
+        serde_json::from_str(&reply).map_err(|e| e.to_string())?
+    }
+
+    #[link(wasm_import_module = "dossier")]
+    extern "C" {
+        fn search_cancelled() -> u32;
+        fn emit_results(ptr: u32, len: u32);
+    }
+
+    /// Runs `f` with a [`SearchContext`](crate::SearchContext) backed by the
+    /// host's state for the current search.
+    pub fn with_search_context<R>(f: impl FnOnce(&crate::SearchContext) -> R) -> R {
+        let cancelled = || unsafe { search_cancelled() != 0 };
+        let sink = |results: Vec<crate::PluginResult>| {
+            let json = serde_json::to_vec(&results).unwrap_or_default();
+            unsafe { emit_results(json.as_ptr() as u32, json.len() as u32) }
+        };
+        let context = crate::SearchContext::new(&cancelled, Some(&sink));
+        f(&context)
+    }
+
+    /// Services provided by the host. Each call fails with an explanatory
+    /// error unless the user granted the plugin the matching capability.
+    pub mod host {
+        use super::take_reply;
+
+        #[link(wasm_import_module = "dossier")]
+        extern "C" {
+            fn clipboard_read() -> u64;
+            fn clipboard_write(ptr: u32, len: u32) -> u64;
+            fn open_url(ptr: u32, len: u32) -> u64;
+            fn http_get(ptr: u32, len: u32) -> u64;
+        }
+
+        pub fn read_clipboard() -> Result<String, String> {
+            unsafe { take_reply(clipboard_read()) }
+        }
+
+        pub fn write_clipboard(text: &str) -> Result<(), String> {
+            unsafe { take_reply(clipboard_write(text.as_ptr() as u32, text.len() as u32)) }
+                .map(|_| ())
+        }
+
+        pub fn open(url: &str) -> Result<(), String> {
+            unsafe { take_reply(open_url(url.as_ptr() as u32, url.len() as u32)) }.map(|_| ())
+        }
+
+        /// Fetches `url` and returns the response body.
+        pub fn get(url: &str) -> Result<String, String> {
+            unsafe { take_reply(http_get(url.as_ptr() as u32, url.len() as u32)) }
+        }
+    }
+}
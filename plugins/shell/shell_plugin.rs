@@ -1,6 +1,7 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
+use dossier_plugin_sdk::{
+    export_plugin, ActionOutcome, Plugin, PluginAction, PluginResult, PluginSearchResult,
+    PluginTrait,
+};
 use std::process::Command;
 
 struct Terminal {
@@ -45,86 +46,94 @@ fn is_terminal_available(executable: &str) -> bool {
         .unwrap_or(false)
 }
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "shell".to_string(),
-        name: "Shell".to_string(),
-        description: "Execute terminal commands".to_string(),
-        prefix: ">".to_string(),
-        icon: "💻".to_string(),
-        config: None,
-    }
-}
+struct ShellPlugin;
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    if query.is_empty() {
-        return PluginSearchResult::Results(vec![]);
+export_plugin!(ShellPlugin);
+
+impl PluginTrait for ShellPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "shell".to_string(),
+            name: "Shell".to_string(),
+            description: "Execute terminal commands".to_string(),
+            prefix: ">".to_string(),
+            icon: "💻".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
 
-    let results = TERMINALS
-        .iter()
-        .filter(|terminal| is_terminal_available(terminal.executable))
-        .map(|terminal| PluginResult {
-            id: format!("{}:{}", terminal.executable, query),
-            title: format!("Run in {}", terminal.name),
-            subtitle: Some(format!("Execute: {}", query)),
-            icon: Some(terminal.icon.to_string()),
-            actions: Some(vec![PluginAction {
-                id: "execute".to_string(),
-                label: "Execute".to_string(),
-                shortcut: Some("Enter".to_string()),
-            }]),
-        })
-        .collect();
-
-    PluginSearchResult::Results(results)
-}
+    fn search(&self, query: &str) -> PluginSearchResult {
+        if query.is_empty() {
+            return PluginSearchResult::Results(vec![]);
+        }
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(
-    result_id: String,
-    _action_id: String,
-) -> Result<String, String> {
-    let parts: Vec<&str> = result_id.splitn(2, ':').collect();
-    if parts.len() != 2 {
-        return Err("Invalid result ID".to_string());
+        let results = TERMINALS
+            .iter()
+            .filter(|terminal| is_terminal_available(terminal.executable))
+            .map(|terminal| PluginResult {
+                id: format!("{}:{}", terminal.executable, query),
+                title: format!("Run in {}", terminal.name),
+                subtitle: Some(format!("Execute: {}", query)),
+                icon: Some(terminal.icon.to_string()),
+                actions: Some(vec![PluginAction {
+                    id: "execute".to_string(),
+                    label: "Execute".to_string(),
+                    shortcut: Some("Enter".to_string()),
+                    dangerous: false,
+                    confirmation: None,
+                }]),
+                score: None,
+                highlights: None,
+            })
+            .collect();
+
+        PluginSearchResult::Results(results)
     }
 
-    let executable = parts[0];
-    let command = parts[1];
+    fn execute_action(&self, result_id: &str, _action_id: &str) -> Result<ActionOutcome, String> {
+        let parts: Vec<&str> = result_id.splitn(2, ':').collect();
+        if parts.len() != 2 {
+            return Err("Invalid result ID".to_string());
+        }
 
-    let terminal = TERMINALS
-        .iter()
-        .find(|t| t.executable == executable)
-        .ok_or("Terminal not found")?;
+        let executable = parts[0];
+        let command = parts[1];
 
-    #[cfg(target_os = "windows")]
-    {
-        let mut cmd = Command::new(terminal.executable);
+        let terminal = TERMINALS
+            .iter()
+            .find(|t| t.executable == executable)
+            .ok_or("Terminal not found")?;
 
-        match terminal.name {
-            "Command Prompt" => {
-                cmd.args(&["/k", command]);
-            }
-            "PowerShell" => {
-                cmd.args(&["-NoExit", "-Command", command]);
-            }
-            "Windows Terminal" => {
-                cmd.args(&["-p", "Command Prompt", "cmd", "/k", command]);
-            }
-            "Git Bash" => {
-                cmd.args(&["-c", &format!("{}; exec bash", command)]);
-            }
-            _ => {
-                cmd.args(terminal.args).arg(command);
+        #[cfg(target_os = "windows")]
+        {
+            let mut cmd = Command::new(terminal.executable);
+
+            match terminal.name {
+                "Command Prompt" => {
+                    cmd.args(&["/k", command]);
+                }
+                "PowerShell" => {
+                    cmd.args(&["-NoExit", "-Command", command]);
+                }
+                "Windows Terminal" => {
+                    cmd.args(&["-p", "Command Prompt", "cmd", "/k", command]);
+                }
+                "Git Bash" => {
+                    cmd.args(&["-c", &format!("{}; exec bash", command)]);
+                }
+                _ => {
+                    cmd.args(terminal.args).arg(command);
+                }
             }
+
+            cmd.spawn()
+                .map_err(|e| format!("Failed to execute command: {}", e))?;
         }
 
-        cmd.spawn()
-            .map_err(|e| format!("Failed to execute command: {}", e))?;
+        Ok(ActionOutcome::Message(format!(
+            "Executed '{}' in {}",
+            command, terminal.name
+        )))
     }
-
-    Ok(format!("Executed '{}' in {}", command, terminal.name))
 }
@@ -1,8 +1,7 @@
-#[path = "../plugins.rs"]
-mod plugins;
-use plugins::{Plugin, PluginAction, PluginResult, PluginSearchResult};
-
-use arboard::Clipboard;
+use dossier_plugin_sdk::{
+    export_plugin, services, ActionOutcome, Plugin, PluginAction, PluginResult, PluginSearchResult,
+    PluginTrait,
+};
 
 fn parse_torrent_line(line: &str) -> (String, String) {
     // Format: "164/11 - Superman (2025) UHDR+DV cz en.mkv (TC, 5.8 GiB)"
@@ -81,7 +80,11 @@ fn parse_torrent_output(output: &str) -> Vec<PluginResult> {
                         id: "copy_magnet".to_string(),
                         label: "Copy Magnet".to_string(),
                         shortcut: Some("Enter".to_string()),
+                        dangerous: false,
+                        confirmation: None,
                     }]),
+                    score: None,
+                    highlights: None,
                 });
             }
         } else {
@@ -92,55 +95,53 @@ fn parse_torrent_output(output: &str) -> Vec<PluginResult> {
     results
 }
 
-#[no_mangle]
-pub extern "Rust" fn get_plugin_info() -> Plugin {
-    Plugin {
-        id: "torrent".to_string(),
-        name: "Torrent Search".to_string(),
-        description: "Search for torrents using Attractorr".to_string(),
-        prefix: "torrent".to_string(),
-        icon: "🔍".to_string(),
-        config: None,
-    }
-}
+struct TorrentPlugin;
 
-#[no_mangle]
-pub extern "Rust" fn search_plugin(query: String) -> PluginSearchResult {
-    if query.is_empty() {
-        return PluginSearchResult::Results(vec![]);
+export_plugin!(TorrentPlugin);
+
+impl PluginTrait for TorrentPlugin {
+    fn get_info(&self) -> Plugin {
+        Plugin {
+            id: "torrent".to_string(),
+            name: "Torrent Search".to_string(),
+            description: "Search for torrents using Attractorr".to_string(),
+            prefix: "torrent".to_string(),
+            icon: "🔍".to_string(),
+            config: None,
+            capabilities: Vec::new(),
+        }
     }
 
-    use std::process::Command;
+    fn search(&self, query: &str) -> PluginSearchResult {
+        if query.is_empty() {
+            return PluginSearchResult::Results(vec![]);
+        }
 
-    let output = Command::new("attractorr").arg(&query).output();
+        use std::process::Command;
 
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let results = parse_torrent_output(&stdout);
-                PluginSearchResult::Results(results)
-            } else {
-                PluginSearchResult::Results(vec![])
+        let output = Command::new("attractorr").arg(query).output();
+
+        match output {
+            Ok(output) => {
+                if output.status.success() {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let results = parse_torrent_output(&stdout);
+                    PluginSearchResult::Results(results)
+                } else {
+                    PluginSearchResult::Results(vec![])
+                }
             }
+            Err(_) => PluginSearchResult::Results(vec![]),
         }
-        Err(_) => PluginSearchResult::Results(vec![]),
     }
-}
 
-#[no_mangle]
-pub extern "Rust" fn execute_plugin_action(
-    result_id: String,
-    action_id: String,
-) -> Result<String, String> {
-    match action_id.as_str() {
-        "copy_magnet" => match Clipboard::new() {
-            Ok(mut clipboard) => match clipboard.set_text(&result_id) {
-                Ok(_) => Ok("Magnet link copied to clipboard".to_string()),
-                Err(e) => Err(format!("Failed to copy magnet link: {}", e)),
-            },
-            Err(e) => Err(format!("Failed to access clipboard: {}", e)),
-        },
-        _ => Err("Unknown action".to_string()),
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        match action_id {
+            "copy_magnet" => services::host()
+                .write_clipboard(result_id)
+                .map(|_| ActionOutcome::Close)
+                .map_err(|e| format!("Failed to copy magnet link: {}", e)),
+            _ => Err("Unknown action".to_string()),
+        }
     }
 }
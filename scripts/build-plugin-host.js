@@ -0,0 +1,51 @@
+#!/usr/bin/env node
+
+// Builds dossier-plugin-host and puts it where Tauri expects the sidecars
+// listed in bundle.externalBin: src-tauri/binaries, named after the target
+// triple. Tauri copies it next to the app executable, in dev builds too.
+// Pass --debug for a debug build.
+
+import fs from 'fs';
+import path from 'path';
+import { execFileSync } from 'child_process';
+import { fileURLToPath } from 'url';
+
+const HOST_NAME = 'dossier-plugin-host';
+const TAURI_DIR = path.join(path.dirname(fileURLToPath(import.meta.url)), '..', 'src-tauri');
+const BINARIES_DIR = path.join(TAURI_DIR, 'binaries');
+const PROFILE = process.argv.includes('--debug') ? 'debug' : 'release';
+
+// Set by the Tauri CLI for the before commands, cross builds included
+function targetTriple() {
+	if (process.env.TAURI_ENV_TARGET_TRIPLE) {
+		return process.env.TAURI_ENV_TARGET_TRIPLE;
+	}
+	const info = execFileSync('rustc', ['-vV'], { encoding: 'utf8' });
+	const host = info.match(/^host: (\S+)$/m);
+	if (!host) {
+		throw new Error('Could not tell the target triple from rustc -vV');
+	}
+	return host[1];
+}
+
+const triple = targetTriple();
+const extension = triple.includes('windows') ? '.exe' : '';
+const sidecar = path.join(BINARIES_DIR, `${HOST_NAME}-${triple}${extension}`);
+
+// tauri-build refuses to build the crate, the host included, while a
+// sidecar is missing
+fs.mkdirSync(BINARIES_DIR, { recursive: true });
+if (!fs.existsSync(sidecar)) {
+	fs.writeFileSync(sidecar, '');
+}
+
+const args = ['build', '--bin', HOST_NAME, '--target', triple];
+if (PROFILE === 'release') {
+	args.push('--release');
+}
+execFileSync('cargo', args, { cwd: TAURI_DIR, stdio: 'inherit' });
+
+const built = path.join(TAURI_DIR, 'target', triple, PROFILE, `${HOST_NAME}${extension}`);
+fs.copyFileSync(built, sidecar);
+fs.chmodSync(sidecar, 0o755);
+console.log(`Copied ${HOST_NAME} to ${sidecar}`);
@@ -0,0 +1,327 @@
+// Keyboard shortcuts of plugin actions (`PluginAction::shortcut`), checked
+// before results reach the bar so it only has to compare strings.
+
+use crate::plugins::PluginResult;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+// Keys named like `KeyboardEvent.code`, without the `Key` and `Digit`
+// prefixes, so the bar can build the same string from a key press
+const NAMED_KEYS: &[&str] = &[
+    "Enter",
+    "Tab",
+    "Space",
+    "Backspace",
+    "Delete",
+    "Escape",
+    "ArrowUp",
+    "ArrowDown",
+    "ArrowLeft",
+    "ArrowRight",
+    "Home",
+    "End",
+    "PageUp",
+    "PageDown",
+    "Insert",
+    "Comma",
+    "Period",
+    "Slash",
+    "Backslash",
+    "Semicolon",
+    "Quote",
+    "BracketLeft",
+    "BracketRight",
+    "Minus",
+    "Equal",
+    "Backquote",
+];
+
+const ALIASES: &[(&str, &str)] = &[
+    ("return", "Enter"),
+    ("esc", "Escape"),
+    ("del", "Delete"),
+    ("up", "ArrowUp"),
+    ("down", "ArrowDown"),
+    ("left", "ArrowLeft"),
+    ("right", "ArrowRight"),
+    (",", "Comma"),
+    (".", "Period"),
+    ("/", "Slash"),
+    (";", "Semicolon"),
+    ("-", "Minus"),
+    ("=", "Equal"),
+];
+
+// Used by the bar itself to run the selected result and to move through
+// results and the action picker
+const RESERVED: &[&str] = &["Enter", "ArrowUp", "ArrowDown", "Escape", "Tab"];
+
+// Results come back on every keystroke, each problem is reported once
+static REPORTED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+fn report(problem: String) {
+    let reported = REPORTED.get_or_init(|| Mutex::new(HashSet::new()));
+    if reported.lock().unwrap().insert(problem.clone()) {
+        eprintln!("{}", problem);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShortcutError {
+    #[error("no key given")]
+    NoKey,
+    #[error("more than one key given: {0} and {1}")]
+    SeveralKeys(String, String),
+    #[error("unknown key {0:?}")]
+    UnknownKey(String),
+    #[error("{0} is used by the bar itself")]
+    Reserved(String),
+    #[error("{0} would type into the search field, add Ctrl, Alt or Meta")]
+    TypesText(String),
+}
+
+/// A parsed shortcut such as `Ctrl+Shift+O`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ActionShortcut {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    meta: bool,
+    key: String,
+}
+
+fn parse_key(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    if let Some((_, key)) = ALIASES.iter().find(|(alias, _)| *alias == lower) {
+        return Some(key.to_string());
+    }
+    if let Some(key) = NAMED_KEYS.iter().find(|key| key.to_lowercase() == lower) {
+        return Some(key.to_string());
+    }
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            return Some(c.to_ascii_uppercase().to_string());
+        }
+    }
+    let function = lower.strip_prefix('f')?.parse::<u8>().ok()?;
+    (1..=24)
+        .contains(&function)
+        .then(|| format!("F{}", function))
+}
+
+impl FromStr for ActionShortcut {
+    type Err = ShortcutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut shortcut = ActionShortcut {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+            key: String::new(),
+        };
+        for part in s.split('+').map(str::trim) {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => shortcut.ctrl = true,
+                "alt" | "option" => shortcut.alt = true,
+                "shift" => shortcut.shift = true,
+                "meta" | "cmd" | "command" | "super" | "win" => shortcut.meta = true,
+                "cmdorctrl" | "commandorcontrol" if cfg!(target_os = "macos") => {
+                    shortcut.meta = true
+                }
+                "cmdorctrl" | "commandorcontrol" => shortcut.ctrl = true,
+                "" => return Err(ShortcutError::UnknownKey(s.to_string())),
+                _ => {
+                    let key = parse_key(part)
+                        .ok_or_else(|| ShortcutError::UnknownKey(part.to_string()))?;
+                    if !shortcut.key.is_empty() {
+                        return Err(ShortcutError::SeveralKeys(shortcut.key, key));
+                    }
+                    shortcut.key = key;
+                }
+            }
+        }
+        if shortcut.key.is_empty() {
+            return Err(ShortcutError::NoKey);
+        }
+        Ok(shortcut)
+    }
+}
+
+impl fmt::Display for ActionShortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+            (self.meta, "Meta"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+impl ActionShortcut {
+    /// Rejects shortcuts the bar cannot hand to an action: its own keys, and
+    /// keys that edit the query unless a modifier other than Shift is held.
+    pub fn validate(&self) -> Result<(), ShortcutError> {
+        let modified = self.ctrl || self.alt || self.meta;
+        if !modified && RESERVED.contains(&self.key.as_str()) {
+            return Err(ShortcutError::Reserved(self.to_string()));
+        }
+        let function_key = self.key.starts_with('F') && self.key.len() > 1;
+        if !modified && !function_key {
+            return Err(ShortcutError::TypesText(self.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites the shortcuts of `result`'s actions to the form the bar matches
+/// key presses against, dropping invalid ones and ones already taken by an
+/// earlier action of the same result. The first action may declare Enter,
+/// which runs it anyway.
+pub fn normalize(plugin_id: &str, result: &mut PluginResult) {
+    let Some(actions) = result.actions.as_mut() else {
+        return;
+    };
+    let mut taken = HashSet::new();
+    for (index, action) in actions.iter_mut().enumerate() {
+        let Some(text) = action.shortcut.take() else {
+            continue;
+        };
+        let shortcut = text.parse::<ActionShortcut>().and_then(|shortcut| {
+            if index == 0 && shortcut.to_string() == "Enter" {
+                return Ok(shortcut);
+            }
+            shortcut.validate().map(|_| shortcut)
+        });
+        match shortcut {
+            Ok(shortcut) if taken.insert(shortcut.clone()) => {
+                action.shortcut = Some(shortcut.to_string());
+            }
+            Ok(shortcut) => report(format!(
+                "Plugin {}: ignoring {} of action {}, another action of the same result uses it",
+                plugin_id, shortcut, action.id
+            )),
+            Err(e) => report(format!(
+                "Plugin {}: ignoring invalid shortcut {:?} of action {}: {}",
+                plugin_id, text, action.id, e
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dossier_plugin_sdk::PluginAction;
+
+    fn parse(shortcut: &str) -> Result<ActionShortcut, ShortcutError> {
+        shortcut.parse()
+    }
+
+    fn action(id: &str, shortcut: &str) -> PluginAction {
+        PluginAction {
+            id: id.to_string(),
+            label: id.to_string(),
+            shortcut: Some(shortcut.to_string()),
+            dangerous: false,
+            confirmation: None,
+        }
+    }
+
+    #[test]
+    fn normalizes_modifiers_aliases_and_case() {
+        for (shortcut, normalized) in [
+            ("ctrl+o", "Ctrl+O"),
+            ("Shift + Control + k", "Ctrl+Shift+K"),
+            ("Cmd+Option+1", "Alt+Meta+1"),
+            ("Alt+return", "Alt+Enter"),
+            ("Ctrl+,", "Ctrl+Comma"),
+            ("f5", "F5"),
+        ] {
+            assert_eq!(parse(shortcut).unwrap().to_string(), normalized);
+        }
+    }
+
+    #[test]
+    fn refuses_shortcuts_that_do_not_parse() {
+        assert!(matches!(parse("Ctrl+Shift"), Err(ShortcutError::NoKey)));
+        assert!(matches!(
+            parse("Ctrl+A+B"),
+            Err(ShortcutError::SeveralKeys(..))
+        ));
+        assert!(matches!(
+            parse("Ctrl+Hyper"),
+            Err(ShortcutError::UnknownKey(_))
+        ));
+        assert!(matches!(parse("Ctrl++"), Err(ShortcutError::UnknownKey(_))));
+        assert!(matches!(parse("F25"), Err(ShortcutError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn refuses_the_keys_of_the_bar() {
+        for shortcut in ["Enter", "Escape", "Tab", "Down", "Shift+Enter", "Shift+Tab"] {
+            let error = parse(shortcut).unwrap().validate().unwrap_err();
+            assert!(matches!(error, ShortcutError::Reserved(_)), "{}", shortcut);
+        }
+        for shortcut in ["Ctrl+Enter", "Alt+Escape", "Meta+Tab"] {
+            assert!(parse(shortcut).unwrap().validate().is_ok(), "{}", shortcut);
+        }
+    }
+
+    #[test]
+    fn refuses_keys_that_type_into_the_search_field() {
+        for shortcut in ["O", "Shift+O", "Space", "Backspace", "Comma"] {
+            let error = parse(shortcut).unwrap().validate().unwrap_err();
+            assert!(matches!(error, ShortcutError::TypesText(_)), "{}", shortcut);
+        }
+        for shortcut in ["Ctrl+O", "Alt+Shift+O", "F2", "Shift+F10"] {
+            assert!(parse(shortcut).unwrap().validate().is_ok(), "{}", shortcut);
+        }
+    }
+
+    #[test]
+    fn drops_shortcuts_taken_by_an_earlier_action_or_the_bar() {
+        let mut result = PluginResult {
+            id: "result".to_string(),
+            title: "Result".to_string(),
+            subtitle: None,
+            icon: None,
+            actions: Some(vec![
+                action("open", "Enter"),
+                action("reveal", "ctrl+o"),
+                action("copy", "Control+O"),
+                action("run", "Return"),
+                action("kill", "Ctrl+K"),
+            ]),
+            score: None,
+            highlights: None,
+        };
+        normalize("test", &mut result);
+
+        let shortcuts: Vec<_> = result
+            .actions
+            .unwrap()
+            .into_iter()
+            .map(|action| action.shortcut)
+            .collect();
+        assert_eq!(
+            shortcuts,
+            [
+                Some("Enter".to_string()),
+                Some("Ctrl+O".to_string()),
+                None,
+                None,
+                Some("Ctrl+K".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,5 @@
+// Runs a single plugin library out of process, see `plugins::host`.
+
+fn main() {
+    std::process::exit(app_lib::run_plugin_host());
+}
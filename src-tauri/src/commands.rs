@@ -1,3 +1,4 @@
 pub mod default;
 pub mod errors;
+pub mod history;
 pub mod settings;
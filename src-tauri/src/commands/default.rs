@@ -1,60 +1,484 @@
-use crate::plugins::loader::DynamicPluginLoader;
+use crate::action_shortcuts;
+use crate::history;
+use crate::plugins::loader::{self, DynamicPluginLoader, PluginDiagnostic};
+use crate::plugins::manifest::Manifest;
+use crate::plugins::signature::SignatureReport;
 use crate::plugins::*;
-use std::sync::OnceLock;
-use tauri::Manager;
+use crate::ranking::{self, TaggedResult};
+use crate::settings::SettingsState;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tauri::{App, AppHandle, Emitter, Listener, Manager};
+use tokio::task::JoinSet;
 
-static PLUGIN_LOADER: OnceLock<DynamicPluginLoader> = OnceLock::new();
+static PLUGIN_LOADER: OnceLock<RwLock<DynamicPluginLoader>> = OnceLock::new();
 
-fn get_loader() -> &'static DynamicPluginLoader {
+// Newest query each plugin is searching, so typing on cancels the search for
+// the previous keystroke instead of letting slow plugins pile up.
+static ACTIVE_SEARCHES: OnceLock<Mutex<HashMap<String, (u64, CancelToken)>>> = OnceLock::new();
+
+fn active_searches() -> &'static Mutex<HashMap<String, (u64, CancelToken)>> {
+    ACTIVE_SEARCHES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Actions of the results shown so far by plugin and action id, and whether
+// any result marked them dangerous. Plugins reuse a handful of action ids, so
+// this is kept as long as the app runs; an action that was never shown is
+// only run once confirmed, no matter how the bar asks for it.
+static ACTIONS: OnceLock<Mutex<HashMap<String, HashMap<String, bool>>>> = OnceLock::new();
+
+fn actions() -> &'static Mutex<HashMap<String, HashMap<String, bool>>> {
+    ACTIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn remember_actions(plugin_id: &str, result: &PluginResult) {
+    let mut actions = actions().lock().unwrap();
+    let known = actions.entry(plugin_id.to_string()).or_default();
+    for action in result.actions.iter().flatten() {
+        *known.entry(action.id.clone()).or_default() |= action.dangerous;
+    }
+}
+
+fn needs_confirmation(plugin_id: &str, action_id: &str) -> bool {
+    let dangerous = actions()
+        .lock()
+        .unwrap()
+        .get(plugin_id)
+        .and_then(|known| known.get(action_id).copied());
+    match dangerous {
+        Some(dangerous) => {
+            dangerous
+                && !get_loader()
+                    .read()
+                    .unwrap()
+                    .confirmation_skipped(plugin_id, action_id)
+        }
+        None => true,
+    }
+}
+
+pub fn get_loader() -> &'static RwLock<DynamicPluginLoader> {
+    PLUGIN_LOADER
+        .get()
+        .expect("the plugin loader is created by setup_plugin_settings")
+}
+
+/// Loads the plugins as configured in the settings, and keeps the loader
+/// configuration (such as which plugins run isolated) in sync with them.
+pub fn setup_plugin_settings(app: &App) {
+    let settings = app.state::<SettingsState>().get();
     PLUGIN_LOADER.get_or_init(|| {
         let mut loader = DynamicPluginLoader::new();
+        loader.apply_settings(&settings);
         loader.load_all_dynamic_plugins();
+        RwLock::new(loader)
+    });
+    save_plugin_sources(app.handle());
+
+    let handle = app.handle().clone();
+    app.handle().listen("settings-changed", move |_| {
+        let settings = handle.state::<SettingsState>().get();
+        let plugins = {
+            let mut loader = get_loader().write().unwrap();
+            if !loader.apply_settings(&settings) {
+                return;
+            }
+            loader.list_plugins()
+        };
+        save_plugin_sources(&handle);
+        // Plugins switched on or allowed to run show up in the bar
+        if let Err(e) = handle.emit("plugins-changed", plugins) {
+            eprintln!("Failed to emit plugins-changed event: {}", e);
+        }
+    });
+}
+
+/// Saves where the plugins that took an id since the last save were loaded
+/// from, so a plugin found elsewhere later cannot take over the grants and
+/// storage that come with it.
+pub fn save_plugin_sources(app: &AppHandle) {
+    let claimed = get_loader().write().unwrap().take_claimed_sources();
+    if claimed.is_empty() {
+        return;
+    }
+    let saved = app.state::<SettingsState>().update(|settings| {
+        for (id, source) in claimed {
+            let options = settings.plugins.entry(id).or_default();
+            options.source.get_or_insert(source);
+        }
+    });
+    if let Err(e) = saved {
+        eprintln!("Failed to save plugin sources: {}", e);
+    }
+}
+
+/// Lets every plugin flush its state, called as the app exits.
+pub fn shutdown_plugins() {
+    if let Some(loader) = PLUGIN_LOADER.get() {
+        loader.read().unwrap().shutdown_all();
+    }
+    loader::remove_shadow_copies();
+}
+
+/// Tells the enabled plugins the bar was shown. Each plugin runs the hook on
+/// a thread of its own, so a slow one holds up neither the window nor the
+/// others.
+pub fn window_shown() {
+    let Some(loader) = PLUGIN_LOADER.get() else {
+        return;
+    };
+    let plugins: Vec<_> = {
+        let loader = loader.read().unwrap();
         loader
-    })
+            .enabled_plugin_ids()
+            .iter()
+            .filter_map(|id| loader.get_plugin(id))
+            .collect()
+    };
+    for plugin in plugins {
+        std::thread::spawn(move || plugin.on_window_shown());
+    }
 }
 
+// The returned handle keeps the plugin's library loaded even if it is
+// hot reloaded while the caller is still using it.
+fn get_plugin(plugin_id: &str) -> Option<Arc<dyn PluginTrait + Send + Sync>> {
+    get_loader().read().unwrap().get_plugin(plugin_id)
+}
+
+/// Runs an action and carries out the part of its outcome that concerns the
+/// window; the bar handles the rest. Dangerous actions, and ones not among
+/// the results shown, are refused unless `confirmed` is set, after the bar
+/// asked the user.
 #[tauri::command]
 pub async fn execute_plugin_action(
     app: tauri::AppHandle,
     plugin_id: String,
     result_id: String,
     action_id: String,
-) -> Result<String, String> {
-    let result = if let Some(plugin) = get_loader().get_plugin(&plugin_id) {
-        plugin.execute_action(&result_id, &action_id)
+    query: Option<String>,
+    confirmed: Option<bool>,
+) -> Result<ActionOutcome, String> {
+    if !confirmed.unwrap_or(false) && needs_confirmation(&plugin_id, &action_id) {
+        return Err(format!(
+            "{} asks for confirmation before running {}",
+            plugin_id, action_id
+        ));
+    }
+
+    // Actions block until the plugin is done, which must not hold up the
+    // async runtime serving the other commands
+    let mut result = if let Some(plugin) = get_plugin(&plugin_id) {
+        let ids = (result_id.clone(), action_id.clone());
+        tauri::async_runtime::spawn_blocking(move || plugin.execute_action(&ids.0, &ids.1))
+            .await
+            .unwrap_or_else(|e| Err(format!("Action {} failed: {}", action_id, e)))
     } else {
         Err("Plugin not found".to_string())
     };
+    if let Ok(ActionOutcome::PushResults { results }) = &mut result {
+        for result in results {
+            action_shortcuts::normalize(&plugin_id, result);
+            remember_actions(&plugin_id, result);
+        }
+    }
 
-    // Hide window after action execution
-    if let Some(window) = app.get_webview_window("main") {
-        let _ = window.hide();
+    // Picked results rank higher the next time a similar query is typed
+    if let (Ok(_), Some(query)) = (&result, &query) {
+        history::record(query, &plugin_id, &result_id);
+    }
+
+    // Anything else is shown in the bar, including errors
+    if let Ok(ActionOutcome::Close | ActionOutcome::Message(_)) = result {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.hide();
+        }
     }
 
     result
 }
 
+/// Registers `query_id` as the plugin's current search, cancelling an older
+/// one still running. Returns `None` if a newer query already started.
+fn begin_search(plugin_id: &str, query_id: u64) -> Option<CancelToken> {
+    let mut searches = active_searches().lock().unwrap();
+    if let Some((current, token)) = searches.get(plugin_id) {
+        if *current > query_id {
+            return None;
+        }
+        if *current < query_id {
+            token.cancel();
+        }
+    }
+    let token = CancelToken::new();
+    searches.insert(plugin_id.to_string(), (query_id, token.clone()));
+    Some(token)
+}
+
+fn end_search(plugin_id: &str, query_id: u64) {
+    let mut searches = active_searches().lock().unwrap();
+    if searches
+        .get(plugin_id)
+        .is_some_and(|(current, _)| *current == query_id)
+    {
+        searches.remove(plugin_id);
+    }
+}
+
+/// Scores, highlights and orders a plugin's results and checks their actions
+/// before they are shown.
+fn prepare(query: &str, plugin_id: &str, results: &mut [PluginResult]) {
+    for result in results.iter_mut() {
+        ranking::annotate(query, result);
+        action_shortcuts::normalize(plugin_id, result);
+        remember_actions(plugin_id, result);
+    }
+    ranking::promote(query, plugin_id, results);
+}
+
+/// Runs one search, dropping its results if a newer query supersedes it or
+/// it times out, in which case `None` is returned.
+async fn run_search(
+    plugin_id: &str,
+    query: &str,
+    query_id: Option<u64>,
+    sink: Option<ResultSink>,
+) -> Option<PluginSearchResult> {
+    let plugin = get_plugin(plugin_id)?;
+    let timeout = get_loader().read().unwrap().search_timeout(plugin_id);
+
+    let token = match query_id {
+        Some(query_id) => begin_search(plugin_id, query_id)?,
+        None => CancelToken::new(),
+    };
+    let context = match sink {
+        Some(sink) => {
+            let (query, plugin_id) = (query.to_string(), plugin_id.to_string());
+            let sink: ResultSink = Arc::new(move |mut results: Vec<PluginResult>| {
+                prepare(&query, &plugin_id, &mut results);
+                sink(results)
+            });
+            SearchContext::streaming(token.clone(), sink)
+        }
+        None => SearchContext::new(token.clone()),
+    };
+
+    let result = tokio::time::timeout(timeout, plugin.search(query, &context)).await;
+    if let Some(query_id) = query_id {
+        end_search(plugin_id, query_id);
+    }
+    // Nothing emitted from here on belongs to a live search
+    let cancelled = token.is_cancelled();
+    token.cancel();
+
+    match result {
+        Ok(_) if cancelled => None,
+        Ok(result) => match context.finish(result) {
+            PluginSearchResult::Results(mut results) => {
+                prepare(query, plugin_id, &mut results);
+                Some(PluginSearchResult::Results(results))
+            }
+            html => Some(html),
+        },
+        Err(_) => {
+            eprintln!(
+                "Plugin {} took longer than {:?} to search, dropping its results",
+                plugin_id, timeout
+            );
+            None
+        }
+    }
+}
+
+/// `query_id` increases with every query the bar sends; results of a query
+/// superseded by a newer one, or of one that timed out, are dropped.
 #[tauri::command]
-pub async fn search_plugin(plugin_id: String, query: String) -> PluginSearchResult {
-    if let Some(plugin) = get_loader().get_plugin(&plugin_id) {
-        plugin.search(&query).await
-    } else {
-        PluginSearchResult::Results(vec![])
+pub async fn search_plugin(
+    plugin_id: String,
+    query: String,
+    query_id: Option<u64>,
+) -> PluginSearchResult {
+    run_search(&plugin_id, &query, query_id, None)
+        .await
+        .unwrap_or(PluginSearchResult::Results(vec![]))
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchResultsEvent {
+    query_id: u64,
+    plugin_id: String,
+    result: PluginSearchResult,
+    done: bool,
+}
+
+/// Like `search_plugin`, but delivers the results as `search-results` events
+/// so batches a plugin finds early show up while it keeps searching. The last
+/// event of a query has `done` set.
+#[tauri::command]
+pub async fn stream_search_plugin(app: AppHandle, plugin_id: String, query: String, query_id: u64) {
+    let send = {
+        let app = app.clone();
+        let plugin_id = plugin_id.clone();
+        move |result: PluginSearchResult, done: bool| {
+            let event = SearchResultsEvent {
+                query_id,
+                plugin_id: plugin_id.clone(),
+                result,
+                done,
+            };
+            if let Err(e) = app.emit("search-results", event) {
+                eprintln!("Failed to send search results: {}", e);
+            }
+        }
+    };
+    let sink: ResultSink = Arc::new({
+        let send = send.clone();
+        move |results| send(PluginSearchResult::Results(results), false)
+    });
+
+    let result = run_search(&plugin_id, &query, Some(query_id), Some(sink))
+        .await
+        .unwrap_or(PluginSearchResult::Results(vec![]));
+    send(result, true);
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchAllResultsEvent {
+    query_id: u64,
+    results: Vec<TaggedResult>,
+}
+
+/// Results of every plugin so far, keyed by plugin id so ties between
+/// equally relevant results are broken the same way on every update.
+type CollectedResults = Arc<Mutex<BTreeMap<String, Vec<PluginResult>>>>;
+
+fn ranked(query: &str, collected: &CollectedResults) -> Vec<TaggedResult> {
+    let results = collected
+        .lock()
+        .unwrap()
+        .iter()
+        .flat_map(|(plugin_id, results)| {
+            results.iter().map(|result| TaggedResult {
+                plugin_id: plugin_id.clone(),
+                result: result.clone(),
+            })
+        })
+        .collect();
+    ranking::rank(query, results, &history::frecencies(query))
+}
+
+/// Searches all enabled plugins at once and returns their results as one
+/// ranked list. While slower plugins are still searching, the ranking of the
+/// results found so far is sent as `search-all-results` events.
+#[tauri::command]
+pub async fn search_all(app: AppHandle, query: String, query_id: u64) -> Vec<TaggedResult> {
+    let plugin_ids = get_loader().read().unwrap().enabled_plugin_ids();
+    let collected: CollectedResults = Arc::default();
+    let send_progress = {
+        let query = query.clone();
+        let collected = collected.clone();
+        move || {
+            let event = SearchAllResultsEvent {
+                query_id,
+                results: ranked(&query, &collected),
+            };
+            if let Err(e) = app.emit("search-all-results", event) {
+                eprintln!("Failed to send search results: {}", e);
+            }
+        }
+    };
+
+    let mut searches = JoinSet::new();
+    for plugin_id in plugin_ids {
+        let query = query.clone();
+        let collected = collected.clone();
+        let send_progress = send_progress.clone();
+        let sink: ResultSink = Arc::new({
+            let plugin_id = plugin_id.clone();
+            let collected = collected.clone();
+            let send_progress = send_progress.clone();
+            move |results| {
+                collected
+                    .lock()
+                    .unwrap()
+                    .entry(plugin_id.clone())
+                    .or_default()
+                    .extend(results);
+                send_progress();
+            }
+        });
+        searches.spawn(async move {
+            let result = run_search(&plugin_id, &query, Some(query_id), Some(sink)).await;
+            // Pages of HTML only make sense when a single plugin is searched
+            if let Some(PluginSearchResult::Results(results)) = result {
+                if !results.is_empty() {
+                    collected
+                        .lock()
+                        .unwrap()
+                        .entry(plugin_id)
+                        .or_default()
+                        .extend(results);
+                    send_progress();
+                }
+            }
+        });
     }
+    while searches.join_next().await.is_some() {}
+
+    ranked(&query, &collected)
 }
 
 #[tauri::command]
 pub fn get_plugin_info(plugin_id: String) -> Result<Plugin, String> {
-    if let Some(plugin) = get_loader().get_plugin(&plugin_id) {
+    if let Some(plugin) = get_plugin(&plugin_id) {
         Ok(plugin.get_info())
     } else {
         Err("Plugin not found".to_string())
     }
 }
 
+/// The settings the plugin declares, for the settings window to show as a
+/// form. Their values live under `plugins.<id>.config`.
+#[tauri::command]
+pub fn get_plugin_settings_schema(plugin_id: String) -> Result<PluginConfig, String> {
+    let plugin = get_plugin(&plugin_id).ok_or("Plugin not found")?;
+    Ok(plugin.get_info().config.unwrap_or_default())
+}
+
+/// Called by the bar when the user types the prefix of `plugin_id`, before
+/// its first search.
+#[tauri::command]
+pub fn plugin_prefix_activated(plugin_id: String) -> Result<(), String> {
+    let plugin = get_plugin(&plugin_id).ok_or("Plugin not found")?;
+    std::thread::spawn(move || plugin.on_prefix_activate());
+    Ok(())
+}
+
 #[tauri::command]
 pub fn list_plugins() -> Vec<Plugin> {
-    get_loader().list_plugins()
+    get_loader().read().unwrap().list_plugins()
+}
+
+#[tauri::command]
+pub fn list_plugin_manifests() -> Vec<Manifest> {
+    get_loader().read().unwrap().list_manifests()
+}
+
+/// How the signatures of the plugin libraries checked out, for the settings
+/// window to point out the unsigned ones.
+#[tauri::command]
+pub fn list_plugin_signatures() -> Vec<SignatureReport> {
+    get_loader().read().unwrap().list_signatures()
+}
+
+/// Every entry of the plugins directory with how loading it went, to find
+/// out why a plugin is missing without running Dossier from a terminal.
+#[tauri::command]
+pub fn get_plugin_diagnostics() -> Vec<PluginDiagnostic> {
+    get_loader().read().unwrap().list_diagnostics()
 }
 
 #[tauri::command]
@@ -0,0 +1,34 @@
+use crate::history;
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt;
+
+#[tauri::command]
+pub fn clear_history() -> Result<(), String> {
+    history::clear()
+}
+
+/// Saves the usage history as JSON, for backups or inspection, to a file
+/// the user picks in a save dialog. The destination never comes from the
+/// webview. Returns whether it was saved, false when the dialog was
+/// cancelled.
+#[tauri::command]
+pub async fn export_history(app: AppHandle) -> Result<bool, String> {
+    let picked = tokio::task::spawn_blocking(move || {
+        app.dialog()
+            .file()
+            .set_title("Export Usage History")
+            .add_filter("JSON", &["json"])
+            .set_file_name("dossier-history.json")
+            .blocking_save_file()
+    })
+    .await
+    .map_err(|e| format!("The save dialog failed: {}", e))?;
+    let Some(picked) = picked else {
+        return Ok(false);
+    };
+    let path = picked
+        .into_path()
+        .map_err(|e| format!("The picked destination is not a file path: {}", e))?;
+    history::export(&path)?;
+    Ok(true)
+}
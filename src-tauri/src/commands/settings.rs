@@ -1,36 +1,115 @@
-use crate::constants::get_settings_path;
-use serde_json::Value;
-use std::fs;
-use tauri::{Emitter, Manager};
+use crate::commands::default::get_loader;
+use crate::plugins::permissions::Grants;
+use crate::plugins::signature::SignatureSettings;
+use crate::settings::{Problem, Settings, SettingsError, SettingsState};
+use tauri::{AppHandle, Emitter, Manager, State, WebviewWindow};
+
+const SETTINGS_WINDOW: &str = "settings";
 
 #[tauri::command]
-pub fn get_settings() -> Result<Value, String> {
-    let settings_path = get_settings_path();
+pub fn get_settings(state: State<SettingsState>) -> Settings {
+    state.get()
+}
 
-    if settings_path.exists() {
-        let content = fs::read_to_string(&settings_path)
-            .map_err(|e| format!("Failed to read settings file: {}", e))?;
+/// Values in settings.json that could not be used when it was loaded and
+/// were replaced by their defaults, cleared once the settings are saved.
+#[tauri::command]
+pub fn get_settings_problems(state: State<SettingsState>) -> Vec<Problem> {
+    state.problems()
+}
 
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse settings: {}", e))
+fn emit_settings_changed(app: &AppHandle, settings: &Settings) {
+    if let Err(e) = app.emit("settings-changed", settings) {
+        eprintln!("Failed to emit settings event: {}", e);
+    }
+}
+
+/// Fails unless the command was sent by the settings window, which alone
+/// may change what plugins are trusted with.
+fn require_settings_window(
+    window: &WebviewWindow,
+    what: &'static str,
+) -> Result<(), SettingsError> {
+    if window.label() == SETTINGS_WINDOW {
+        Ok(())
     } else {
-        Ok(serde_json::json!({}))
+        Err(SettingsError::NotAllowed(what))
     }
 }
 
+/// Saves `settings`, or returns what is wrong with them without saving
+/// anything. Grants and signature settings are kept as they are, they are
+/// changed by `set_plugin_grants` and `set_signature_settings`.
 #[tauri::command]
-pub fn set_settings(settings: Value, app: tauri::AppHandle) -> Result<(), String> {
-    let settings_path = get_settings_path();
-
-    let content = serde_json::to_string_pretty(&settings)
-        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+pub fn set_settings(
+    settings: Settings,
+    state: State<SettingsState>,
+    app: AppHandle,
+) -> Result<(), SettingsError> {
+    let settings = state.update(|current| *current = settings.keeping_trust_of(current))?;
+    emit_settings_changed(&app, &settings);
+    Ok(())
+}
 
-    fs::write(&settings_path, content)
-        .map_err(|e| format!("Failed to write settings file: {}", e))?;
+/// Replaces the capabilities plugin `plugin_id` was allowed or denied.
+#[tauri::command]
+pub fn set_plugin_grants(
+    plugin_id: String,
+    grants: Grants,
+    window: WebviewWindow,
+    state: State<SettingsState>,
+    app: AppHandle,
+) -> Result<(), SettingsError> {
+    require_settings_window(&window, "plugin permissions")?;
+    let settings = state.update(|settings| {
+        settings.plugins.entry(plugin_id).or_default().grants = Some(grants);
+    })?;
+    emit_settings_changed(&app, &settings);
+    Ok(())
+}
 
-    // Emit settings changed event
-    app.emit("settings-changed", &settings)
-        .map_err(|e| format!("Failed to emit settings event: {}", e))?;
+/// Replaces the signature policy and trusted keys.
+#[tauri::command]
+pub fn set_signature_settings(
+    signatures: SignatureSettings,
+    window: WebviewWindow,
+    state: State<SettingsState>,
+    app: AppHandle,
+) -> Result<(), SettingsError> {
+    require_settings_window(&window, "signature settings")?;
+    let settings = state.update(|settings| settings.signatures = signatures)?;
+    emit_settings_changed(&app, &settings);
+    Ok(())
+}
 
+/// Answers the question the bar asks before a plugin is first loaded. Only
+/// the capabilities of a plugin held back for them that the user was not
+/// asked about yet are decided; a denied plugin is switched off.
+#[tauri::command]
+pub fn answer_permission_request(
+    plugin_id: String,
+    allowed: bool,
+    state: State<SettingsState>,
+    app: AppHandle,
+) -> Result<(), SettingsError> {
+    let undecided = get_loader()
+        .read()
+        .unwrap()
+        .undecided_capabilities(&plugin_id);
+    if undecided.is_empty() {
+        return Ok(());
+    }
+    let settings = state.update(|settings| {
+        let plugin = settings.plugins.entry(plugin_id).or_default();
+        let grants = plugin.grants.get_or_insert_with(Grants::default);
+        for capability in undecided {
+            grants.decide(capability, allowed);
+        }
+        if !allowed {
+            plugin.enabled = false;
+        }
+    })?;
+    emit_settings_changed(&app, &settings);
     Ok(())
 }
 
@@ -9,10 +9,32 @@ pub fn get_config_dir() -> PathBuf {
         .join(APP_NAME)
 }
 
+/// Per-user directory for files that can be recreated, such as the
+/// private copies of plugin libraries.
+pub fn get_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| get_config_dir().join("cache"))
+        .join(APP_NAME)
+}
+
 pub fn get_plugins_dir() -> PathBuf {
     get_config_dir().join("plugins")
 }
 
+pub fn get_plugin_data_dir(plugin_id: &str) -> PathBuf {
+    get_config_dir().join("data").join(plugin_id)
+}
+
+pub fn get_plugin_storage_path(plugin_id: &str) -> PathBuf {
+    get_config_dir()
+        .join("storage")
+        .join(format!("{}.json", plugin_id))
+}
+
 pub fn get_settings_path() -> PathBuf {
     get_config_dir().join("settings.json")
 }
+
+pub fn get_history_path() -> PathBuf {
+    get_config_dir().join("history.json")
+}
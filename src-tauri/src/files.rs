@@ -0,0 +1,19 @@
+// Writing the files Dossier keeps, such as the settings and the history.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Writes `content` to a temporary file next to `path` and renames it over
+/// `path`, so a crash leaves either the old or the new content and never
+/// half of it. Creates the directory of `path` if missing.
+pub fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(content).and_then(|_| file.sync_all())?;
+    fs::rename(&temp, path)
+}
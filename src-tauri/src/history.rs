@@ -0,0 +1,218 @@
+// Remembers which results were picked for which queries, so results picked
+// often and recently rank higher the next time a similar query is typed.
+
+use crate::constants::get_history_path;
+use crate::files::write_atomically;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Oldest entries are forgotten beyond this, they barely count anyway
+const MAX_ENTRIES: usize = 1000;
+
+const DAY: u64 = 24 * 60 * 60;
+
+static HISTORY: OnceLock<Mutex<Vec<HistoryEntry>>> = OnceLock::new();
+
+/// One result picked from the bar.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub query: String,
+    pub plugin_id: String,
+    pub result_id: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+fn normalize(query: &str) -> String {
+    query.trim().to_lowercase()
+}
+
+fn history() -> &'static Mutex<Vec<HistoryEntry>> {
+    HISTORY.get_or_init(|| {
+        let path = get_history_path();
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("Ignoring unreadable history {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Mutex::new(entries)
+    })
+}
+
+fn save(entries: &[HistoryEntry]) -> Result<(), String> {
+    let content = serde_json::to_string(entries)
+        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+    write_atomically(&get_history_path(), content.as_bytes())
+        .map_err(|e| format!("Failed to write history file: {}", e))
+}
+
+/// Adds `entry`, forgetting the oldest entries beyond [`MAX_ENTRIES`].
+fn push(entries: &mut Vec<HistoryEntry>, entry: HistoryEntry) {
+    entries.push(entry);
+    if entries.len() > MAX_ENTRIES {
+        let excess = entries.len() - MAX_ENTRIES;
+        entries.drain(..excess);
+    }
+}
+
+/// Records that the result `result_id` of `plugin_id` was picked after
+/// typing `query`.
+pub fn record(query: &str, plugin_id: &str, result_id: &str) {
+    let mut entries = history().lock().unwrap();
+    let entry = HistoryEntry {
+        query: normalize(query),
+        plugin_id: plugin_id.to_string(),
+        result_id: result_id.to_string(),
+        timestamp: now(),
+    };
+    push(&mut entries, entry);
+    if let Err(e) = save(&entries) {
+        eprintln!("{}", e);
+    }
+}
+
+// Recent picks weigh more, like browsers rank their address bar suggestions
+fn weight(age: u64) -> u32 {
+    match age / DAY {
+        0..=3 => 100,
+        4..=14 => 70,
+        15..=31 => 50,
+        32..=90 => 30,
+        _ => 10,
+    }
+}
+
+/// Frecency of every result picked for a query sharing a prefix with
+/// `query`, keyed by plugin and result id. Picking "Firefox" after typing
+/// "fire" boosts it when typing "fi" as well as "firefox".
+pub fn frecencies(query: &str) -> HashMap<(String, String), u32> {
+    frecencies_in(&history().lock().unwrap(), query, now())
+}
+
+/// [`frecencies`] of `entries` at `now`.
+fn frecencies_in(
+    entries: &[HistoryEntry],
+    query: &str,
+    now: u64,
+) -> HashMap<(String, String), u32> {
+    let query = normalize(query);
+    let mut frecencies = HashMap::new();
+    if query.is_empty() {
+        return frecencies;
+    }
+    for entry in entries {
+        if entry.query.is_empty()
+            || !(entry.query.starts_with(&query) || query.starts_with(&entry.query))
+        {
+            continue;
+        }
+        *frecencies
+            .entry((entry.plugin_id.clone(), entry.result_id.clone()))
+            .or_default() += weight(now.saturating_sub(entry.timestamp));
+    }
+    frecencies
+}
+
+/// Forgets every picked result.
+pub fn clear() -> Result<(), String> {
+    let mut entries = history().lock().unwrap();
+    entries.clear();
+    save(&entries)
+}
+
+/// Writes the history as a pretty printed JSON array to `path`.
+pub fn export(path: &Path) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(&*history().lock().unwrap())
+        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+    write_atomically(path, content.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000 * DAY;
+
+    fn entry(query: &str, result_id: &str, days_ago: u64) -> HistoryEntry {
+        HistoryEntry {
+            query: normalize(query),
+            plugin_id: "apps".to_string(),
+            result_id: result_id.to_string(),
+            timestamp: NOW - days_ago * DAY,
+        }
+    }
+
+    fn frecency(entries: &[HistoryEntry], query: &str, result_id: &str) -> u32 {
+        let key = ("apps".to_string(), result_id.to_string());
+        frecencies_in(entries, query, NOW)
+            .get(&key)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn boosts_picks_for_queries_sharing_a_prefix() {
+        let entries = [entry("Fire ", "firefox", 0)];
+        for query in ["fi", "fire", "FIREFOX"] {
+            assert_eq!(frecency(&entries, query, "firefox"), 100, "{}", query);
+        }
+        assert_eq!(frecency(&entries, "code", "firefox"), 0);
+        assert_eq!(frecency(&entries, "ifire", "firefox"), 0);
+    }
+
+    #[test]
+    fn gives_no_boost_without_a_query() {
+        let entries = [entry("fire", "firefox", 0), entry("", "files", 0)];
+        assert!(frecencies_in(&entries, " ", NOW).is_empty());
+        assert_eq!(frecency(&entries, "f", "files"), 0);
+    }
+
+    #[test]
+    fn weighs_older_picks_less() {
+        let weights: Vec<_> = [0, 10, 20, 60, 365]
+            .into_iter()
+            .map(|days_ago| frecency(&[entry("fire", "firefox", days_ago)], "fire", "firefox"))
+            .collect();
+        assert_eq!(weights, [100, 70, 50, 30, 10]);
+    }
+
+    #[test]
+    fn adds_up_every_pick() {
+        let entries = [
+            entry("fire", "firefox", 0),
+            entry("firef", "firefox", 60),
+            entry("fire", "files", 0),
+        ];
+        assert_eq!(frecency(&entries, "fire", "firefox"), 130);
+        assert_eq!(frecency(&entries, "fire", "files"), 100);
+    }
+
+    #[test]
+    fn forgets_the_oldest_entries() {
+        let mut entries = Vec::new();
+        for index in 0..MAX_ENTRIES + 5 {
+            push(&mut entries, entry("fire", &index.to_string(), 0));
+        }
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].result_id, "5");
+        assert_eq!(
+            entries.last().unwrap().result_id,
+            (MAX_ENTRIES + 4).to_string()
+        );
+    }
+}
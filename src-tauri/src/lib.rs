@@ -1,16 +1,26 @@
+mod action_shortcuts;
 mod commands;
 mod constants;
+mod files;
+mod history;
 mod plugins;
+mod ranking;
+mod settings;
 mod shortcuts;
 use commands::default::{
-    execute_plugin_action, get_is_window_shown, get_plugin_info, list_plugins, search_plugin,
-    set_is_window_shown,
+    execute_plugin_action, get_is_window_shown, get_plugin_diagnostics, get_plugin_info,
+    get_plugin_settings_schema, list_plugin_manifests, list_plugin_signatures, list_plugins,
+    plugin_prefix_activated, search_all, search_plugin, set_is_window_shown, stream_search_plugin,
+};
+use commands::history::{clear_history, export_history};
+use commands::settings::{
+    answer_permission_request, get_settings, get_settings_problems, open_settings_window,
+    set_plugin_grants, set_settings, set_signature_settings, update_shortcuts,
 };
-use commands::settings::{get_settings, open_settings_window, set_settings, update_shortcuts};
 use tauri::{
     menu::{MenuBuilder, MenuItem},
     tray::{TrayIconBuilder, TrayIconEvent},
-    App, Manager,
+    App, Manager, RunEvent, WindowEvent,
 };
 
 fn setup_tray(app: &App) -> Result<(), Box<dyn std::error::Error>> {
@@ -82,29 +92,68 @@ fn setup_debug(app: &App) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Entry point of the `dossier-plugin-host` binary, returns the exit code.
+pub fn run_plugin_host() -> i32 {
+    plugins::host::run()
+}
+
 #[allow(clippy::missing_panics_doc)]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
+            app.manage(settings::SettingsState::load());
             setup_tray(&*app)?;
             setup_debug(&*app)?;
             shortcuts::setup_shortcuts(&*app)?;
+            commands::default::setup_plugin_settings(&*app);
+            if let Err(e) = plugins::watcher::watch_plugins_dir(app.handle().clone()) {
+                eprintln!("Failed to watch plugins directory: {}", e);
+            }
+            if let Err(e) = settings::watcher::watch_settings_file(app.handle().clone()) {
+                eprintln!("Failed to watch settings file: {}", e);
+            }
             Ok(())
         })
+        .on_window_event(|window, event| {
+            // The bar hides as soon as it loses focus, so gaining it means it
+            // was just shown
+            if window.label() == "main" && matches!(event, WindowEvent::Focused(true)) {
+                commands::default::window_shown();
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             search_plugin,
+            stream_search_plugin,
+            search_all,
             get_plugin_info,
+            get_plugin_diagnostics,
+            get_plugin_settings_schema,
+            plugin_prefix_activated,
             list_plugins,
+            list_plugin_manifests,
+            list_plugin_signatures,
             execute_plugin_action,
             get_is_window_shown,
             set_is_window_shown,
             get_settings,
+            get_settings_problems,
             set_settings,
+            set_plugin_grants,
+            set_signature_settings,
+            answer_permission_request,
             update_shortcuts,
-            open_settings_window
+            open_settings_window,
+            clear_history,
+            export_history
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|_, event| {
+            if let RunEvent::Exit = event {
+                commands::default::shutdown_plugins();
+            }
+        });
 }
@@ -0,0 +1,218 @@
+// Entry point of the `dossier-plugin-host` process, which loads a single
+// plugin library and serves the framed JSON-RPC protocol over stdin/stdout.
+
+use super::rpc::{self, Notification, Outgoing, Request, Response};
+use super::*;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Takes over the process stdout for protocol frames and points the standard
+/// output handle at stderr, so a plugin printing to stdout cannot corrupt the
+/// stream.
+#[cfg(unix)]
+fn take_protocol_output() -> std::io::Result<File> {
+    use std::os::fd::FromRawFd;
+
+    extern "C" {
+        fn dup(fd: i32) -> i32;
+        fn dup2(src: i32, dst: i32) -> i32;
+    }
+
+    unsafe {
+        let fd = dup(1);
+        if fd < 0 || dup2(2, 1) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(fd))
+    }
+}
+
+#[cfg(windows)]
+fn take_protocol_output() -> std::io::Result<File> {
+    use std::os::windows::io::FromRawHandle;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut std::ffi::c_void;
+        fn SetStdHandle(std_handle: u32, handle: *mut std::ffi::c_void) -> i32;
+    }
+
+    unsafe {
+        let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
+        if SetStdHandle(STD_OUTPUT_HANDLE, GetStdHandle(STD_ERROR_HANDLE)) == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(File::from_raw_handle(stdout))
+    }
+}
+
+/// Reads what the app sends, handing requests to `requests` with a token
+/// cancelling them, and acting on `cancel` notifications right away, while
+/// the request they are about is still being served. Stops once the app
+/// closed the pipe, or after handing on an unreadable frame.
+fn read_requests(requests: Sender<std::io::Result<(Request, CancelToken)>>) {
+    let mut input = BufReader::new(std::io::stdin());
+    // The app waits for each response before sending the next request, so
+    // only the last one can still be running
+    let mut running: Option<(u64, CancelToken)> = None;
+    loop {
+        let request = match rpc::read_frame(&mut input) {
+            Ok(Some(Outgoing::Request(request))) => {
+                let cancel = CancelToken::new();
+                running = Some((request.id, cancel.clone()));
+                Ok((request, cancel))
+            }
+            Ok(Some(Outgoing::Notification(notification))) => {
+                if notification.method == rpc::METHOD_CANCEL {
+                    let id = notification.params.get("id").and_then(Value::as_u64);
+                    if let Some((running_id, cancel)) = &running {
+                        if id == Some(*running_id) {
+                            cancel.cancel();
+                        }
+                    }
+                }
+                continue;
+            }
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        let failed = request.is_err();
+        if requests.send(request).is_err() || failed {
+            return;
+        }
+    }
+}
+
+async fn dispatch(
+    plugin: &Arc<dyn PluginTrait + Send + Sync>,
+    request: &Request,
+    cancel: CancelToken,
+    output: &Arc<Mutex<File>>,
+) -> Result<Value, String> {
+    let param = |name: &str| {
+        request
+            .params
+            .get(name)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    match request.method.as_str() {
+        rpc::METHOD_INFO => serde_json::to_value(plugin.get_info()).map_err(|e| e.to_string()),
+        rpc::METHOD_SEARCH => {
+            // Batches are forwarded as they come, ahead of the response
+            let output = output.clone();
+            let sink: ResultSink = Arc::new(move |results| {
+                let notification =
+                    Notification::new(rpc::METHOD_RESULTS, json!({ "results": results }));
+                let _ = rpc::write_frame(&mut *output.lock().unwrap(), &notification);
+            });
+            let context = SearchContext::streaming(cancel, sink);
+            let results = plugin.search(&param("query"), &context).await;
+            // Stray batches would otherwise be taken for the next search's
+            context.cancel_token().cancel();
+            serde_json::to_value(context.finish(results)).map_err(|e| e.to_string())
+        }
+        rpc::METHOD_EXECUTE => plugin
+            .execute_action(&param("result_id"), &param("action_id"))
+            .and_then(|outcome| serde_json::to_value(outcome).map_err(|e| e.to_string())),
+        rpc::METHOD_CONFIGURE => serde_json::from_value(request.params.clone())
+            .map(|values| plugin.configure(&values))
+            .map(|_| Value::Null)
+            .map_err(|e| e.to_string()),
+        rpc::METHOD_INIT => {
+            let context: HostContext =
+                serde_json::from_value(request.params.clone()).map_err(|e| e.to_string())?;
+            // Requests are served one at a time, searches must not wait for
+            // the plugin to warm up
+            let plugin = plugin.clone();
+            std::thread::spawn(move || plugin.init(&context));
+            Ok(Value::Null)
+        }
+        rpc::METHOD_PREFIX_ACTIVATE => {
+            plugin.on_prefix_activate();
+            Ok(Value::Null)
+        }
+        rpc::METHOD_WINDOW_SHOWN => {
+            plugin.on_window_shown();
+            Ok(Value::Null)
+        }
+        rpc::METHOD_SHUTDOWN => {
+            plugin.shutdown();
+            Ok(Value::Null)
+        }
+        method => Err(format!("Unknown method: {}", method)),
+    }
+}
+
+pub fn run() -> i32 {
+    let mut args = std::env::args_os().skip(1).map(PathBuf::from);
+    let (Some(library), Some(source)) = (args.next(), args.next()) else {
+        eprintln!(
+            "usage: {} <plugin library> <plugin source>",
+            process::HOST_BINARY_NAME
+        );
+        return 2;
+    };
+
+    let output = match take_protocol_output() {
+        Ok(output) => Arc::new(Mutex::new(output)),
+        Err(e) => {
+            eprintln!("Failed to set up plugin host output: {}", e);
+            return 1;
+        }
+    };
+
+    // The host services use the proxy, grants and plugin sources set when
+    // the process started, it is restarted when they change
+    if let Some(settings) = crate::settings::peek(&crate::constants::get_settings_path()) {
+        permissions::set_grants(permissions::from_settings(&settings));
+        permissions::set_sources(permissions::sources_from_settings(&settings));
+        services::set_proxy(settings.proxy);
+    }
+
+    let plugin: Arc<dyn PluginTrait + Send + Sync> = match loader::open_library(&library, &source) {
+        Ok(plugin) => plugin.into(),
+        Err(e) => {
+            eprintln!("Failed to load plugin {:?}: {}", library, e);
+            return 1;
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start plugin host runtime: {}", e);
+            return 1;
+        }
+    };
+
+    let (sender, requests) = mpsc::channel();
+    std::thread::spawn(move || read_requests(sender));
+
+    loop {
+        let (request, cancel) = match requests.recv() {
+            Ok(Ok(request)) => request,
+            // The launcher closed the pipe, shut down quietly
+            Err(_) => return 0,
+            Ok(Err(e)) => {
+                eprintln!("Invalid request: {}", e);
+                return 1;
+            }
+        };
+
+        let result = runtime.block_on(dispatch(&plugin, &request, cancel, &output));
+        let mut output = output.lock().unwrap();
+        if rpc::write_frame(&mut *output, &Response::new(request.id, result)).is_err() {
+            return 1;
+        }
+        let _ = output.flush();
+    }
+}
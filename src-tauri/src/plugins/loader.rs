@@ -1,143 +1,1246 @@
+use super::manifest::{self, Manifest};
+use super::permissions::{self, Caller, Capability, Grants};
+use super::process::ProcessPlugin;
+use super::services::{self, ServiceHandle};
+use super::signature::{self, SignatureReport, SignatureStatus, Trust};
+use super::wasm::WasmPlugin;
 use super::*;
+use crate::settings::Settings;
+use dossier_plugin_sdk::abi::{
+    self, AbiVersionFn, ConfigureFn, ExecuteFn, FreeStringFn, HookFn, InfoFn, InitFn, SearchFn,
+    SearchWithContextFn, SetHostFn,
+};
 use libloading::{Library, Symbol};
-use std::collections::HashMap;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{c_char, c_void};
 use std::fs;
-use std::future::Future;
-use std::path::Path;
+use std::mem::ManuallyDrop;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{ProcessesToUpdate, System};
 
-type GetInfoFn = extern "Rust" fn() -> Plugin;
-type SearchFn = extern "Rust" fn(String) -> PluginSearchResult;
-type ExecuteActionFn = extern "Rust" fn(String, String) -> Result<String, String>;
+/// How long a search may take unless `plugins.<id>.searchTimeoutMs` says
+/// otherwise.
+pub const DEFAULT_SEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+const LIBRARY_EXTENSIONS: [&str; 3] = ["dll", "so", "dylib"];
+const WASM_EXTENSION: &str = "wasm";
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Library(#[from] libloading::Error),
+    #[error("missing required symbol `{0}`, the library is not a Dossier plugin or was built for an older plugin ABI")]
+    MissingSymbol(String),
+    #[error("plugin ABI version {found} is not supported (host requires version {expected}), rebuild the plugin against the current plugin interface")]
+    AbiMismatch { found: u32, expected: u32 },
+    #[error("plugin returned invalid info: {0}")]
+    InvalidInfo(String),
+    #[error("invalid plugin manifest: {0}")]
+    Manifest(#[from] toml::de::Error),
+    #[error("invalid plugin manifest: {0}")]
+    InvalidManifest(String),
+    #[error("plugin requires Dossier {required} or newer (running {running})")]
+    HostTooOld { required: String, running: String },
+    #[error(transparent)]
+    Wasm(#[from] wasmtime::Error),
+    #[error("{0}")]
+    Process(String),
+    #[error("refused by the signature policy, {0}")]
+    Signature(String),
+    #[error("plugin id `{id}` is already used by the plugin loaded from {}", .path.display())]
+    DuplicateId { id: String, path: PathBuf },
+    #[error("plugin id `{id}` belongs to the plugin first loaded from {}, remove `plugins.{id}.source` from the settings to hand it to this one", .path.display())]
+    ForeignId { id: String, path: PathBuf },
+}
+
+impl LoadError {
+    /// The ABI version the plugin was built for, when it was refused for it.
+    fn abi_version(&self) -> Option<u32> {
+        match self {
+            Self::AbiMismatch { found, .. } => Some(*found),
+            _ => None,
+        }
+    }
+
+    /// The id of a plugin refused for claiming one that is not its own.
+    fn plugin_id(&self) -> Option<String> {
+        match self {
+            Self::DuplicateId { id, .. } | Self::ForeignId { id, .. } => Some(id.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// What became of a file or directory found in the plugins directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LoadStatus {
+    Loaded,
+    Disabled,
+    /// Declares capabilities the user has not allowed or denied yet.
+    AwaitingPermission,
+    /// Declares capabilities the user denied.
+    PermissionDenied,
+    Failed,
+    /// Not a plugin library, WebAssembly module or plugin directory.
+    Ignored,
+}
+
+/// The outcome of the last attempt to load one entry of the plugins
+/// directory, so a plugin missing from the bar can be told apart from one
+/// that was never found.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginDiagnostic {
+    pub path: PathBuf,
+    /// Unknown when a library without a manifest could not be opened.
+    pub plugin_id: Option<String>,
+    pub status: LoadStatus,
+    pub error: Option<String>,
+    /// Set for libraries and modules, which only load when built for this
+    /// host's version, and for ones refused for theirs. Stdio plugins have
+    /// none.
+    pub abi_version: Option<u32>,
+    /// Milliseconds since the Unix epoch.
+    pub loaded_at: u64,
+    pub load_time_ms: u64,
+    pub signature: Option<SignatureStatus>,
+}
+
+unsafe fn get_symbol<T: Copy>(lib: &Library, name: &[u8]) -> Result<T, LoadError> {
+    let symbol: Symbol<T> = lib
+        .get(name)
+        .map_err(|_| LoadError::MissingSymbol(String::from_utf8_lossy(name).into_owned()))?;
+    Ok(*symbol)
+}
+
+pub fn is_plugin_library(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|ext| LIBRARY_EXTENSIONS.contains(&ext))
+}
+
+pub fn is_wasm_module(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some(WASM_EXTENSION)
+}
+
+/// A native library or WebAssembly module the loader can load directly.
+pub fn is_plugin_file(path: &Path) -> bool {
+    is_plugin_library(path) || is_wasm_module(path)
+}
+
+/// Maps a path that changed under the plugins directory to the plugin it
+/// belongs to: a library at the top level, or a plugin subdirectory for any
+/// file inside it.
+pub fn plugin_source(plugins_dir: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(plugins_dir).ok()?;
+    let mut components = relative.components();
+    let source = plugins_dir.join(components.next()?);
+
+    // Caches and tooling state written by the plugin itself must not
+    // restart it
+    let ignored = components.any(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        name.starts_with('.') || name == "__pycache__"
+    });
+    if ignored {
+        return None;
+    }
+
+    // A removed directory no longer looks like one, let the loader decide
+    if is_plugin_file(&source) || !source.is_file() {
+        Some(source)
+    } else {
+        None
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Directory holding the shadow copies of every process, one directory
+/// named after its pid each.
+fn shadow_root() -> PathBuf {
+    crate::constants::get_cache_dir().join("plugins")
+}
+
+/// Directory of this process's shadow copies, in the user's cache
+/// directory. Each process has its own, so none removes copies another one
+/// still uses.
+fn shadow_dir() -> PathBuf {
+    shadow_root().join(std::process::id().to_string())
+}
+
+/// Creates the directory shadow copies go to, readable and writable by this
+/// user alone so no one else can swap a copy once it was checked.
+fn create_shadow_dir() -> std::io::Result<PathBuf> {
+    let dir = shadow_dir();
+    fs::create_dir_all(&dir)?;
+    if fs::symlink_metadata(&dir)?.file_type().is_symlink() {
+        return Err(std::io::Error::other(format!(
+            "{:?} is a symbolic link",
+            dir
+        )));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Fails unless the directory belongs to this user
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// A private copy of a plugin file, removed once dropped. The copy is what
+/// gets checked and loaded, so replacing the original in between has no
+/// effect. The original also stays unlocked (Windows) and is never mapped
+/// (Unix), so a rebuild can overwrite it while the old version is still
+/// serving calls, and the dynamic linker cannot hand back the old handle
+/// for the same path.
+pub struct ShadowFile {
+    path: PathBuf,
+}
+
+impl ShadowFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Another copy of the same bytes, for a second user of the file.
+    pub fn duplicate(&self) -> std::io::Result<Self> {
+        shadow_copy(&self.path)
+    }
+}
+
+impl Drop for ShadowFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn shadow_copy(path: &Path) -> std::io::Result<ShadowFile> {
+    shadow_copy_into(&create_shadow_dir()?, path)
+}
+
+fn shadow_copy_into(dir: &Path, path: &Path) -> std::io::Result<ShadowFile> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("plugin");
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let target = dir.join(format!(
+        "{}-{}-{}.{}",
+        stem,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        ext
+    ));
+    let copy = ShadowFile { path: target };
+    fs::copy(path, copy.path())?;
+    Ok(copy)
+}
+
+/// A library loaded from its shadow copy, which is removed once unloaded.
+struct ShadowLibrary {
+    lib: ManuallyDrop<Library>,
+    // Dropped after the library is unloaded
+    _file: ShadowFile,
+    // Handed to the library, so dropped only after it is unloaded
+    services: Box<ServiceHandle>,
+}
+
+impl Drop for ShadowLibrary {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.lib) };
+    }
+}
 
 struct DynamicPlugin {
-    get_info: GetInfoFn,
+    info: Plugin,
     search: SearchFn,
-    execute_action: ExecuteActionFn,
-    _lib: Library,
+    // Libraries built before search contexts existed only export `search`
+    search_with_context: Option<SearchWithContextFn>,
+    execute_action: ExecuteFn,
+    free_string: FreeStringFn,
+    configure: Option<ConfigureFn>,
+    init: Option<InitFn>,
+    prefix_activate: Option<HookFn>,
+    window_shown: Option<HookFn>,
+    shutdown: Option<HookFn>,
+    // Keeps the function pointers above valid. Calls running on other
+    // threads hold a clone so a reload cannot unmap code still in use.
+    lib: Arc<ShadowLibrary>,
+}
+
+impl DynamicPlugin {
+    fn decode<T: serde::de::DeserializeOwned>(&self, json: Option<String>) -> Result<T, String> {
+        let json = json.ok_or_else(|| format!("Plugin {} returned no data", self.info.id))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Plugin {} returned malformed data: {}", self.info.id, e))
+    }
+}
+
+/// Called by plugins with the results found so far; `data` is the
+/// [`SearchContext`] of the running search.
+unsafe extern "C" fn emit_results(data: *mut c_void, batch: *const c_char) {
+    let context = &*(data as *const SearchContext);
+    let batch = abi::from_raw_str(batch);
+    abi::guard(
+        AssertUnwindSafe(|| match serde_json::from_str(&batch) {
+            Ok(results) => context.emit(results),
+            Err(e) => eprintln!("Plugin emitted malformed results: {}", e),
+        }),
+        || (),
+    )
 }
 
 #[async_trait::async_trait]
 impl PluginTrait for DynamicPlugin {
     fn get_info(&self) -> Plugin {
-        (self.get_info)()
+        self.info.clone()
     }
 
-    async fn search(&self, query: &str) -> PluginSearchResult {
-        tokio::task::spawn_blocking({
+    async fn search(&self, query: &str, context: &SearchContext) -> PluginSearchResult {
+        let json = tokio::task::spawn_blocking({
             let search_fn = self.search;
-            let query = query.to_string();
-            move || match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                search_fn(query)
-            })) {
-                Ok(results) => results,
-                Err(_) => PluginSearchResult::Results(vec![]),
+            let search_with_context = self.search_with_context;
+            let free_string = self.free_string;
+            let lib = self.lib.clone();
+            let query = abi::to_c_string(query);
+            let context = context.clone();
+            move || {
+                if context.is_cancelled() {
+                    return None;
+                }
+                let raw = match search_with_context {
+                    Some(search_fn) => {
+                        let data = &context as *const SearchContext as *mut c_void;
+                        let raw_context = abi::RawSearchContext::new(context.cancel_token().flag())
+                            .with_emit(emit_results, data);
+                        unsafe { search_fn(query.as_ptr(), &raw_context) }
+                    }
+                    None => unsafe { search_fn(query.as_ptr()) },
+                };
+                let json = unsafe { abi::take_plugin_string(raw, free_string) };
+                drop(lib);
+                json
             }
         })
         .await
-        .unwrap_or(PluginSearchResult::Results(vec![]))
+        .ok()
+        .flatten();
+
+        if context.is_cancelled() {
+            return PluginSearchResult::Results(vec![]);
+        }
+        self.decode(json).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            PluginSearchResult::Results(vec![])
+        })
+    }
+
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        let result_id = abi::to_c_string(result_id);
+        let action_id = abi::to_c_string(action_id);
+        let json = unsafe {
+            abi::take_plugin_string(
+                (self.execute_action)(result_id.as_ptr(), action_id.as_ptr()),
+                self.free_string,
+            )
+        };
+        self.decode::<Result<ActionOutcome, String>>(json)?
+    }
+
+    fn configure(&self, values: &ConfigValues) {
+        let Some(configure) = self.configure else {
+            return;
+        };
+        let values = abi::to_c_string(&serde_json::to_string(values).unwrap_or_default());
+        unsafe { configure(values.as_ptr()) }
+    }
+
+    fn init(&self, context: &HostContext) {
+        let Some(init) = self.init else {
+            return;
+        };
+        let context = abi::to_c_string(&serde_json::to_string(context).unwrap_or_default());
+        unsafe { init(context.as_ptr()) }
+    }
+
+    fn on_prefix_activate(&self) {
+        if let Some(hook) = self.prefix_activate {
+            unsafe { hook() }
+        }
+    }
+
+    fn on_window_shown(&self) {
+        if let Some(hook) = self.window_shown {
+            unsafe { hook() }
+        }
+    }
+
+    fn shutdown(&self) {
+        if let Some(hook) = self.shutdown {
+            unsafe { hook() }
+        }
     }
+}
+
+/// Loads the library from `file`, a copy of the plugin directory or file
+/// `source`, which it keeps until unloaded.
+fn open_dynamic_plugin(file: ShadowFile, source: &Path) -> Result<DynamicPlugin, LoadError> {
+    unsafe {
+        let lib = Library::new(file.path())?;
+        let lib = Arc::new(ShadowLibrary {
+            lib: ManuallyDrop::new(lib),
+            _file: file,
+            services: Box::new(ServiceHandle::new(source.to_path_buf())),
+        });
+
+        let abi_version: AbiVersionFn = get_symbol(&lib.lib, abi::ABI_VERSION_SYMBOL)?;
+        let found = abi_version();
+        if found != abi::ABI_VERSION {
+            return Err(LoadError::AbiMismatch {
+                found,
+                expected: abi::ABI_VERSION,
+            });
+        }
+
+        // Libraries built before host services existed do without them
+        if let Ok(set_host) = get_symbol::<SetHostFn>(&lib.lib, abi::SET_HOST_SYMBOL) {
+            set_host(&lib.services.raw());
+        }
+
+        let get_info: InfoFn = get_symbol(&lib.lib, abi::INFO_SYMBOL)?;
+        let search: SearchFn = get_symbol(&lib.lib, abi::SEARCH_SYMBOL)?;
+        let search_with_context: Option<SearchWithContextFn> =
+            get_symbol(&lib.lib, abi::SEARCH_WITH_CONTEXT_SYMBOL).ok();
+        let execute_action: ExecuteFn = get_symbol(&lib.lib, abi::EXECUTE_SYMBOL)?;
+        let free_string: FreeStringFn = get_symbol(&lib.lib, abi::FREE_STRING_SYMBOL)?;
+        let configure: Option<ConfigureFn> = get_symbol(&lib.lib, abi::CONFIGURE_SYMBOL).ok();
+        let init: Option<InitFn> = get_symbol(&lib.lib, abi::INIT_SYMBOL).ok();
+        let prefix_activate: Option<HookFn> =
+            get_symbol(&lib.lib, abi::PREFIX_ACTIVATE_SYMBOL).ok();
+        let window_shown: Option<HookFn> = get_symbol(&lib.lib, abi::WINDOW_SHOWN_SYMBOL).ok();
+        let shutdown: Option<HookFn> = get_symbol(&lib.lib, abi::SHUTDOWN_SYMBOL).ok();
+
+        let info_json = abi::take_plugin_string(get_info(), free_string)
+            .ok_or_else(|| LoadError::InvalidInfo("no data".to_string()))?;
+        let info: Plugin =
+            serde_json::from_str(&info_json).map_err(|e| LoadError::InvalidInfo(e.to_string()))?;
+        lib.services.set_plugin_id(&info.id);
+
+        Ok(DynamicPlugin {
+            info,
+            search,
+            search_with_context,
+            execute_action,
+            free_string,
+            configure,
+            init,
+            prefix_activate,
+            window_shown,
+            shutdown,
+            lib,
+        })
+    }
+}
+
+/// Loads a single plugin library in-process, bypassing the loader's
+/// configuration. Used by `dossier-plugin-host`, with the checked copy the
+/// app made of the library and the plugin directory or file it came from.
+/// Its own copy goes next to the app's, so it is removed along with the
+/// app's copies even when the host is killed.
+pub fn open_library(
+    path: &Path,
+    source: &Path,
+) -> Result<Box<dyn PluginTrait + Send + Sync>, LoadError> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Ok(Box::new(open_dynamic_plugin(
+        shadow_copy_into(dir, path)?,
+        source,
+    )?))
+}
+
+/// Removes the shadow copies of this process, and those the plugin hosts it
+/// started made, as the app exits. Copies of libraries still loaded may be
+/// locked (Windows) and stay behind.
+pub fn remove_shadow_copies() {
+    let _ = fs::remove_dir_all(shadow_dir());
+}
+
+/// Removes the shadow copies of processes which are no longer running, left
+/// behind when they crashed or were killed.
+fn remove_stale_shadow_copies() {
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::All, false);
+    remove_shadow_copies_in(&shadow_root(), |pid| {
+        system.process(sysinfo::Pid::from_u32(pid)).is_some()
+    });
+}
+
+/// Removes the directories of `root` named after a pid which is not
+/// `running`.
+fn remove_shadow_copies_in(root: &Path, running: impl Fn(u32) -> bool) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let pid = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok());
+        if let Some(pid) = pid {
+            if pid != std::process::id() && !running(pid) {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+}
+
+/// Ids of plugins configured to run in `dossier-plugin-host`, from the
+/// `plugins.<id>.isolated` settings.
+fn isolated_plugins(settings: &Settings) -> HashSet<String> {
+    settings
+        .plugins
+        .iter()
+        .filter(|(_, options)| options.isolated)
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Ids of plugins switched off in the `plugins.<id>.enabled` settings.
+fn disabled_plugins(settings: &Settings) -> HashSet<String> {
+    settings
+        .plugins
+        .iter()
+        .filter(|(_, options)| !options.enabled)
+        .map(|(id, _)| id.clone())
+        .collect()
+}
 
-    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<String, String> {
-        (self.execute_action)(result_id.to_string(), action_id.to_string())
+/// Search deadlines from the `plugins.<id>.searchTimeoutMs` settings.
+fn search_timeouts(settings: &Settings) -> HashMap<String, Duration> {
+    settings
+        .plugins
+        .iter()
+        .filter_map(|(id, options)| {
+            let timeout = options.search_timeout_ms?;
+            Some((id.clone(), Duration::from_millis(timeout)))
+        })
+        .collect()
+}
+
+/// Actions users chose to run without being asked first, from the
+/// `plugins.<id>.skipConfirmation` settings.
+fn skipped_confirmations(settings: &Settings) -> HashMap<String, HashSet<String>> {
+    settings
+        .plugins
+        .iter()
+        .filter(|(_, options)| !options.skip_confirmation.is_empty())
+        .map(|(id, options)| {
+            let actions = options.skip_confirmation.iter().cloned().collect();
+            (id.clone(), actions)
+        })
+        .collect()
+}
+
+/// Directory reserved for the files of plugin `id`, created on demand.
+fn plugin_data_dir(id: &str) -> Option<PathBuf> {
+    if !is_file_name(id) {
+        return None;
+    }
+    let dir = crate::constants::get_plugin_data_dir(id);
+    match fs::create_dir_all(&dir) {
+        Ok(()) => Some(dir),
+        Err(e) => {
+            eprintln!("Failed to create data directory of plugin {}: {}", id, e);
+            None
+        }
     }
 }
 
-unsafe impl Send for DynamicPlugin {}
-unsafe impl Sync for DynamicPlugin {}
+/// Values of the settings plugins declare themselves, from the
+/// `plugins.<id>.config` settings.
+fn plugin_configs(settings: &Settings) -> HashMap<String, Map<String, Value>> {
+    settings
+        .plugins
+        .iter()
+        .map(|(id, options)| (id.clone(), options.config.clone()))
+        .collect()
+}
 
 pub struct DynamicPluginLoader {
-    libraries: Vec<Library>,
-    plugins: HashMap<String, Box<dyn PluginTrait + Send + Sync>>,
+    plugins: HashMap<String, Arc<dyn PluginTrait + Send + Sync>>,
+    // Library file or plugin directory each plugin comes from, used to
+    // reload it.
+    sources: HashMap<PathBuf, String>,
+    // Library file or plugin directory each id belongs to, from the
+    // settings and claimed since. Only a plugin from there loads with it.
+    origins: BTreeMap<String, PathBuf>,
+    // Ids claimed since the settings were last saved with them
+    claimed: BTreeMap<String, PathBuf>,
+    // Manifests of plugin directories, including disabled plugins that are
+    // listed but not loaded.
+    manifests: HashMap<String, Manifest>,
+    // Info of plugins kept from loading until the user allows every
+    // capability they declare
+    pending: HashMap<String, Plugin>,
+    isolated: HashSet<String>,
+    disabled: HashSet<String>,
+    grants: BTreeMap<String, Grants>,
+    search_timeouts: HashMap<String, Duration>,
+    skipped_confirmations: HashMap<String, HashSet<String>>,
+    configs: HashMap<String, Map<String, Value>>,
+    trust: Trust,
+    // Signature check of every library or module opened, by its path
+    signatures: BTreeMap<PathBuf, SignatureReport>,
+    // Outcome of loading every entry of the plugins directory, by its path
+    diagnostics: BTreeMap<PathBuf, PluginDiagnostic>,
 }
 
 impl DynamicPluginLoader {
     pub fn new() -> Self {
         Self {
-            libraries: Vec::new(),
             plugins: HashMap::new(),
+            sources: HashMap::new(),
+            origins: BTreeMap::new(),
+            claimed: BTreeMap::new(),
+            manifests: HashMap::new(),
+            pending: HashMap::new(),
+            isolated: HashSet::new(),
+            disabled: HashSet::new(),
+            grants: BTreeMap::new(),
+            search_timeouts: HashMap::new(),
+            skipped_confirmations: HashMap::new(),
+            configs: HashMap::new(),
+            trust: Trust::default(),
+            signatures: BTreeMap::new(),
+            diagnostics: BTreeMap::new(),
+        }
+    }
+
+    /// Picks up the loader configuration, grants and proxy from the
+    /// settings, reloads the plugins whose isolation mode, grants, source or
+    /// enabled state changed, or all of them when the signature settings
+    /// changed, and reconfigures the ones whose own settings changed.
+    /// Plugins refused for their id are tried again when a source changed.
+    /// Returns whether any plugin was reloaded.
+    pub fn apply_settings(&mut self, settings: &Settings) -> bool {
+        services::set_proxy(settings.proxy.clone());
+        let isolated = isolated_plugins(settings);
+        let disabled = disabled_plugins(settings);
+        let grants = permissions::from_settings(settings);
+        permissions::set_grants(grants.clone());
+        let trust = Trust::from_settings(&settings.signatures);
+        let trust_changed = trust != self.trust;
+
+        // Ids claimed but not saved yet stay claimed, ones of plugins still
+        // loaded that lost their source are claimed again
+        let mut origins = permissions::sources_from_settings(settings);
+        self.claimed.retain(|id, _| !origins.contains_key(id));
+        for (path, id) in &self.sources {
+            if !origins.contains_key(id) && !self.claimed.contains_key(id) {
+                self.claimed.insert(id.clone(), path.clone());
+            }
         }
+        origins.extend(self.claimed.clone());
+        permissions::set_sources(origins.clone());
+        let origins_changed = origins != self.origins;
+
+        let mut changed: Vec<PathBuf> = self
+            .sources
+            .iter()
+            .filter(|(path, id)| {
+                trust_changed
+                    || isolated.contains(*id) != self.isolated.contains(*id)
+                    || grants.get(*id) != self.grants.get(*id)
+                    || origins.get(*id) != Some(*path)
+                    || (self.manifests.contains_key(*id)
+                        && disabled.contains(*id) != self.disabled.contains(*id))
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        if origins_changed {
+            changed.extend(
+                self.diagnostics
+                    .values()
+                    .filter(|diagnostic| diagnostic.status == LoadStatus::Failed)
+                    .filter(|diagnostic| !self.sources.contains_key(&diagnostic.path))
+                    .map(|diagnostic| diagnostic.path.clone()),
+            );
+        }
+        self.origins = origins;
+        self.isolated = isolated;
+        self.disabled = disabled;
+        self.grants = grants;
+        self.trust = trust;
+        self.search_timeouts = search_timeouts(settings);
+        self.skipped_confirmations = skipped_confirmations(settings);
+        let configs = plugin_configs(settings);
+        let reconfigured: Vec<String> = self
+            .plugins
+            .keys()
+            .filter(|id| configs.get(*id) != self.configs.get(*id))
+            .cloned()
+            .collect();
+        self.configs = configs;
+
+        // Reloaded plugins are configured as they are registered
+        for id in reconfigured {
+            if let Some(plugin) = self.plugins.get(&id) {
+                self.configure(&id, plugin.as_ref());
+            }
+        }
+        for path in &changed {
+            if let Err(e) = self.reload_plugin(path) {
+                eprintln!("Failed to reload plugin {:?}: {}", path, e);
+            }
+        }
+        !changed.is_empty()
+    }
+
+    /// Hands `plugin` the values of the settings it declares, if any.
+    fn configure(&self, id: &str, plugin: &(dyn PluginTrait + Send + Sync)) {
+        let Some(schema) = plugin.get_info().config.filter(|c| !c.fields.is_empty()) else {
+            return;
+        };
+        let empty = Map::new();
+        let stored = self.configs.get(id).unwrap_or(&empty);
+        let (values, problems) = schema.resolve(stored);
+        for problem in problems {
+            eprintln!(
+                "Plugin {}: ignoring setting {}, using the default",
+                id, problem
+            );
+        }
+        plugin.configure(&values);
+    }
+
+    pub fn search_timeout(&self, plugin_id: &str) -> Duration {
+        self.search_timeouts
+            .get(plugin_id)
+            .copied()
+            .unwrap_or(DEFAULT_SEARCH_TIMEOUT)
+    }
+
+    /// Whether the user opted out of confirming the plugin's dangerous
+    /// action `action_id`.
+    pub fn confirmation_skipped(&self, plugin_id: &str, action_id: &str) -> bool {
+        self.skipped_confirmations
+            .get(plugin_id)
+            .is_some_and(|actions| actions.contains(action_id))
     }
 
     pub fn load_plugins_from_directory<P: AsRef<Path>>(
         &mut self,
         dir: P,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        println!("Scanning directory: {:?}", dir.as_ref());
         if !dir.as_ref().exists() {
-            println!("Directory does not exist: {:?}", dir.as_ref());
             return Ok(());
         }
 
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
-            println!("Found file: {:?}", path);
-
-            if path.extension().and_then(|s| s.to_str()) == Some("dll")
-                || path.extension().and_then(|s| s.to_str()) == Some("so")
-                || path.extension().and_then(|s| s.to_str()) == Some("dylib")
-            {
-                println!("Attempting to load plugin: {:?}", path);
-                if let Err(e) = self.load_plugin_library(&path) {
+
+            if is_plugin_file(&path) || manifest::is_plugin_dir(&path) {
+                if let Err(e) = self.load_plugin(&path) {
                     eprintln!("Failed to load plugin {:?}: {}", path, e);
-                } else {
-                    println!("Successfully loaded plugin: {:?}", path);
                 }
+            } else {
+                self.record_ignored(&path);
             }
         }
         Ok(())
     }
 
-    fn load_plugin_library<P: AsRef<Path>>(
+    /// Loads the plugin directory, library or WebAssembly module at `path`
+    /// and records how that went.
+    fn load_plugin(&mut self, path: &Path) -> Result<(), LoadError> {
+        let started = Instant::now();
+        let result = if path.is_dir() {
+            self.load_manifest_plugin(path)
+        } else {
+            self.load_plugin_file(path)
+        };
+        self.record_diagnostic(path, started, &result);
+        result
+    }
+
+    fn record_diagnostic(&mut self, path: &Path, started: Instant, result: &Result<(), LoadError>) {
+        let load_time_ms = started.elapsed().as_millis() as u64;
+        let plugin_id = self
+            .sources
+            .get(path)
+            .cloned()
+            .or_else(|| result.as_ref().err().and_then(LoadError::plugin_id));
+        let loaded = plugin_id
+            .as_ref()
+            .is_some_and(|id| self.plugins.contains_key(id));
+        let disabled = plugin_id
+            .as_ref()
+            .is_some_and(|id| self.disabled.contains(id));
+        // Libraries and modules only load when built for this version
+        let has_library = self
+            .signatures
+            .keys()
+            .any(|library| library.starts_with(path) && is_plugin_file(library));
+        let loaded_abi = (loaded && has_library).then_some(abi::ABI_VERSION);
+        let undecided = plugin_id
+            .as_ref()
+            .and_then(|id| self.pending.get(id))
+            .is_some_and(|info| {
+                !self
+                    .grants(&info.id)
+                    .undecided(&info.capabilities)
+                    .is_empty()
+            });
+        // Disabled plugins without a manifest are loaded, just not searched
+        let (status, error, abi_version) = match result {
+            Err(e) => (LoadStatus::Failed, Some(e.to_string()), e.abi_version()),
+            Ok(()) if disabled => (LoadStatus::Disabled, None, loaded_abi),
+            Ok(()) if loaded => (LoadStatus::Loaded, None, loaded_abi),
+            Ok(()) if undecided => (LoadStatus::AwaitingPermission, None, None),
+            Ok(()) => (LoadStatus::PermissionDenied, None, None),
+        };
+        let signature = self
+            .signatures
+            .values()
+            .find(|report| report.library.starts_with(path))
+            .map(|report| report.status.clone());
+        self.diagnostics.insert(
+            path.to_path_buf(),
+            PluginDiagnostic {
+                path: path.to_path_buf(),
+                plugin_id: plugin_id.or_else(|| {
+                    self.signatures
+                        .get(path)
+                        .and_then(|report| report.plugin_id.clone())
+                }),
+                status,
+                error,
+                abi_version,
+                loaded_at: unix_millis(),
+                load_time_ms,
+                signature,
+            },
+        );
+    }
+
+    /// Lists an entry of the plugins directory that is not loaded, except
+    /// signatures and hidden files, which are expected next to plugins.
+    fn record_ignored(&mut self, path: &Path) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let signature =
+            path.extension().and_then(|s| s.to_str()) == Some(signature::SIGNATURE_EXTENSION);
+        if name.starts_with('.') || signature {
+            return;
+        }
+        let error = if path.is_dir() {
+            format!("the directory has no {}", manifest::MANIFEST_FILE)
+        } else {
+            "the file is not a plugin library or WebAssembly module".to_string()
+        };
+        self.diagnostics.insert(
+            path.to_path_buf(),
+            PluginDiagnostic {
+                path: path.to_path_buf(),
+                plugin_id: None,
+                status: LoadStatus::Ignored,
+                error: Some(error),
+                abi_version: None,
+                loaded_at: unix_millis(),
+                load_time_ms: 0,
+                signature: None,
+            },
+        );
+    }
+
+    /// Takes `id` for the plugin from `path`. Refused while a plugin from
+    /// elsewhere is loaded with it, or when it belongs to a plugin first
+    /// loaded from elsewhere, whose grants and storage it would get.
+    fn claim(&mut self, path: &Path, id: &str) -> Result<(), LoadError> {
+        let loaded = self
+            .sources
+            .iter()
+            .find(|(source, existing)| *existing == id && source.as_path() != path);
+        if let Some((source, _)) = loaded {
+            return Err(LoadError::DuplicateId {
+                id: id.to_string(),
+                path: source.clone(),
+            });
+        }
+        match self.origins.get(id) {
+            Some(origin) if origin != path => {
+                return Err(LoadError::ForeignId {
+                    id: id.to_string(),
+                    path: origin.clone(),
+                })
+            }
+            Some(_) => {}
+            None => {
+                self.origins.insert(id.to_string(), path.to_path_buf());
+                self.claimed.insert(id.to_string(), path.to_path_buf());
+                permissions::set_sources(self.origins.clone());
+            }
+        }
+        self.sources.insert(path.to_path_buf(), id.to_string());
+        Ok(())
+    }
+
+    /// Ids claimed since the settings were last saved with them, by the
+    /// plugin directory or file they belong to. They are claimed until the
+    /// settings say otherwise, saving them makes that last across restarts.
+    pub fn take_claimed_sources(&mut self) -> BTreeMap<String, PathBuf> {
+        std::mem::take(&mut self.claimed)
+    }
+
+    /// Keeps the plugin with `info` from loading unless the user allowed
+    /// every capability it declares. It is listed with `info` until they
+    /// decide, and loaded again once they allowed them.
+    fn hold_back(&mut self, info: Plugin) -> bool {
+        let grants = self.grants(&info.id);
+        let blocked: Vec<&str> = info
+            .capabilities
+            .iter()
+            .filter(|capability| !grants.allows(**capability))
+            .map(|capability| capability.name())
+            .collect();
+        if blocked.is_empty() {
+            self.pending.remove(&info.id);
+            return false;
+        }
+        self.pending.insert(info.id.clone(), info);
+        true
+    }
+
+    /// Loads the plugin described by the manifest in `dir`. The manifest is
+    /// checked first, so nothing of an incompatible or disabled plugin runs,
+    /// nor of one declaring capabilities the user has not allowed.
+    fn load_manifest_plugin(&mut self, dir: &Path) -> Result<(), LoadError> {
+        let manifest = Manifest::load(dir)?;
+        let id = manifest.id.clone();
+        self.claim(dir, &id)?;
+
+        if self.disabled.contains(&id) {
+            self.manifests.insert(id, manifest);
+            return Ok(());
+        }
+        if self.hold_back(manifest.plugin_info()) {
+            self.manifests.insert(id, manifest);
+            return Ok(());
+        }
+
+        let plugin = match manifest.library_path(dir) {
+            Some(library) => match self.open_plugin_file(dir, &library, Some(&id))? {
+                Some(plugin) => plugin,
+                None => return Ok(()),
+            },
+            None => {
+                let status = self.trust.check_command();
+                self.admit_signature(dir, Some(&id), status)?;
+                Box::new(ProcessPlugin::stdio(dir, &manifest).map_err(LoadError::Process)?)
+            }
+        };
+        let info = plugin.get_info();
+        if info.id != id {
+            return Err(LoadError::InvalidInfo(format!(
+                "the manifest declares id `{}` but the plugin reports `{}`",
+                id, info.id
+            )));
+        }
+
+        // The info may declare more than the manifest
+        let mut declared = manifest.plugin_info();
+        for capability in info.capabilities {
+            if !declared.capabilities.contains(&capability) {
+                declared.capabilities.push(capability);
+            }
+        }
+        self.manifests.insert(id.clone(), manifest);
+        if !self.hold_back(declared) {
+            self.register_plugin(id, plugin);
+        }
+        Ok(())
+    }
+
+    /// Loads a library or WebAssembly module without a manifest.
+    fn load_plugin_file(&mut self, path: &Path) -> Result<(), LoadError> {
+        let Some(plugin) = self.open_plugin_file(path, path, None)? else {
+            return Ok(());
+        };
+        let info = plugin.get_info();
+        let id = info.id.clone();
+        if let Some(report) = self.signatures.get_mut(path) {
+            report.plugin_id = Some(id.clone());
+        }
+        self.claim(path, &id)?;
+        if !self.hold_back(info) {
+            self.register_plugin(id, plugin);
+        }
+        Ok(())
+    }
+
+    /// Opens a library or WebAssembly module of the plugin directory or file
+    /// `source` according to the settings for its plugin, once its
+    /// signature passed the signature policy. `id` comes from the manifest
+    /// when there is one, otherwise the library is opened to ask for it,
+    /// and `None` is returned when the plugin is held back for capabilities
+    /// the user did not allow. Everything is done with a copy taken up
+    /// front, so the bytes that are run are the ones that were checked.
+    fn open_plugin_file(
         &mut self,
-        path: P,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        unsafe {
-            let lib = Library::new(path.as_ref())?;
+        source: &Path,
+        path: &Path,
+        id: Option<&str>,
+    ) -> Result<Option<Box<dyn PluginTrait + Send + Sync>>, LoadError> {
+        let copy = shadow_copy(path)?;
+        let content = fs::read(copy.path())?;
+        let status = self.trust.check(path, &content);
+        self.admit_signature(path, id, status)?;
 
-            let get_info: Symbol<GetInfoFn> = lib.get(b"get_plugin_info")?;
-            let search: Symbol<SearchFn> = lib.get(b"search_plugin")?;
-            let execute_action: Symbol<ExecuteActionFn> = lib.get(b"execute_plugin_action")?;
+        if is_wasm_module(path) {
+            // Its info comes from an instance without any capability, the
+            // plugin is only run with its grants once it is registered, and
+            // only if its id belongs to it
+            let plugin = WasmPlugin::load(&content, source, |caller| self.grants_of(caller))?;
+            return Ok(Some(Box::new(plugin)));
+        }
 
-            let plugin = DynamicPlugin {
-                get_info: *get_info,
-                search: *search,
-                execute_action: *execute_action,
-                _lib: lib,
-            };
+        let (id, plugin) = match id {
+            Some(id) => (id.to_string(), None),
+            None => {
+                // Only asked for its info, nothing else of it runs before
+                // the user allowed what it declares
+                let plugin = open_dynamic_plugin(copy.duplicate()?, source)?;
+                if let Some(report) = self.signatures.get_mut(path) {
+                    report.plugin_id = Some(plugin.info.id.clone());
+                }
+                self.claim(source, &plugin.info.id)?;
+                if self.hold_back(plugin.info.clone()) {
+                    return Ok(None);
+                }
+                (plugin.info.id.clone(), Some(plugin))
+            }
+        };
+        if self.isolated.contains(&id) {
+            // Any library opened for its info is dropped, it runs in its own process
+            drop(plugin);
+            return Ok(Some(Box::new(
+                ProcessPlugin::isolated(copy, source).map_err(LoadError::Process)?,
+            )));
+        }
+        match plugin {
+            Some(plugin) => Ok(Some(Box::new(plugin))),
+            None => Ok(Some(Box::new(open_dynamic_plugin(copy, source)?))),
+        }
+    }
+
+    /// Records the signature check of the library or command at `path` and
+    /// whether the signature policy lets it load.
+    fn admit_signature(
+        &mut self,
+        path: &Path,
+        id: Option<&str>,
+        status: SignatureStatus,
+    ) -> Result<(), LoadError> {
+        let admitted = self.trust.admit(path, &status);
+        self.signatures.insert(
+            path.to_path_buf(),
+            SignatureReport {
+                library: path.to_path_buf(),
+                plugin_id: id.map(str::to_string),
+                status,
+            },
+        );
+        admitted
+    }
 
-            let info = plugin.get_info();
-            self.plugins.insert(info.id.clone(), Box::new(plugin));
+    /// Drops whatever was loaded from `path` and loads it again if it still
+    /// exists. A previous library is unloaded once the last in-flight call
+    /// into it returns, a previous plugin process is stopped.
+    pub fn reload_plugin(&mut self, path: &Path) -> Result<(), LoadError> {
+        self.signatures
+            .retain(|library, _| !library.starts_with(path));
+        self.diagnostics.remove(path);
+        if let Some(id) = self.sources.remove(path) {
+            if let Some(plugin) = self.plugins.remove(&id) {
+                plugin.shutdown();
+            }
+            self.manifests.remove(&id);
+            self.pending.remove(&id);
+        }
+        if (is_plugin_file(path) && path.exists()) || manifest::is_plugin_dir(path) {
+            self.load_plugin(path)?;
+        } else if path.exists() {
+            self.record_ignored(path);
         }
         Ok(())
     }
 
+    fn grants(&self, id: &str) -> Grants {
+        self.grants.get(id).cloned().unwrap_or_default()
+    }
+
+    /// Grants of the id `caller` reports, none unless the id belongs to the
+    /// plugin directory or file it was loaded from.
+    fn grants_of(&self, caller: &Caller) -> Grants {
+        match self.origins.get(&caller.id) {
+            Some(origin) if *origin != caller.source => Grants::default(),
+            _ => self.grants(&caller.id),
+        }
+    }
+
+    /// Configures the plugin and makes it available, then lets it initialize
+    /// in the background. A plugin registered before with the same id is
+    /// shut down.
     pub fn register_plugin(&mut self, id: String, plugin: Box<dyn PluginTrait + Send + Sync>) {
-        self.plugins.insert(id, plugin);
+        self.configure(&id, plugin.as_ref());
+        let plugin: Arc<dyn PluginTrait + Send + Sync> = Arc::from(plugin);
+        let context = HostContext {
+            host_version: env!("CARGO_PKG_VERSION").to_string(),
+            data_dir: plugin_data_dir(&id),
+        };
+        let initialized = plugin.clone();
+        std::thread::spawn(move || initialized.init(&context));
+        if let Some(replaced) = self.plugins.insert(id, plugin) {
+            replaced.shutdown();
+        }
+    }
+
+    /// Lets every loaded plugin flush its state, before the app exits.
+    pub fn shutdown_all(&self) {
+        for plugin in self.plugins.values() {
+            plugin.shutdown();
+        }
+    }
+
+    pub fn get_plugin(&self, id: &str) -> Option<Arc<dyn PluginTrait + Send + Sync>> {
+        self.plugins.get(id).cloned()
     }
 
-    pub fn get_plugin(&self, id: &str) -> Option<&(dyn PluginTrait + Send + Sync)> {
-        self.plugins.get(id).map(|p| p.as_ref())
+    /// Manifests of every plugin directory, loaded or not.
+    pub fn list_manifests(&self) -> Vec<Manifest> {
+        self.manifests.values().cloned().collect()
     }
 
+    /// Loaded plugins, followed by ones held back for their capabilities and
+    /// disabled ones known from their manifest. Each declares the
+    /// capabilities of its manifest and its info, as far as they are known.
     pub fn list_plugins(&self) -> Vec<Plugin> {
-        self.plugins.values().map(|p| p.get_info()).collect()
+        let loaded = self.plugins.iter().map(|(id, plugin)| {
+            let mut info = plugin.get_info();
+            if let Some(manifest) = self.manifests.get(id) {
+                for capability in &manifest.capabilities {
+                    if !info.capabilities.contains(capability) {
+                        info.capabilities.push(*capability);
+                    }
+                }
+            }
+            info
+        });
+        let held_back = self
+            .pending
+            .iter()
+            .filter(|(id, _)| !self.plugins.contains_key(*id))
+            .map(|(_, info)| info.clone());
+        let disabled = self
+            .manifests
+            .iter()
+            .filter(|(id, _)| !self.plugins.contains_key(*id) && !self.pending.contains_key(*id))
+            .map(|(_, manifest)| manifest.plugin_info());
+        loaded.chain(held_back).chain(disabled).collect()
+    }
+
+    /// Capabilities a plugin held back from loading declares that the user
+    /// has not been asked about, none for plugins that are not held back.
+    pub fn undecided_capabilities(&self, id: &str) -> Vec<Capability> {
+        self.pending
+            .get(id)
+            .map(|info| self.grants(id).undecided(&info.capabilities))
+            .unwrap_or_default()
+    }
+
+    /// Signature checks of the libraries and modules opened since they last
+    /// changed, including refused ones. Plugins talking over stdio have no
+    /// library and are reported as unsigned.
+    pub fn list_signatures(&self) -> Vec<SignatureReport> {
+        self.signatures.values().cloned().collect()
+    }
+
+    /// How loading went for every entry of the plugins directory, whether
+    /// or not it became a plugin.
+    pub fn list_diagnostics(&self) -> Vec<PluginDiagnostic> {
+        self.diagnostics.values().cloned().collect()
+    }
+
+    /// Ids of the loaded plugins that are not switched off, in a stable order.
+    pub fn enabled_plugin_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .plugins
+            .keys()
+            .filter(|id| !self.disabled.contains(*id))
+            .cloned()
+            .collect();
+        ids.sort();
+        ids
     }
 
     pub fn load_all_dynamic_plugins(&mut self) {
-        println!("Starting dynamic plugin loading...");
+        // Shadow copies left behind by an earlier process with the same id,
+        // and by processes which exited without removing theirs
+        remove_shadow_copies();
+        remove_stale_shadow_copies();
 
         let build_dir = crate::constants::get_plugins_dir();
-        println!("Looking for plugins in: {:?}", build_dir);
-        println!("Directory exists: {}", build_dir.exists());
-
         if let Err(e) = self.load_plugins_from_directory(&build_dir) {
             eprintln!("Failed to load plugins from {:?}: {}", build_dir, e);
         }
+    }
+}
 
-        println!("Total plugins loaded: {}", self.list_plugins().len());
-        for plugin in self.list_plugins() {
-            println!("Plugin: {} ({})", plugin.name, plugin.id);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_the_shadow_copies_of_exited_processes() {
+        let root = std::env::temp_dir().join(format!("dossier-loader-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for name in ["1", "2", "cache"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(root.join(name).join("plugin.so"), b"").unwrap();
         }
+        let own = root.join(std::process::id().to_string());
+        fs::create_dir_all(&own).unwrap();
+
+        remove_shadow_copies_in(&root, |pid| pid == 2);
+
+        assert!(!root.join("1").exists());
+        assert!(root.join("2").join("plugin.so").exists());
+        assert!(root.join("cache").exists());
+        assert!(own.exists());
+        fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -0,0 +1,263 @@
+// A plugin living in its own subdirectory of the plugins directory is
+// described by a `plugin.toml` manifest. The loader reads it before running
+// any plugin code, so disabled plugins are never loaded and incompatible
+// ones are rejected up front.
+
+use super::loader::LoadError;
+use super::permissions::Capability;
+use super::{is_file_name, Plugin};
+use dossier_plugin_sdk::abi::ABI_VERSION;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "plugin.toml";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub author: Option<String>,
+    pub description: String,
+    pub prefix: String,
+    pub icon: String,
+    /// Plugin ABI the library was built for, checked before it is loaded.
+    pub abi_version: Option<u32>,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    /// Oldest Dossier version the plugin works with.
+    pub min_host_version: Option<String>,
+    /// Native library or `.wasm` module in the plugin directory, named
+    /// without any directory. A name
+    /// without an extension gets the platform's library prefix and suffix
+    /// (`google_plugin` is `libgoogle_plugin.so` on Linux).
+    pub library: Option<String>,
+    /// Executable speaking JSON-RPC over stdio, either a file in the plugin
+    /// directory or a program looked up on `PATH` (e.g. `python3`), named
+    /// without any directory.
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Compares dotted version numbers, ignoring any pre-release suffix.
+fn version_at_least(version: &str, minimum: &str) -> Result<bool, String> {
+    let parse = |v: &str| -> Result<Vec<u64>, String> {
+        v.split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| {
+                part.parse()
+                    .map_err(|_| format!("`{}` is not a version number", v))
+            })
+            .collect()
+    };
+    let (mut version, mut minimum) = (parse(version)?, parse(minimum)?);
+    let len = version.len().max(minimum.len());
+    version.resize(len, 0);
+    minimum.resize(len, 0);
+    Ok(version >= minimum)
+}
+
+impl Manifest {
+    /// Reads the manifest in `dir` and checks that this host can run the
+    /// plugin.
+    pub fn load(dir: &Path) -> Result<Self, LoadError> {
+        let content = fs::read_to_string(dir.join(MANIFEST_FILE))?;
+        let manifest: Self = toml::from_str(&content)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<(), LoadError> {
+        if self.library.is_some() == self.command.is_some() {
+            return Err(LoadError::InvalidManifest(
+                "exactly one of `library` and `command` must be set".to_string(),
+            ));
+        }
+        // Both name a file of the plugin directory, or a program on `PATH`
+        for (field, name) in [("library", &self.library), ("command", &self.command)] {
+            if let Some(name) = name.as_deref().filter(|name| !is_file_name(name)) {
+                return Err(LoadError::InvalidManifest(format!(
+                    "`{}` must be a file name without directories, not {:?}",
+                    field, name
+                )));
+            }
+        }
+        if let Some(found) = self.abi_version {
+            if found != ABI_VERSION {
+                return Err(LoadError::AbiMismatch {
+                    found,
+                    expected: ABI_VERSION,
+                });
+            }
+        }
+        if let Some(required) = &self.min_host_version {
+            let running = env!("CARGO_PKG_VERSION");
+            if !version_at_least(running, required).map_err(LoadError::InvalidManifest)? {
+                return Err(LoadError::HostTooOld {
+                    required: required.clone(),
+                    running: running.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The plugin info as far as the manifest knows it, for plugins that are
+    /// listed without being loaded.
+    pub fn plugin_info(&self) -> Plugin {
+        Plugin {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            prefix: self.prefix.clone(),
+            icon: self.icon.clone(),
+            config: None,
+            capabilities: self.capabilities.clone(),
+        }
+    }
+
+    pub fn library_path(&self, dir: &Path) -> Option<PathBuf> {
+        let library = self.library.as_deref()?;
+        if Path::new(library).extension().is_some() {
+            Some(dir.join(library))
+        } else {
+            Some(dir.join(format!(
+                "{}{}{}",
+                std::env::consts::DLL_PREFIX,
+                library,
+                std::env::consts::DLL_SUFFIX
+            )))
+        }
+    }
+
+    pub fn program(&self, dir: &Path) -> Option<PathBuf> {
+        let command = self.command.as_deref()?;
+        let local = dir.join(command);
+        if local.is_file() {
+            Some(local)
+        } else {
+            Some(PathBuf::from(command))
+        }
+    }
+
+    pub fn args(&self) -> Vec<OsString> {
+        self.args.iter().map(OsString::from).collect()
+    }
+}
+
+pub fn is_plugin_dir(path: &Path) -> bool {
+    path.join(MANIFEST_FILE).is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(fields: &str) -> Result<Manifest, LoadError> {
+        let content = format!(
+            "id = \"calc\"\n\
+             name = \"Calculator\"\n\
+             version = \"1.0.0\"\n\
+             description = \"Evaluates expressions\"\n\
+             prefix = \"=\"\n\
+             icon = \"calculator\"\n\
+             {}",
+            fields
+        );
+        let manifest: Manifest = toml::from_str(&content)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    #[test]
+    fn accepts_a_library_or_a_command() {
+        let manifest = parse("library = \"calc\"\ncapabilities = [\"clipboard\"]").unwrap();
+        assert_eq!(manifest.capabilities, [Capability::Clipboard]);
+        let library = manifest.library_path(Path::new("plugins")).unwrap();
+        assert_eq!(
+            library.file_name().unwrap().to_string_lossy(),
+            format!(
+                "{}calc{}",
+                std::env::consts::DLL_PREFIX,
+                std::env::consts::DLL_SUFFIX
+            )
+        );
+
+        let manifest = parse("library = \"calc.wasm\"").unwrap();
+        let library = manifest.library_path(Path::new("plugins")).unwrap();
+        assert_eq!(library, Path::new("plugins").join("calc.wasm"));
+
+        let manifest = parse("command = \"python3\"\nargs = [\"calc.py\"]").unwrap();
+        assert_eq!(manifest.args(), [OsString::from("calc.py")]);
+    }
+
+    #[test]
+    fn needs_exactly_one_of_library_and_command() {
+        for fields in ["", "library = \"calc\"\ncommand = \"calc\""] {
+            assert!(matches!(parse(fields), Err(LoadError::InvalidManifest(_))));
+        }
+    }
+
+    #[test]
+    fn refuses_paths_outside_the_plugin_directory() {
+        for fields in [
+            "library = \"../calc.so\"",
+            "library = \"/usr/lib/calc.so\"",
+            "command = \"bin/calc\"",
+            "command = \"..\"",
+        ] {
+            assert!(
+                matches!(parse(fields), Err(LoadError::InvalidManifest(_))),
+                "{}",
+                fields
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_unknown_fields() {
+        let parsed = parse("library = \"calc\"\npermissions = [\"clipboard\"]");
+        assert!(matches!(parsed, Err(LoadError::Manifest(_))));
+    }
+
+    #[test]
+    fn checks_the_abi_version() {
+        let current = format!("library = \"calc\"\nabi_version = {}", ABI_VERSION);
+        assert!(parse(&current).is_ok());
+
+        let next = format!("library = \"calc\"\nabi_version = {}", ABI_VERSION + 1);
+        assert!(matches!(
+            parse(&next),
+            Err(LoadError::AbiMismatch { found, expected })
+                if found == ABI_VERSION + 1 && expected == ABI_VERSION
+        ));
+    }
+
+    #[test]
+    fn checks_the_host_version() {
+        assert!(parse("library = \"calc\"\nmin_host_version = \"0.0.1\"").is_ok());
+        assert!(matches!(
+            parse("library = \"calc\"\nmin_host_version = \"999.0\""),
+            Err(LoadError::HostTooOld { .. })
+        ));
+        assert!(matches!(
+            parse("library = \"calc\"\nmin_host_version = \"soon\""),
+            Err(LoadError::InvalidManifest(_))
+        ));
+    }
+
+    #[test]
+    fn compares_versions_by_number() {
+        assert!(version_at_least("1.10.0", "1.9").unwrap());
+        assert!(version_at_least("1.2", "1.2.0").unwrap());
+        assert!(version_at_least("1.2.3-beta.1", "1.2.3").unwrap());
+        assert!(!version_at_least("1.2.3", "1.3").unwrap());
+        assert!(version_at_least("1.x", "1.0").is_err());
+    }
+}
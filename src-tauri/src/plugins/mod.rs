@@ -1,56 +1,132 @@
+pub mod host;
 pub mod loader;
+pub mod manifest;
+pub mod permissions;
+pub mod process;
+pub mod rpc;
+pub mod services;
+pub mod signature;
+pub mod storage;
+pub mod wasm;
+pub mod watcher;
 
-// All plugins are dynamic - loaded from .build directory
+// Plugins are native libraries, WebAssembly modules or programs found in
+// the plugins directory by `loader`
 
-use std::collections::HashMap;
+pub use dossier_plugin_sdk::{
+    ActionOutcome, ConfigValues, HostContext, Plugin, PluginConfig, PluginResult,
+    PluginSearchResult,
+};
 
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
-pub struct PluginConfig {
-    #[serde(flatten)]
-    pub data: HashMap<String, serde_json::Value>,
-}
+use std::path::{Component, Path};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
-pub struct PluginAction {
-    pub id: String,
-    pub label: String,
-    pub shortcut: Option<String>,
+/// Whether a plugin id can name a file or directory of its own. Ids come
+/// from the plugins themselves, this keeps them from escaping.
+pub fn is_file_name(id: &str) -> bool {
+    let mut components = Path::new(id).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
 }
 
-#[derive(serde::Serialize, Clone)]
-pub struct PluginResult {
-    pub id: String,
-    pub title: String,
-    pub subtitle: Option<String>,
-    pub icon: Option<String>,
-    pub actions: Option<Vec<PluginAction>>,
-}
+/// Cancellation flag of one search, set once the query is superseded by a
+/// newer one or runs past the plugin's timeout.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-#[derive(serde::Serialize, Clone)]
-pub struct PluginHtmlResult {
-    pub html: String,
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn flag(&self) -> &AtomicBool {
+        &self.0
+    }
 }
 
-#[derive(serde::Serialize, Clone)]
-#[serde(untagged)]
-pub enum PluginSearchResult {
-    Results(Vec<PluginResult>),
-    Html(PluginHtmlResult),
+/// Receives batches of results while a search is still running.
+pub type ResultSink = Arc<dyn Fn(Vec<PluginResult>) + Send + Sync>;
+
+/// State of one search: whether it is still wanted, and where results found
+/// before it completes are streamed to. Clones share the state, which is the
+/// plugins' own [`dossier_plugin_sdk::SearchContext`].
+#[derive(Clone)]
+pub struct SearchContext {
+    cancel: CancelToken,
+    inner: Arc<dossier_plugin_sdk::SearchContext<'static>>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
-pub struct Plugin {
-    pub id: String,
-    pub name: String,
-    pub description: String,
-    pub prefix: String,
-    pub icon: String,
-    pub config: Option<PluginConfig>,
+impl SearchContext {
+    pub fn new(cancel: CancelToken) -> Self {
+        Self::with_sink(cancel, None)
+    }
+
+    pub fn streaming(cancel: CancelToken, sink: ResultSink) -> Self {
+        Self::with_sink(cancel, Some(Box::new(move |results| sink(results))))
+    }
+
+    fn with_sink(
+        cancel: CancelToken,
+        sink: Option<dossier_plugin_sdk::ResultSink<'static>>,
+    ) -> Self {
+        let token = cancel.clone();
+        let cancelled = Box::new(move || token.is_cancelled());
+        Self {
+            cancel,
+            inner: Arc::new(dossier_plugin_sdk::SearchContext::owning(cancelled, sink)),
+        }
+    }
+
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub fn emit(&self, results: Vec<PluginResult>) {
+        self.inner.emit(results);
+    }
+
+    /// Puts the batches that were not streamed in front of the final result.
+    pub fn finish(&self, result: PluginSearchResult) -> PluginSearchResult {
+        self.inner.finish(result)
+    }
 }
 
 #[async_trait::async_trait]
 pub trait PluginTrait: Send + Sync {
     fn get_info(&self) -> Plugin;
-    async fn search(&self, query: &str) -> PluginSearchResult;
-    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<String, String>;
+    /// Plugins may stream batches through `context` before returning the
+    /// rest, and may stop early once it is cancelled; whatever they return
+    /// then is discarded.
+    async fn search(&self, query: &str, context: &SearchContext) -> PluginSearchResult;
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String>;
+    /// Hands the plugin the values of the settings it declared in
+    /// [`Plugin::config`].
+    fn configure(&self, values: &ConfigValues) {
+        let _ = values;
+    }
+    /// Lets the plugin warm up once it is loaded and configured. Called on a
+    /// background thread, searches may run concurrently.
+    fn init(&self, context: &HostContext) {
+        let _ = context;
+    }
+    fn on_prefix_activate(&self) {}
+    fn on_window_shown(&self) {}
+    /// Lets the plugin flush its state before it is unloaded or the app
+    /// exits.
+    fn shutdown(&self) {}
 }
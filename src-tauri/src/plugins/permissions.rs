@@ -0,0 +1,173 @@
+// What plugins are allowed to do. Plugins declare the capabilities they
+// need in their manifest or info, the user allows or denies each of them
+// once, and the host services refuse calls needing a capability that was
+// not allowed. Native code can still do anything by itself; only
+// WebAssembly plugins are confined to their grants. Ids are whatever
+// plugins say they are, so each id belongs to the plugin directory or file
+// it was first loaded from, and only a plugin from there gets its grants
+// and storage.
+
+use crate::settings::Settings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+pub use dossier_plugin_sdk::Capability;
+
+/// Capabilities the user allowed or denied a plugin, from
+/// `plugins.<id>.grants`. A capability the user was never asked about is
+/// unset, and denied like a refused one.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Grants {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_url: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filesystem_read: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filesystem_write: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spawn_process: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kill_process: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notifications: Option<bool>,
+    /// Directories a WebAssembly plugin can read.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub read_paths: Vec<PathBuf>,
+    /// Directories a WebAssembly plugin can read and write.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub write_paths: Vec<PathBuf>,
+}
+
+impl Grants {
+    /// The user's answer for `capability`, `None` if they were not asked.
+    fn decision(&self, capability: Capability) -> Option<bool> {
+        match capability {
+            Capability::Clipboard => self.clipboard,
+            Capability::OpenUrl => self.open_url,
+            Capability::Network => self.network,
+            Capability::FilesystemRead => self.filesystem_read,
+            Capability::FilesystemWrite => self.filesystem_write,
+            Capability::SpawnProcess => self.spawn_process,
+            Capability::KillProcess => self.kill_process,
+            Capability::Notifications => self.notifications,
+        }
+    }
+
+    /// Records the user's answer for `capability`.
+    pub fn decide(&mut self, capability: Capability, allowed: bool) {
+        let decision = match capability {
+            Capability::Clipboard => &mut self.clipboard,
+            Capability::OpenUrl => &mut self.open_url,
+            Capability::Network => &mut self.network,
+            Capability::FilesystemRead => &mut self.filesystem_read,
+            Capability::FilesystemWrite => &mut self.filesystem_write,
+            Capability::SpawnProcess => &mut self.spawn_process,
+            Capability::KillProcess => &mut self.kill_process,
+            Capability::Notifications => &mut self.notifications,
+        };
+        *decision = Some(allowed);
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.decision(capability) == Some(true)
+    }
+
+    pub fn require(&self, capability: Capability) -> Result<(), String> {
+        if self.allows(capability) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Permission denied: the plugin has not been granted the {} capability",
+                capability
+            ))
+        }
+    }
+
+    /// The capabilities of `declared` the user still has to be asked about.
+    pub fn undecided(&self, declared: &[Capability]) -> Vec<Capability> {
+        declared
+            .iter()
+            .copied()
+            .filter(|capability| self.decision(*capability).is_none())
+            .collect()
+    }
+}
+
+/// Grants of every plugin that has some, from the `plugins.<id>.grants`
+/// settings.
+pub fn from_settings(settings: &Settings) -> BTreeMap<String, Grants> {
+    settings
+        .plugins
+        .iter()
+        .filter_map(|(id, options)| Some((id.clone(), options.grants.clone()?)))
+        .collect()
+}
+
+/// Where the plugin each id belongs to was loaded from, from the
+/// `plugins.<id>.source` settings.
+pub fn sources_from_settings(settings: &Settings) -> BTreeMap<String, PathBuf> {
+    settings
+        .plugins
+        .iter()
+        .filter_map(|(id, options)| Some((id.clone(), options.source.clone()?)))
+        .collect()
+}
+
+/// A plugin calling a host service: the id it reports, and the plugin
+/// directory or file it was loaded from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Caller {
+    pub id: String,
+    pub source: PathBuf,
+}
+
+impl Caller {
+    pub fn new(id: &str, source: &Path) -> Self {
+        Self {
+            id: id.to_string(),
+            source: source.to_path_buf(),
+        }
+    }
+}
+
+// Checked by the host services, which only know the calling plugin
+static GRANTS: RwLock<BTreeMap<String, Grants>> = RwLock::new(BTreeMap::new());
+static SOURCES: RwLock<BTreeMap<String, PathBuf>> = RwLock::new(BTreeMap::new());
+
+pub fn set_grants(grants: BTreeMap<String, Grants>) {
+    *GRANTS.write().unwrap() = grants;
+}
+
+pub fn set_sources(sources: BTreeMap<String, PathBuf>) {
+    *SOURCES.write().unwrap() = sources;
+}
+
+/// Fails if the id `caller` reports belongs to a plugin loaded from
+/// elsewhere, whose grants and storage are not the caller's.
+pub fn require_owner(caller: &Caller) -> Result<(), String> {
+    match SOURCES.read().unwrap().get(&caller.id) {
+        Some(source) if *source != caller.source => Err(format!(
+            "Permission denied: the plugin id {} belongs to the plugin loaded from {}",
+            caller.id,
+            source.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Fails unless the user allowed the plugin `caller` to use `capability`.
+pub fn require(caller: &Caller, capability: Capability) -> Result<(), String> {
+    require_owner(caller)?;
+    let grants = GRANTS.read().unwrap();
+    match grants.get(&caller.id) {
+        Some(granted) => granted.require(capability),
+        None => Grants::default().require(capability),
+    }
+}
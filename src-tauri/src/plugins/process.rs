@@ -0,0 +1,581 @@
+// Plugins running in a child process, so a crash (segfault, abort, panic)
+// takes down only that process instead of the whole launcher.
+
+use super::loader::ShadowFile;
+use super::manifest::Manifest;
+use super::rpc::{self, Framing, Incoming, Notification, Request};
+use super::*;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const HOST_BINARY_NAME: &str = "dossier-plugin-host";
+
+// A plugin that keeps crashing is given up on until the window has passed,
+// instead of being respawned in a tight loop.
+const MAX_STARTS: usize = 5;
+const START_WINDOW: Duration = Duration::from_secs(60);
+
+// A process that sends nothing for this long while a request is waiting is
+// taken to hang, and is killed so the next request starts a fresh one.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+// How often a search waiting for its response checks whether it was
+// cancelled, to pass that on to the process
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+// Shutting down runs while the plugins are being reloaded or the app exits,
+// which a plugin must not hold up for long
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Path of the `dossier-plugin-host` executable shipped next to the app.
+/// It is bundled as a sidecar (`bundle.externalBin`), which Tauri installs
+/// next to the app executable without its target triple, and looks up
+/// there by name.
+pub fn host_binary_path() -> std::io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    Ok(exe.with_file_name(format!(
+        "{}{}",
+        HOST_BINARY_NAME,
+        std::env::consts::EXE_SUFFIX
+    )))
+}
+
+struct Connection {
+    // Shared with `PluginProcess::running`, so the process can be killed
+    // while a request holds the connection
+    child: Arc<Mutex<Child>>,
+    stdin: ChildStdin,
+    // Messages read from stdout by a thread of their own, so waiting for
+    // them can time out
+    incoming: Receiver<Result<Incoming, String>>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Reads messages from `stdout` until the process closes it or sends
+/// something unreadable, or the connection is dropped.
+fn read_messages(framing: Framing, stdout: ChildStdout, sender: Sender<Result<Incoming, String>>) {
+    let mut stdout = BufReader::new(stdout);
+    loop {
+        let message = match framing.read::<_, Incoming>(&mut stdout) {
+            Ok(Some(message)) => Ok(message),
+            Ok(None) => return,
+            Err(e) => Err(e.to_string()),
+        };
+        let failed = message.is_err();
+        if sender.send(message).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Hands the results a search sent ahead of its response to `context`.
+fn forward_results(context: &SearchContext, notification: Notification) {
+    if notification.method != rpc::METHOD_RESULTS {
+        return;
+    }
+    match serde_json::from_value(notification.params["results"].clone()) {
+        Ok(results) => context.emit(results),
+        Err(e) => eprintln!("Plugin process sent malformed results: {}", e),
+    }
+}
+
+/// Why a request got no response.
+enum Broken {
+    /// The process exited or sent something it should not have.
+    Failed(String),
+    /// The process sent nothing for the given time.
+    Hung(Duration),
+}
+
+impl fmt::Display for Broken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Broken::Failed(e) => f.write_str(e),
+            Broken::Hung(timeout) => write!(
+                f,
+                "no response within {} seconds, stopped it",
+                timeout.as_secs()
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ProcessState {
+    connection: Option<Connection>,
+    next_id: u64,
+    starts: VecDeque<Instant>,
+    // Settings and host context of the plugin, sent again to every process
+    // started
+    config: Option<Value>,
+    context: Option<Value>,
+}
+
+/// A child process speaking JSON-RPC over stdin/stdout, restarted on demand
+/// after it exits or stops responding.
+pub struct PluginProcess {
+    name: String,
+    program: PathBuf,
+    args: Vec<OsString>,
+    current_dir: Option<PathBuf>,
+    framing: Framing,
+    state: Mutex<ProcessState>,
+    // Process of the current connection, killed by `shutdown` without
+    // waiting for a request in progress
+    running: Mutex<Option<Arc<Mutex<Child>>>>,
+}
+
+impl PluginProcess {
+    pub fn new(name: String, program: PathBuf, args: Vec<OsString>, framing: Framing) -> Self {
+        Self {
+            name,
+            program,
+            args,
+            current_dir: None,
+            framing,
+            state: Mutex::new(ProcessState::default()),
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Runs the process from `dir` instead of the app's working directory.
+    pub fn current_dir(mut self, dir: PathBuf) -> Self {
+        self.current_dir = Some(dir);
+        self
+    }
+
+    fn spawn(&self, state: &mut ProcessState) -> Result<Connection, String> {
+        let now = Instant::now();
+        while state
+            .starts
+            .front()
+            .is_some_and(|start| now.duration_since(*start) > START_WINDOW)
+        {
+            state.starts.pop_front();
+        }
+        if state.starts.len() >= MAX_STARTS {
+            return Err(format!(
+                "Plugin process {} crashed {} times in the last minute, not restarting it yet",
+                self.name, MAX_STARTS
+            ));
+        }
+        state.starts.push_back(now);
+
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            command.creation_flags(0x08000000);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to start plugin process {}: {}", self.name, e))?;
+        let stdin = child.stdin.take().ok_or("Plugin process has no stdin")?;
+        let stdout = child.stdout.take().ok_or("Plugin process has no stdout")?;
+        let (sender, incoming) = mpsc::channel();
+        let framing = self.framing;
+        std::thread::spawn(move || read_messages(framing, stdout, sender));
+
+        let child = Arc::new(Mutex::new(child));
+        *self.running.lock().unwrap() = Some(child.clone());
+        Ok(Connection {
+            child,
+            stdin,
+            incoming,
+        })
+    }
+
+    /// Sends one request and waits for its response. Requests flagged as
+    /// `retry` are resent once to a fresh process if the current one died;
+    /// others (such as actions with side effects) are not replayed, and
+    /// neither is a request the process hung on.
+    pub fn request(&self, method: &str, params: Value, retry: bool) -> Result<Value, String> {
+        let mut state = self.state.lock().unwrap();
+        self.send(&mut state, method, params, retry, RESPONSE_TIMEOUT, None)
+    }
+
+    /// Sends a search, streaming the results the process sends before its
+    /// response to `context`, and telling the process when `context` is
+    /// cancelled. Nothing is sent and `None` is returned if the search was
+    /// cancelled while earlier requests were being answered.
+    pub fn search(&self, params: Value, context: &SearchContext) -> Result<Option<Value>, String> {
+        let mut state = self.state.lock().unwrap();
+        if context.is_cancelled() {
+            return Ok(None);
+        }
+        self.send(
+            &mut state,
+            rpc::METHOD_SEARCH,
+            params,
+            true,
+            RESPONSE_TIMEOUT,
+            Some(context),
+        )
+        .map(Some)
+    }
+
+    fn send(
+        &self,
+        state: &mut ProcessState,
+        method: &str,
+        params: Value,
+        retry: bool,
+        timeout: Duration,
+        search: Option<&SearchContext>,
+    ) -> Result<Value, String> {
+        let attempts = if retry { 2 } else { 1 };
+        let mut last_error = String::new();
+
+        for attempt in 0..attempts {
+            if attempt > 0 && search.is_some_and(SearchContext::is_cancelled) {
+                break;
+            }
+            self.connect(state)?;
+            state.next_id += 1;
+            let id = state.next_id;
+            let connection = state.connection.as_mut().unwrap();
+            let exchanged = self.exchange(connection, id, method, params.clone(), timeout, search);
+            let hung = match exchanged {
+                Ok(result) => return result,
+                Err(e) => {
+                    last_error = e.to_string();
+                    matches!(e, Broken::Hung(_))
+                }
+            };
+
+            eprintln!("Plugin process {} failed: {}", self.name, last_error);
+            // Dropping the connection kills whatever is left of the process
+            state.connection = None;
+            if hung {
+                break;
+            }
+        }
+
+        Err(format!(
+            "Plugin process {} failed: {}",
+            self.name, last_error
+        ))
+    }
+
+    /// Starts the process unless it is running, then configures and
+    /// initializes it.
+    fn connect(&self, state: &mut ProcessState) -> Result<(), String> {
+        if state.connection.is_some() {
+            return Ok(());
+        }
+        let mut connection = self.spawn(state)?;
+        if let Some(values) = state.config.clone() {
+            state.next_id += 1;
+            let configured = self
+                .exchange(
+                    &mut connection,
+                    state.next_id,
+                    rpc::METHOD_CONFIGURE,
+                    values,
+                    RESPONSE_TIMEOUT,
+                    None,
+                )
+                .map_err(|e| e.to_string())
+                .and_then(|result| result.map(|_| ()));
+            if let Err(e) = configured {
+                eprintln!("Failed to configure plugin process {}: {}", self.name, e);
+            }
+        }
+        if let Some(context) = state.context.clone() {
+            state.next_id += 1;
+            // Hooks are optional, a plugin rejecting one is not an error
+            let _ = self.exchange(
+                &mut connection,
+                state.next_id,
+                rpc::METHOD_INIT,
+                context,
+                RESPONSE_TIMEOUT,
+                None,
+            );
+        }
+        state.connection = Some(connection);
+        Ok(())
+    }
+
+    /// Writes one request and reads up to its response, giving up once the
+    /// process sent nothing for `timeout`. For a search, the results sent
+    /// ahead of the response go to its context, and a `cancel` notification
+    /// follows the request once the context is cancelled. A broken
+    /// connection is an `Err`, an error reported by the plugin an
+    /// `Ok(Err(_))`.
+    fn exchange(
+        &self,
+        connection: &mut Connection,
+        id: u64,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+        search: Option<&SearchContext>,
+    ) -> Result<Result<Value, String>, Broken> {
+        let request = Request::new(id, method, params);
+        self.framing
+            .write(&mut connection.stdin, &request)
+            .map_err(|e| Broken::Failed(e.to_string()))?;
+        let mut deadline = Instant::now() + timeout;
+        let mut cancel_sent = false;
+        loop {
+            if !cancel_sent && search.is_some_and(SearchContext::is_cancelled) {
+                let cancel = Notification::new(rpc::METHOD_CANCEL, json!({ "id": id }));
+                self.framing
+                    .write(&mut connection.stdin, &cancel)
+                    .map_err(|e| Broken::Failed(e.to_string()))?;
+                cancel_sent = true;
+            }
+            let mut wait = deadline.saturating_duration_since(Instant::now());
+            if search.is_some() && !cancel_sent {
+                wait = wait.min(CANCEL_POLL);
+            }
+            match connection.incoming.recv_timeout(wait) {
+                Ok(Ok(Incoming::Notification(notification))) => {
+                    deadline = Instant::now() + timeout;
+                    if let Some(context) = search {
+                        forward_results(context, notification);
+                    }
+                }
+                Ok(Ok(Incoming::Response(response))) if response.id == id => {
+                    return Ok(response.into_result())
+                }
+                Ok(Ok(_)) => {
+                    return Err(Broken::Failed(
+                        "response does not match the request".to_string(),
+                    ))
+                }
+                Ok(Err(e)) => return Err(Broken::Failed(e)),
+                Err(RecvTimeoutError::Timeout) if Instant::now() < deadline => {}
+                Err(RecvTimeoutError::Timeout) => return Err(Broken::Hung(timeout)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Broken::Failed("process exited".to_string()))
+                }
+            }
+        }
+    }
+
+    /// Sends the plugin's settings to the running process, and to every
+    /// process started from now on.
+    pub fn configure(&self, values: Value) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.config = Some(values.clone());
+        if state.connection.is_none() {
+            return Ok(());
+        }
+        self.send(
+            &mut state,
+            rpc::METHOD_CONFIGURE,
+            values,
+            true,
+            RESPONSE_TIMEOUT,
+            None,
+        )
+        .map(|_| ())
+    }
+
+    /// Initializes the running process, and every process started from now
+    /// on, with the host context.
+    pub fn init(&self, context: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.context = Some(context.clone());
+        if state.connection.is_some() {
+            let _ = self.send(
+                &mut state,
+                rpc::METHOD_INIT,
+                context,
+                false,
+                RESPONSE_TIMEOUT,
+                None,
+            );
+        }
+    }
+
+    /// Sends a lifecycle hook to the running process, if any. Hooks are
+    /// optional, so errors the plugin reports are ignored.
+    pub fn hook(&self, method: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.connection.is_some() {
+            let _ = self.send(
+                &mut state,
+                method,
+                Value::Null,
+                false,
+                RESPONSE_TIMEOUT,
+                None,
+            );
+        }
+    }
+
+    /// Lets the running process flush its state, then stops it. The next
+    /// request starts it again. A process busy with a request, which may
+    /// never return, is killed instead of waited for, failing the request.
+    pub fn shutdown(&self) {
+        let Ok(mut state) = self.state.try_lock() else {
+            if let Some(child) = self.running.lock().unwrap().as_ref() {
+                let _ = child.lock().unwrap().kill();
+            }
+            return;
+        };
+        if state.connection.is_some() {
+            let _ = self.send(
+                &mut state,
+                rpc::METHOD_SHUTDOWN,
+                Value::Null,
+                false,
+                SHUTDOWN_TIMEOUT,
+                None,
+            );
+        }
+        state.connection = None;
+    }
+}
+
+pub struct ProcessPlugin {
+    info: Plugin,
+    process: Arc<PluginProcess>,
+    // Checked copy of the library an isolated plugin runs, which every
+    // restarted process loads again
+    _library: Option<ShadowFile>,
+}
+
+impl ProcessPlugin {
+    /// Starts the process and asks it for the plugin info.
+    pub fn start(process: PluginProcess) -> Result<Self, String> {
+        let info = process.request(rpc::METHOD_INFO, Value::Null, true)?;
+        let info: Plugin = serde_json::from_value(info)
+            .map_err(|e| format!("Plugin process returned invalid info: {}", e))?;
+        Ok(Self {
+            info,
+            process: Arc::new(process),
+            _library: None,
+        })
+    }
+
+    /// Runs the plugin library copied to `library` from the plugin directory
+    /// or file `source` inside `dossier-plugin-host`, keeping the copy as
+    /// long as the plugin.
+    pub fn isolated(library: ShadowFile, source: &Path) -> Result<Self, String> {
+        let host = host_binary_path().map_err(|e| e.to_string())?;
+        let name = library
+            .path()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let process = PluginProcess::new(
+            name,
+            host,
+            vec![
+                library.path().as_os_str().to_os_string(),
+                source.as_os_str().to_os_string(),
+            ],
+            Framing::LengthPrefixed,
+        );
+        Ok(Self {
+            _library: Some(library),
+            ..Self::start(process)?
+        })
+    }
+
+    /// Runs the executable described by the manifest in `dir`, which speaks
+    /// line-delimited JSON-RPC itself.
+    pub fn stdio(dir: &Path, manifest: &Manifest) -> Result<Self, String> {
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let program = manifest
+            .program(dir)
+            .ok_or("The plugin manifest does not declare a command")?;
+        let process = PluginProcess::new(name, program, manifest.args(), Framing::Lines)
+            .current_dir(dir.to_path_buf());
+        Self::start(process)
+    }
+}
+
+#[async_trait::async_trait]
+impl PluginTrait for ProcessPlugin {
+    fn get_info(&self) -> Plugin {
+        self.info.clone()
+    }
+
+    async fn search(&self, query: &str, context: &SearchContext) -> PluginSearchResult {
+        let process = self.process.clone();
+        let params = json!({ "query": query });
+        let context = context.clone();
+        // Requests are answered one at a time, so a superseded query still
+        // waiting behind a slow one is never sent
+        let result = tokio::task::spawn_blocking(move || {
+            process
+                .search(params, &context)
+                .map(|value| value.unwrap_or_else(|| json!([])))
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))
+        .and_then(|value| serde_json::from_value(value).map_err(|e| e.to_string()));
+
+        result.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            PluginSearchResult::Results(vec![])
+        })
+    }
+
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        let params = json!({ "result_id": result_id, "action_id": action_id });
+        match self.process.request(rpc::METHOD_EXECUTE, params, false)? {
+            // Scripts may reply with no result at all
+            Value::Null => Ok(ActionOutcome::Close),
+            value => serde_json::from_value(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn configure(&self, values: &ConfigValues) {
+        let configured = serde_json::to_value(values)
+            .map_err(|e| e.to_string())
+            .and_then(|values| self.process.configure(values));
+        if let Err(e) = configured {
+            eprintln!("Failed to configure plugin {}: {}", self.info.id, e);
+        }
+    }
+
+    fn init(&self, context: &HostContext) {
+        match serde_json::to_value(context) {
+            Ok(context) => self.process.init(context),
+            Err(e) => eprintln!("Failed to initialize plugin {}: {}", self.info.id, e),
+        }
+    }
+
+    fn on_prefix_activate(&self) {
+        self.process.hook(rpc::METHOD_PREFIX_ACTIVATE)
+    }
+
+    fn on_window_shown(&self) {
+        self.process.hook(rpc::METHOD_WINDOW_SHOWN)
+    }
+
+    fn shutdown(&self) {
+        self.process.shutdown()
+    }
+}
@@ -0,0 +1,265 @@
+// JSON-RPC 2.0 messages exchanged with out-of-process plugins.
+//
+// `dossier-plugin-host` sends each message as one frame: a little-endian
+// `u32` byte length followed by that many bytes of UTF-8 JSON. Stdio plugins
+// written in other languages use one JSON message per line instead.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, BufRead, Read, Write};
+
+// Guards against a corrupted length prefix allocating unbounded memory.
+const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+pub const METHOD_INFO: &str = "info";
+pub const METHOD_SEARCH: &str = "search";
+pub const METHOD_EXECUTE: &str = "execute";
+/// Request carrying the values of the plugin's settings, sent after start
+/// and whenever they change to plugins that declare settings.
+pub const METHOD_CONFIGURE: &str = "configure";
+/// Lifecycle requests, see `PluginTrait`. `init` carries the `HostContext`
+/// and is replayed after every restart, the others carry no params.
+pub const METHOD_INIT: &str = "init";
+pub const METHOD_PREFIX_ACTIVATE: &str = "prefixActivate";
+pub const METHOD_WINDOW_SHOWN: &str = "windowShown";
+pub const METHOD_SHUTDOWN: &str = "shutdown";
+/// Notification carrying `{"results": [...]}` found so far by the search
+/// being answered.
+pub const METHOD_RESULTS: &str = "results";
+/// Notification carrying `{"id": ...}` of a search that is no longer
+/// wanted, sent while its response is pending. The plugin may stop and
+/// answer early; whatever it answers is dropped.
+pub const METHOD_CANCEL: &str = "cancel";
+
+#[derive(Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    pub id: u64,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// A message the plugin sends without expecting a reply.
+#[derive(Serialize, Deserialize)]
+pub struct Notification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl Notification {
+    pub fn new(method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// Anything the app may send to a plugin process.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Outgoing {
+    Request(Request),
+    Notification(Notification),
+}
+
+/// Anything a plugin process may send while a request is pending.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Incoming {
+    Response(Response),
+    Notification(Notification),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn new(id: u64, result: Result<Value, String>) -> Self {
+        let (result, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(message) => (
+                None,
+                Some(RpcError {
+                    code: -32000,
+                    message,
+                }),
+            ),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+
+    pub fn into_result(self) -> Result<Value, String> {
+        match self.error {
+            Some(error) => Err(error.message),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    let len = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+/// Reads the next frame, or `None` when the other side closed the stream.
+pub fn read_frame<R: Read, T: for<'de> Deserialize<'de>>(reader: &mut R) -> io::Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(serde_json::from_slice(&payload)?))
+}
+
+/// Reads the next newline-terminated message, skipping blank lines, or
+/// `None` when the other side closed the stream. A message is not buffered
+/// past the limit, whether or not its newline ever comes.
+pub fn read_line<R: BufRead, T: for<'de> Deserialize<'de>>(
+    reader: &mut R,
+) -> io::Result<Option<T>> {
+    read_line_limited(reader, MAX_FRAME_LEN)
+}
+
+fn read_line_limited<R: BufRead, T: for<'de> Deserialize<'de>>(
+    reader: &mut R,
+    limit: usize,
+) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        // The message and its newline
+        if reader
+            .by_ref()
+            .take(limit as u64 + 1)
+            .read_line(&mut line)?
+            == 0
+        {
+            return Ok(None);
+        }
+        if line.strip_suffix('\n').unwrap_or(&line).len() > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("message exceeds the limit of {} bytes", limit),
+            ));
+        }
+        if !line.trim().is_empty() {
+            return Ok(Some(serde_json::from_str(&line)?));
+        }
+    }
+}
+
+pub fn write_line<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    let mut payload = serde_json::to_vec(message)?;
+    payload.push(b'\n');
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+/// How messages are delimited on a plugin process's stdin/stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// Length-prefixed frames, used by `dossier-plugin-host`.
+    LengthPrefixed,
+    /// One JSON message per line, used by stdio plugins.
+    Lines,
+}
+
+impl Framing {
+    pub fn write<W: Write, T: Serialize>(self, writer: &mut W, message: &T) -> io::Result<()> {
+        match self {
+            Framing::LengthPrefixed => write_frame(writer, message),
+            Framing::Lines => write_line(writer, message),
+        }
+    }
+
+    pub fn read<R: BufRead, T: for<'de> Deserialize<'de>>(
+        self,
+        reader: &mut R,
+    ) -> io::Result<Option<T>> {
+        match self {
+            Framing::LengthPrefixed => read_frame(reader),
+            Framing::Lines => read_line(reader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_all(input: &str, limit: usize) -> io::Result<Vec<Value>> {
+        let mut reader = Cursor::new(input);
+        let mut messages = Vec::new();
+        while let Some(message) = read_line_limited(&mut reader, limit)? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    #[test]
+    fn reads_messages_up_to_the_limit() {
+        let messages = read_all("[1,2]\n\n  \n1234\n\"ab\"", 5).unwrap();
+        assert_eq!(
+            messages,
+            [serde_json::json!([1, 2]), 1234.into(), "ab".into()]
+        );
+    }
+
+    #[test]
+    fn rejects_messages_past_the_limit() {
+        let error = read_all("1\n[1,2,3]\n2\n", 5).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("limit of 5 bytes"));
+        // Without a newline in sight
+        assert!(read_all(&"1".repeat(100), 5).is_err());
+    }
+}
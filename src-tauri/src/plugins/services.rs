@@ -0,0 +1,250 @@
+// Services the host offers plugins, so they do not each bring their own
+// clipboard, opener, notification and HTTP code. Native libraries reach
+// them through the table handed to `dossier_plugin_set_host`, WebAssembly
+// modules through the `host_call` import; both end up in `call`.
+
+use super::permissions::{self, Caller, Capability};
+use super::storage;
+use crate::constants::APP_NAME;
+use dossier_plugin_sdk::abi::{self, RawHostServices};
+use dossier_plugin_sdk::services::{self as api, HttpRequest, HttpResponse, LogLevel};
+use serde_json::Value;
+use std::ffi::{c_char, c_void};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+// From the `proxy` setting; the environment's proxy is used when unset
+static PROXY: RwLock<Option<String>> = RwLock::new(None);
+
+// Reused while the proxy stays the same, so connections are pooled
+static AGENT: Mutex<Option<(Option<String>, ureq::Agent)>> = Mutex::new(None);
+
+pub fn set_proxy(proxy: Option<String>) {
+    *PROXY.write().unwrap() = proxy;
+}
+
+pub fn read_clipboard() -> Result<String, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| e.to_string())
+}
+
+pub fn write_clipboard(text: String) -> Result<(), String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|e| e.to_string())
+}
+
+// `C:\...` is a path, anything with a longer scheme a URL
+fn is_url(target: &str) -> bool {
+    target.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+/// Opens a URL in its default handler, or a path with its default
+/// application.
+pub fn open(target: &str) -> Result<(), String> {
+    let opened = if is_url(target) {
+        tauri_plugin_opener::open_url(target, None::<&str>)
+    } else {
+        tauri_plugin_opener::open_path(target, None::<&str>)
+    };
+    opened.map_err(|e| e.to_string())
+}
+
+pub fn notify(title: &str, body: &str) -> Result<(), String> {
+    notify_rust::Notification::new()
+        .appname(APP_NAME)
+        .summary(title)
+        .body(body)
+        .show()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Logs through `tauri-plugin-log` when it is set up, to stderr otherwise.
+pub fn log(plugin_id: &str, level: LogLevel, message: &str) {
+    let level = match level {
+        LogLevel::Error => log::Level::Error,
+        LogLevel::Warn => log::Level::Warn,
+        LogLevel::Info => log::Level::Info,
+        LogLevel::Debug => log::Level::Debug,
+        LogLevel::Trace => log::Level::Trace,
+    };
+    let target = format!("plugin:{}", plugin_id);
+    if log::log_enabled!(target: &target, level) {
+        log::log!(target: &target, level, "{}", message);
+    } else {
+        eprintln!("[{}] {}", target, message);
+    }
+}
+
+fn agent() -> Result<ureq::Agent, String> {
+    let proxy = PROXY.read().unwrap().clone();
+    let mut agent = AGENT.lock().unwrap();
+    if let Some((cached, agent)) = agent.as_ref() {
+        if *cached == proxy {
+            return Ok(agent.clone());
+        }
+    }
+    let mut builder = ureq::AgentBuilder::new().try_proxy_from_env(true);
+    if let Some(proxy) = &proxy {
+        builder = builder.proxy(ureq::Proxy::new(proxy).map_err(|e| e.to_string())?);
+    }
+    let built = builder.build();
+    *agent = Some((proxy, built.clone()));
+    Ok(built)
+}
+
+pub fn http(request: HttpRequest) -> Result<HttpResponse, String> {
+    let mut call = agent()?.request(&request.method, &request.url);
+    for (name, value) in &request.headers {
+        call = call.set(name, value);
+    }
+    let response = match request.body {
+        Some(body) => call.send_string(&body),
+        None => call.call(),
+    };
+    let response = match response {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(e.to_string()),
+    };
+    let status = response.status();
+    let headers = response
+        .headers_names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = response.header(&name)?.to_string();
+            Some((name, value))
+        })
+        .collect();
+    let body = response.into_string().map_err(|e| e.to_string())?;
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn param<'a>(params: &'a Value, name: &str) -> Result<&'a str, String> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("Missing parameter {}", name))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, String> {
+    serde_json::from_value(params).map_err(|e| format!("Invalid parameters: {}", e))
+}
+
+/// The capability a plugin needs to be granted to call `method`.
+fn capability(method: &str) -> Option<Capability> {
+    match method {
+        api::CLIPBOARD_READ | api::CLIPBOARD_WRITE => Some(Capability::Clipboard),
+        api::OPEN => Some(Capability::OpenUrl),
+        api::NOTIFY => Some(Capability::Notifications),
+        api::HTTP => Some(Capability::Network),
+        _ => None,
+    }
+}
+
+/// Runs the service `method` on behalf of plugin `caller`, if the user
+/// allowed the plugin to and its id is its own.
+pub fn call(caller: &Caller, method: &str, params: Value) -> Result<Value, String> {
+    permissions::require_owner(caller)?;
+    if let Some(capability) = capability(method) {
+        permissions::require(caller, capability)?;
+    }
+    let plugin_id = caller.id.as_str();
+    match method {
+        api::CLIPBOARD_READ => read_clipboard().map(Value::from),
+        api::CLIPBOARD_WRITE => {
+            write_clipboard(param(&params, "text")?.to_string()).map(|_| Value::Null)
+        }
+        api::OPEN => open(param(&params, "target")?).map(|_| Value::Null),
+        api::NOTIFY => {
+            notify(param(&params, "title")?, param(&params, "body")?).map(|_| Value::Null)
+        }
+        api::LOG => {
+            let level = decode(params.get("level").cloned().unwrap_or_default())?;
+            log(plugin_id, level, param(&params, "message")?);
+            Ok(Value::Null)
+        }
+        api::STORAGE_GET => {
+            storage::get(plugin_id, param(&params, "key")?).map(|value| value.unwrap_or_default())
+        }
+        api::STORAGE_SET => {
+            let value = params.get("value").cloned().unwrap_or_default();
+            storage::set(plugin_id, param(&params, "key")?, value).map(|_| Value::Null)
+        }
+        api::STORAGE_REMOVE => {
+            storage::remove(plugin_id, param(&params, "key")?).map(|_| Value::Null)
+        }
+        api::STORAGE_KEYS => storage::keys(plugin_id).map(Value::from),
+        api::STORAGE_CLEAR => storage::clear(plugin_id).map(|_| Value::Null),
+        api::HTTP => http(decode(params)?)
+            .and_then(|response| serde_json::to_value(response).map_err(|e| e.to_string())),
+        method => Err(format!("Unknown host service: {}", method)),
+    }
+}
+
+/// Tells the host which plugin calls through the table handed to a
+/// library. Must outlive the library.
+pub struct ServiceHandle {
+    source: PathBuf,
+    plugin_id: OnceLock<String>,
+}
+
+impl ServiceHandle {
+    /// A handle for the library loaded from `source`, the plugin directory
+    /// or file it was found as.
+    pub fn new(source: PathBuf) -> Self {
+        Self {
+            source,
+            plugin_id: OnceLock::new(),
+        }
+    }
+
+    /// Set once the library has told its id, calls before that cannot use
+    /// the services tied to a plugin such as storage.
+    pub fn set_plugin_id(&self, id: &str) {
+        let _ = self.plugin_id.set(id.to_string());
+    }
+
+    fn caller(&self) -> Caller {
+        let id = self.plugin_id.get().map(String::as_str).unwrap_or_default();
+        Caller::new(id, &self.source)
+    }
+
+    pub fn raw(&self) -> RawHostServices {
+        let data = self as *const ServiceHandle as *mut c_void;
+        RawHostServices::new(host_call, free_reply, data)
+    }
+}
+
+unsafe extern "C" fn host_call(
+    data: *mut c_void,
+    method: *const c_char,
+    params: *const c_char,
+) -> *mut c_char {
+    let handle = &*(data as *const ServiceHandle);
+    let method = abi::from_raw_str(method);
+    let params = abi::from_raw_str(params);
+    let result = abi::guard(
+        AssertUnwindSafe(|| {
+            let params = serde_json::from_str(&params).map_err(|e| e.to_string())?;
+            call(&handle.caller(), &method, params)
+        }),
+        || Err(format!("Host service {} failed", method)),
+    );
+    abi::to_raw_json(&result)
+}
+
+unsafe extern "C" fn free_reply(ptr: *mut c_char) {
+    abi::free_raw(ptr)
+}
@@ -0,0 +1,315 @@
+// Ed25519 signatures of plugin libraries. A library `libfoo.so` is signed
+// by `libfoo.so.sig` next to it, holding the base64 encoded signature of
+// the library's bytes. Which keys are trusted and what happens to
+// libraries that are not signed by one of them is up to the settings.
+// Plugins running a command cannot be signed and count as unsigned.
+
+use super::loader::LoadError;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+// Key release builds sign the bundled plugins with, set when building
+const BUILTIN_KEY: Option<&str> = option_env!("DOSSIER_PLUGIN_SIGNING_KEY");
+const BUILTIN_KEY_NAME: &str = "Dossier";
+
+/// What happens to libraries without a valid signature by a trusted key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePolicy {
+    /// Signatures are not looked at.
+    Off,
+    /// They load, and are reported.
+    #[default]
+    Warn,
+    /// They are refused.
+    Enforce,
+}
+
+/// A public key whose signatures are trusted, as its base64 encoded 32
+/// bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedKey {
+    pub name: String,
+    pub key: String,
+}
+
+/// The `signatures` settings.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SignatureSettings {
+    pub policy: SignaturePolicy,
+    pub trusted_keys: Vec<TrustedKey>,
+}
+
+pub fn parse_key(key: &str) -> Result<VerifyingKey, String> {
+    let bytes = BASE64
+        .decode(key.trim())
+        .map_err(|e| format!("{:?} is not base64: {}", key, e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| format!("{:?} is not a 32 byte Ed25519 public key", key))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("{:?} is not a valid key: {}", key, e))
+}
+
+/// Where the signature of `library` is looked for.
+pub fn signature_path(library: &Path) -> PathBuf {
+    let mut path = OsString::from(library.as_os_str());
+    path.push(".");
+    path.push(SIGNATURE_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Outcome of checking the signature of a library.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum SignatureStatus {
+    /// The policy is off.
+    NotChecked,
+    Signed {
+        signer: String,
+    },
+    /// There is no signature file.
+    Unsigned,
+    /// There is one, but it is malformed, does not match the library or
+    /// was made with a key that is not trusted.
+    Invalid {
+        reason: String,
+    },
+}
+
+/// The keys and policy libraries are checked against.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trust {
+    policy: SignaturePolicy,
+    keys: Vec<(String, VerifyingKey)>,
+}
+
+impl Trust {
+    /// Keys that do not parse are skipped, the settings report them.
+    pub fn from_settings(settings: &SignatureSettings) -> Self {
+        let builtin = BUILTIN_KEY.map(|key| (BUILTIN_KEY_NAME, key));
+        let configured = settings
+            .trusted_keys
+            .iter()
+            .map(|trusted| (trusted.name.as_str(), trusted.key.as_str()));
+        let keys = builtin
+            .into_iter()
+            .chain(configured)
+            .filter_map(|(name, key)| Some((name.to_string(), parse_key(key).ok()?)))
+            .collect();
+        Self {
+            policy: settings.policy,
+            keys,
+        }
+    }
+
+    /// Checks `content`, the bytes of `library` that are about to be
+    /// loaded, against the signature next to `library`.
+    pub fn check(&self, library: &Path, content: &[u8]) -> SignatureStatus {
+        if self.policy == SignaturePolicy::Off {
+            return SignatureStatus::NotChecked;
+        }
+        let invalid = |reason: String| SignatureStatus::Invalid { reason };
+        let encoded = match fs::read_to_string(signature_path(library)) {
+            Ok(encoded) => encoded,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return SignatureStatus::Unsigned,
+            Err(e) => return invalid(format!("the signature cannot be read: {}", e)),
+        };
+        let signature = match BASE64.decode(encoded.trim()) {
+            Ok(bytes) => match Signature::from_slice(&bytes) {
+                Ok(signature) => signature,
+                Err(_) => return invalid("the signature is not 64 bytes long".to_string()),
+            },
+            Err(e) => return invalid(format!("the signature is not base64: {}", e)),
+        };
+        match self
+            .keys
+            .iter()
+            .find(|(_, key)| key.verify_strict(content, &signature).is_ok())
+        {
+            Some((name, _)) => SignatureStatus::Signed {
+                signer: name.clone(),
+            },
+            None => invalid("it was not made by a trusted key for this file".to_string()),
+        }
+    }
+
+    /// Status of a plugin that runs a command instead of a library. What
+    /// the command runs, interpreters and scripts included, cannot be
+    /// checked, so it counts as unsigned.
+    pub fn check_command(&self) -> SignatureStatus {
+        if self.policy == SignaturePolicy::Off {
+            SignatureStatus::NotChecked
+        } else {
+            SignatureStatus::Unsigned
+        }
+    }
+
+    /// Whether a library or command with signature `status` may be loaded
+    /// under the policy. Ones loaded without a valid signature are logged.
+    pub fn admit(&self, library: &Path, status: &SignatureStatus) -> Result<(), LoadError> {
+        let problem = match status {
+            SignatureStatus::NotChecked | SignatureStatus::Signed { .. } => return Ok(()),
+            SignatureStatus::Unsigned => "it is not signed".to_string(),
+            SignatureStatus::Invalid { reason } => format!("its signature is invalid: {}", reason),
+        };
+        if self.policy == SignaturePolicy::Enforce {
+            return Err(LoadError::Signature(problem));
+        }
+        eprintln!("Loading {:?} although {}", library, problem);
+        Ok(())
+    }
+}
+
+/// The signature check of one library, shown in the settings window.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureReport {
+    /// The library or module, or the directory of a plugin running a
+    /// command.
+    pub library: PathBuf,
+    /// Unknown for a refused library without a manifest.
+    pub plugin_id: Option<String>,
+    #[serde(flatten)]
+    pub status: SignatureStatus,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const LIBRARY: &[u8] = b"\x7fELF not really a library";
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn public_key(key: &SigningKey) -> String {
+        BASE64.encode(key.verifying_key().as_bytes())
+    }
+
+    fn trusting(policy: SignaturePolicy, key: &SigningKey) -> Trust {
+        Trust::from_settings(&SignatureSettings {
+            policy,
+            trusted_keys: vec![TrustedKey {
+                name: "Test".to_string(),
+                key: public_key(key),
+            }],
+        })
+    }
+
+    /// `LIBRARY` written to a directory of its own, with `signature` next
+    /// to it if given.
+    fn library(name: &str, signature: Option<&str>) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dossier-signature-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let library = dir.join("libtest.so");
+        fs::write(&library, LIBRARY).unwrap();
+        if let Some(signature) = signature {
+            fs::write(signature_path(&library), signature).unwrap();
+        }
+        library
+    }
+
+    fn check(trust: &Trust, name: &str, signature: Option<&str>) -> SignatureStatus {
+        let library = library(name, signature);
+        let status = trust.check(&library, LIBRARY);
+        fs::remove_dir_all(library.parent().unwrap()).unwrap();
+        status
+    }
+
+    fn is_invalid(status: &SignatureStatus) -> bool {
+        matches!(status, SignatureStatus::Invalid { .. })
+    }
+
+    #[test]
+    fn parses_base64_encoded_public_keys() {
+        let key = public_key(&signing_key(1));
+        assert!(parse_key(&format!(" {}\n", key)).is_ok());
+        assert!(parse_key("not base64!").is_err());
+        assert!(parse_key(&BASE64.encode([1; 16])).is_err());
+    }
+
+    #[test]
+    fn accepts_a_signature_by_a_trusted_key() {
+        let key = signing_key(1);
+        let signature = BASE64.encode(key.sign(LIBRARY).to_bytes());
+        assert_eq!(
+            check(
+                &trusting(SignaturePolicy::Enforce, &key),
+                "signed",
+                Some(&signature)
+            ),
+            SignatureStatus::Signed {
+                signer: "Test".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn refuses_signatures_of_other_content_or_by_other_keys() {
+        let key = signing_key(1);
+        let trust = trusting(SignaturePolicy::Enforce, &key);
+
+        let other_content = BASE64.encode(key.sign(b"another library").to_bytes());
+        assert!(is_invalid(&check(&trust, "content", Some(&other_content))));
+
+        let other_key = BASE64.encode(signing_key(2).sign(LIBRARY).to_bytes());
+        assert!(is_invalid(&check(&trust, "key", Some(&other_key))));
+
+        assert!(is_invalid(&check(&trust, "base64", Some("not base64!"))));
+        let short = BASE64.encode([0; 32]);
+        assert!(is_invalid(&check(&trust, "short", Some(&short))));
+    }
+
+    #[test]
+    fn reports_libraries_without_a_signature() {
+        let trust = trusting(SignaturePolicy::Warn, &signing_key(1));
+        assert_eq!(check(&trust, "unsigned", None), SignatureStatus::Unsigned);
+        assert_eq!(trust.check_command(), SignatureStatus::Unsigned);
+    }
+
+    #[test]
+    fn checks_nothing_when_off() {
+        let trust = trusting(SignaturePolicy::Off, &signing_key(1));
+        assert_eq!(
+            check(&trust, "off", Some("not base64!")),
+            SignatureStatus::NotChecked
+        );
+        assert_eq!(trust.check_command(), SignatureStatus::NotChecked);
+    }
+
+    #[test]
+    fn refuses_unsigned_libraries_only_when_enforced() {
+        let library = Path::new("libtest.so");
+        let invalid = SignatureStatus::Invalid {
+            reason: "it was not made by a trusted key for this file".to_string(),
+        };
+        let signed = SignatureStatus::Signed {
+            signer: "Test".to_string(),
+        };
+
+        let warn = trusting(SignaturePolicy::Warn, &signing_key(1));
+        for status in [&SignatureStatus::Unsigned, &invalid, &signed] {
+            assert!(warn.admit(library, status).is_ok());
+        }
+        let enforce = trusting(SignaturePolicy::Enforce, &signing_key(1));
+        for status in [&SignatureStatus::Unsigned, &invalid] {
+            assert!(matches!(
+                enforce.admit(library, status),
+                Err(LoadError::Signature(_))
+            ));
+        }
+        assert!(enforce.admit(library, &signed).is_ok());
+    }
+}
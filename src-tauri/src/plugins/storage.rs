@@ -0,0 +1,93 @@
+// Key-value storage of each plugin, one JSON object per plugin, so plugins
+// do not each invent a file format and a place to keep their state.
+
+use super::is_file_name;
+use crate::constants::get_plugin_storage_path;
+use crate::files::write_atomically;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Most a plugin may keep, measured as the size of its file.
+pub const QUOTA_BYTES: usize = 1024 * 1024;
+
+// Every change rewrites the whole file, one at a time
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn path(plugin_id: &str) -> Result<PathBuf, String> {
+    if !is_file_name(plugin_id) {
+        return Err(format!("{:?} cannot have storage", plugin_id));
+    }
+    Ok(get_plugin_storage_path(plugin_id))
+}
+
+fn read(plugin_id: &str) -> Result<Map<String, Value>, String> {
+    let path = path(plugin_id)?;
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Unreadable storage {}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Map::new()),
+        Err(e) => Err(format!("Failed to read storage file: {}", e)),
+    }
+}
+
+/// Saves `entries` unless that takes the plugin over its quota. Changes
+/// that shrink the storage are always saved, so a plugin over its quota
+/// can still clean up.
+fn save(plugin_id: &str, entries: &Map<String, Value>) -> Result<(), String> {
+    let path = path(plugin_id)?;
+    let content = serde_json::to_string(entries)
+        .map_err(|e| format!("Failed to serialize storage: {}", e))?;
+    if content.len() > QUOTA_BYTES {
+        let current = fs::metadata(&path).map(|m| m.len() as usize).unwrap_or(0);
+        if content.len() > current {
+            return Err(format!(
+                "Storage of {} would take {} bytes, over its quota of {} bytes",
+                plugin_id,
+                content.len(),
+                QUOTA_BYTES
+            ));
+        }
+    }
+    write_atomically(&path, content.as_bytes())
+        .map_err(|e| format!("Failed to write storage file: {}", e))
+}
+
+pub fn get(plugin_id: &str, key: &str) -> Result<Option<Value>, String> {
+    let _lock = LOCK.lock().unwrap();
+    Ok(read(plugin_id)?.remove(key))
+}
+
+pub fn set(plugin_id: &str, key: &str, value: Value) -> Result<(), String> {
+    let _lock = LOCK.lock().unwrap();
+    let mut entries = read(plugin_id)?;
+    entries.insert(key.to_string(), value);
+    save(plugin_id, &entries)
+}
+
+pub fn remove(plugin_id: &str, key: &str) -> Result<(), String> {
+    let _lock = LOCK.lock().unwrap();
+    let mut entries = read(plugin_id)?;
+    if entries.remove(key).is_some() {
+        save(plugin_id, &entries)?;
+    }
+    Ok(())
+}
+
+pub fn keys(plugin_id: &str) -> Result<Vec<String>, String> {
+    let _lock = LOCK.lock().unwrap();
+    Ok(read(plugin_id)?.into_iter().map(|(key, _)| key).collect())
+}
+
+/// Forgets everything the plugin stored.
+pub fn clear(plugin_id: &str) -> Result<(), String> {
+    let _lock = LOCK.lock().unwrap();
+    match fs::remove_file(path(plugin_id)?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(format!("Failed to remove storage file: {}", e))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,578 @@
+// WebAssembly plugins, run by wasmtime in a sandbox that only exposes the
+// capabilities the user granted them. A trap (panic, runaway loop, memory
+// exhaustion) fails the current call and the module is instantiated afresh
+// for the next one.
+
+use super::loader::LoadError;
+use super::permissions::{Caller, Capability, Grants};
+use super::services;
+use super::*;
+use dossier_plugin_sdk::abi;
+use dossier_plugin_sdk::services as api;
+use dossier_plugin_sdk::wasm::{self as wasm_abi, pack, unpack};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use wasmtime::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+
+const MAX_MEMORY: usize = 256 * 1024 * 1024;
+// Around a second of work; a search stuck in a loop traps instead of hanging
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+
+/// WASI blocks on a tokio runtime internally, which panics on an async
+/// worker thread, so calls that may come from one get a thread of their own.
+fn on_own_thread<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(f)
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Engine::new(&config).expect("Failed to create the WebAssembly engine")
+    })
+}
+
+struct HostState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    caller: Caller,
+    grants: Grants,
+    // The search being run, if any
+    search: Option<SearchContext>,
+}
+
+fn read_clipboard(grants: &Grants) -> Result<String, String> {
+    grants.require(Capability::Clipboard)?;
+    services::read_clipboard()
+}
+
+fn write_clipboard(grants: &Grants, text: String) -> Result<String, String> {
+    grants.require(Capability::Clipboard)?;
+    services::write_clipboard(text).map(|_| String::new())
+}
+
+fn open_url(grants: &Grants, url: &str) -> Result<String, String> {
+    grants.require(Capability::OpenUrl)?;
+    // Anything else (file:, paths, custom schemes) could launch arbitrary
+    // programs
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!("Only http and https URLs can be opened: {}", url));
+    }
+    services::open(url).map(|_| String::new())
+}
+
+fn http_get(grants: &Grants, url: String) -> Result<String, String> {
+    grants.require(Capability::Network)?;
+    let response = services::http(api::HttpRequest::get(&url))?;
+    if !(200..300).contains(&response.status) {
+        return Err(format!("{} returned status {}", url, response.status));
+    }
+    Ok(response.body)
+}
+
+/// Runs a host service for the module, which checks its grants. Modules
+/// can only open web pages.
+fn host_call(state: &HostState, method: &str, params: Value) -> Result<Value, String> {
+    if method == api::OPEN {
+        let target = params.get("target").and_then(Value::as_str);
+        return open_url(&state.grants, target.unwrap_or_default()).map(|_| Value::Null);
+    }
+    services::call(&state.caller, method, params)
+}
+
+fn guest_memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+    match caller.get_export(wasm_abi::MEMORY_EXPORT) {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmtime::Error::msg("plugin does not export its memory")),
+    }
+}
+
+fn read_guest_str(
+    caller: &mut Caller<'_, HostState>,
+    ptr: u32,
+    len: u32,
+) -> wasmtime::Result<String> {
+    let mut bytes = vec![0u8; len as usize];
+    guest_memory(caller)?.read(&caller, ptr as usize, &mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Copies the JSON encoded reply into a buffer allocated by the guest.
+fn reply<T: Serialize>(
+    caller: &mut Caller<'_, HostState>,
+    result: Result<T, String>,
+) -> wasmtime::Result<u64> {
+    let json = serde_json::to_vec(&result)?;
+    let alloc = caller
+        .get_export(wasm_abi::ALLOC_EXPORT)
+        .and_then(Extern::into_func)
+        .ok_or_else(|| wasmtime::Error::msg("plugin does not export dossier_alloc"))?
+        .typed::<u32, u32>(&caller)?;
+    let ptr = alloc.call(&mut *caller, json.len() as u32)?;
+    guest_memory(caller)?.write(&mut *caller, ptr as usize, &json)?;
+    Ok(pack(ptr, json.len() as u32))
+}
+
+fn add_host_functions(linker: &mut Linker<HostState>) -> wasmtime::Result<()> {
+    let module = wasm_abi::HOST_MODULE;
+    linker.func_wrap(
+        module,
+        wasm_abi::CLIPBOARD_READ_IMPORT,
+        |mut caller: Caller<'_, HostState>| {
+            let result = read_clipboard(&caller.data().grants);
+            reply(&mut caller, result)
+        },
+    )?;
+    linker.func_wrap(
+        module,
+        wasm_abi::CLIPBOARD_WRITE_IMPORT,
+        |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| {
+            let text = read_guest_str(&mut caller, ptr, len)?;
+            let result = write_clipboard(&caller.data().grants, text);
+            reply(&mut caller, result)
+        },
+    )?;
+    linker.func_wrap(
+        module,
+        wasm_abi::OPEN_URL_IMPORT,
+        |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| {
+            let url = read_guest_str(&mut caller, ptr, len)?;
+            let result = open_url(&caller.data().grants, &url);
+            reply(&mut caller, result)
+        },
+    )?;
+    linker.func_wrap(
+        module,
+        wasm_abi::HTTP_GET_IMPORT,
+        |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| {
+            let url = read_guest_str(&mut caller, ptr, len)?;
+            let result = http_get(&caller.data().grants, url);
+            reply(&mut caller, result)
+        },
+    )?;
+    linker.func_wrap(
+        module,
+        wasm_abi::HOST_CALL_IMPORT,
+        |mut caller: Caller<'_, HostState>,
+         method_ptr: u32,
+         method_len: u32,
+         params_ptr: u32,
+         params_len: u32| {
+            let method = read_guest_str(&mut caller, method_ptr, method_len)?;
+            let params = read_guest_str(&mut caller, params_ptr, params_len)?;
+            let result = serde_json::from_str(&params)
+                .map_err(|e| e.to_string())
+                .and_then(|params| host_call(caller.data(), &method, params));
+            reply(&mut caller, result)
+        },
+    )?;
+    linker.func_wrap(
+        module,
+        wasm_abi::SEARCH_CANCELLED_IMPORT,
+        |caller: Caller<'_, HostState>| {
+            let cancelled = caller
+                .data()
+                .search
+                .as_ref()
+                .is_some_and(SearchContext::is_cancelled);
+            u32::from(cancelled)
+        },
+    )?;
+    linker.func_wrap(
+        module,
+        wasm_abi::EMIT_RESULTS_IMPORT,
+        |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| -> wasmtime::Result<()> {
+            let batch = read_guest_str(&mut caller, ptr, len)?;
+            if let Some(context) = &caller.data().search {
+                match serde_json::from_str(&batch) {
+                    Ok(results) => context.emit(results),
+                    Err(e) => eprintln!("Plugin emitted malformed results: {}", e),
+                }
+            }
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+fn typed_export<Params, Results>(
+    store: &mut Store<HostState>,
+    instance: &Instance,
+    name: &str,
+) -> Result<TypedFunc<Params, Results>, LoadError>
+where
+    Params: wasmtime::WasmParams,
+    Results: wasmtime::WasmResults,
+{
+    instance
+        .get_typed_func(&mut *store, name)
+        .map_err(|_| LoadError::MissingSymbol(name.to_string()))
+}
+
+fn export_name(symbol: &[u8]) -> &str {
+    std::str::from_utf8(symbol).unwrap_or_default()
+}
+
+/// One instantiation of a plugin module.
+struct Sandbox {
+    store: Store<HostState>,
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+    free: TypedFunc<(u32, u32), ()>,
+    info: TypedFunc<(), u64>,
+    search: TypedFunc<(u32, u32), u64>,
+    execute: TypedFunc<(u32, u32, u32, u32), u64>,
+    // Only exported by plugins built since settings were added
+    configure: Option<TypedFunc<(u32, u32), ()>>,
+    // Only exported by plugins built since lifecycle hooks were added
+    init: Option<TypedFunc<(u32, u32), ()>>,
+    prefix_activate: Option<TypedFunc<(), ()>>,
+    window_shown: Option<TypedFunc<(), ()>>,
+    shutdown: Option<TypedFunc<(), ()>>,
+}
+
+impl Sandbox {
+    fn new(module: &Module, caller: &Caller, grants: &Grants) -> Result<Self, LoadError> {
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.inherit_stderr();
+        for (paths, dir_perms, file_perms) in [
+            (&grants.read_paths, DirPerms::READ, FilePerms::READ),
+            (&grants.write_paths, DirPerms::all(), FilePerms::all()),
+        ] {
+            for path in paths {
+                // Mounted at its real location so paths mean the same inside and out
+                let guest_path = path.to_string_lossy();
+                if let Err(e) = wasi.preopened_dir(path, &guest_path, dir_perms, file_perms) {
+                    eprintln!("Failed to grant access to {:?}: {}", path, e);
+                }
+            }
+        }
+
+        let state = HostState {
+            wasi: wasi.build_p1(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+            caller: caller.clone(),
+            grants: grants.clone(),
+            search: None,
+        };
+        let mut store = Store::new(engine(), state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+
+        let mut linker = Linker::new(engine());
+        preview1::add_to_linker_sync(&mut linker, |state: &mut HostState| &mut state.wasi)?;
+        add_host_functions(&mut linker)?;
+        let instance = linker.instantiate(&mut store, module)?;
+
+        let abi_version: TypedFunc<(), u32> =
+            typed_export(&mut store, &instance, export_name(abi::ABI_VERSION_SYMBOL))?;
+        let found = abi_version.call(&mut store, ())?;
+        if found != abi::ABI_VERSION {
+            return Err(LoadError::AbiMismatch {
+                found,
+                expected: abi::ABI_VERSION,
+            });
+        }
+
+        let memory = instance
+            .get_memory(&mut store, wasm_abi::MEMORY_EXPORT)
+            .ok_or_else(|| LoadError::MissingSymbol(wasm_abi::MEMORY_EXPORT.to_string()))?;
+        Ok(Self {
+            memory,
+            alloc: typed_export(&mut store, &instance, wasm_abi::ALLOC_EXPORT)?,
+            free: typed_export(&mut store, &instance, wasm_abi::FREE_EXPORT)?,
+            info: typed_export(&mut store, &instance, export_name(abi::INFO_SYMBOL))?,
+            search: typed_export(&mut store, &instance, export_name(abi::SEARCH_SYMBOL))?,
+            execute: typed_export(&mut store, &instance, export_name(abi::EXECUTE_SYMBOL))?,
+            configure: typed_export(&mut store, &instance, export_name(abi::CONFIGURE_SYMBOL)).ok(),
+            init: typed_export(&mut store, &instance, export_name(abi::INIT_SYMBOL)).ok(),
+            prefix_activate: typed_export(
+                &mut store,
+                &instance,
+                export_name(abi::PREFIX_ACTIVATE_SYMBOL),
+            )
+            .ok(),
+            window_shown: typed_export(
+                &mut store,
+                &instance,
+                export_name(abi::WINDOW_SHOWN_SYMBOL),
+            )
+            .ok(),
+            shutdown: typed_export(&mut store, &instance, export_name(abi::SHUTDOWN_SYMBOL)).ok(),
+            store,
+        })
+    }
+
+    fn write_str(&mut self, value: &str) -> wasmtime::Result<(u32, u32)> {
+        let len = value.len() as u32;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as usize, value.as_bytes())?;
+        Ok((ptr, len))
+    }
+
+    fn take_str(&mut self, packed: u64) -> wasmtime::Result<String> {
+        let (ptr, len) = unpack(packed);
+        let mut bytes = vec![0u8; len as usize];
+        self.memory.read(&self.store, ptr as usize, &mut bytes)?;
+        self.free.call(&mut self.store, (ptr, len))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn info(&mut self) -> wasmtime::Result<String> {
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        let packed = self.info.call(&mut self.store, ())?;
+        self.take_str(packed)
+    }
+
+    fn search(&mut self, query: &str, context: &SearchContext) -> wasmtime::Result<String> {
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        self.store.data_mut().search = Some(context.clone());
+        let result = self.write_str(query).and_then(|(ptr, len)| {
+            let packed = self.search.call(&mut self.store, (ptr, len))?;
+            self.free.call(&mut self.store, (ptr, len))?;
+            self.take_str(packed)
+        });
+        self.store.data_mut().search = None;
+        result
+    }
+
+    fn execute(&mut self, result_id: &str, action_id: &str) -> wasmtime::Result<String> {
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        let (result_ptr, result_len) = self.write_str(result_id)?;
+        let (action_ptr, action_len) = self.write_str(action_id)?;
+        let packed = self.execute.call(
+            &mut self.store,
+            (result_ptr, result_len, action_ptr, action_len),
+        )?;
+        self.free.call(&mut self.store, (result_ptr, result_len))?;
+        self.free.call(&mut self.store, (action_ptr, action_len))?;
+        self.take_str(packed)
+    }
+
+    /// Passes `value` to an optional export taking a string, doing nothing
+    /// if the plugin does not export it.
+    fn call_with_str(
+        &mut self,
+        func: Option<TypedFunc<(u32, u32), ()>>,
+        value: &str,
+    ) -> wasmtime::Result<String> {
+        let Some(func) = func else {
+            return Ok(String::new());
+        };
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        let (ptr, len) = self.write_str(value)?;
+        func.call(&mut self.store, (ptr, len))?;
+        self.free.call(&mut self.store, (ptr, len))?;
+        Ok(String::new())
+    }
+
+    fn configure(&mut self, values: &str) -> wasmtime::Result<String> {
+        self.call_with_str(self.configure.clone(), values)
+    }
+
+    fn init(&mut self, context: &str) -> wasmtime::Result<String> {
+        self.call_with_str(self.init.clone(), context)
+    }
+
+    fn hook(&mut self, hook: Option<TypedFunc<(), ()>>) -> wasmtime::Result<String> {
+        if let Some(hook) = hook {
+            self.store.set_fuel(FUEL_PER_CALL)?;
+            hook.call(&mut self.store, ())?;
+        }
+        Ok(String::new())
+    }
+}
+
+struct WasmRuntime {
+    module: Module,
+    caller: Caller,
+    grants: Grants,
+    sandbox: Mutex<Option<Sandbox>>,
+    // JSON encoded settings and host context, handed to every new instance
+    config: Mutex<Option<String>>,
+    context: Mutex<Option<String>>,
+}
+
+impl WasmRuntime {
+    /// Runs `f` against the current instance, instantiating the module first
+    /// if the previous instance trapped.
+    fn call(
+        &self,
+        f: impl FnOnce(&mut Sandbox) -> wasmtime::Result<String>,
+    ) -> Result<String, String> {
+        let mut sandbox = self.sandbox.lock().unwrap();
+        if sandbox.is_none() {
+            let mut fresh = Sandbox::new(&self.module, &self.caller, &self.grants)
+                .map_err(|e| e.to_string())?;
+            if let Some(values) = self.config.lock().unwrap().as_deref() {
+                if let Err(e) = fresh.configure(values) {
+                    eprintln!("Failed to configure WebAssembly plugin: {:#}", e);
+                }
+            }
+            if let Some(context) = self.context.lock().unwrap().as_deref() {
+                if let Err(e) = fresh.init(context) {
+                    eprintln!("Failed to initialize WebAssembly plugin: {:#}", e);
+                }
+            }
+            *sandbox = Some(fresh);
+        }
+        let result = f(sandbox.as_mut().unwrap());
+        if result.is_err() {
+            // A trap can leave the instance's memory in any state
+            *sandbox = None;
+        }
+        result.map_err(|e| format!("WebAssembly plugin failed: {:#}", e))
+    }
+
+    fn configure(&self, values: String) -> Result<(), String> {
+        *self.config.lock().unwrap() = Some(values.clone());
+        // Otherwise the next call configures the instance it creates
+        if self.sandbox.lock().unwrap().is_none() {
+            return Ok(());
+        }
+        self.call(|s| s.configure(&values)).map(|_| ())
+    }
+
+    fn init(&self, context: String) -> Result<(), String> {
+        *self.context.lock().unwrap() = Some(context.clone());
+        // Instantiating the module initializes it, so the plugin gets to warm
+        // up now rather than on the first search
+        if self.sandbox.lock().unwrap().is_none() {
+            return self.call(|_| Ok(String::new())).map(|_| ());
+        }
+        self.call(|s| s.init(&context)).map(|_| ())
+    }
+
+    /// Runs a hook in the current instance. Nothing is instantiated for it,
+    /// a fresh instance has nothing to flush or warm up beyond `init`.
+    fn hook(&self, hook: impl FnOnce(&Sandbox) -> Option<TypedFunc<(), ()>>) {
+        if self.sandbox.lock().unwrap().is_none() {
+            return;
+        }
+        if let Err(e) = self.call(|s| s.hook(hook(s))) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+pub struct WasmPlugin {
+    info: Plugin,
+    runtime: Arc<WasmRuntime>,
+}
+
+impl WasmPlugin {
+    /// Compiles the module `content`, found as `source`, and instantiates
+    /// it with the grants `grants_for` returns for the plugin's id and
+    /// `source`. A module claiming the id of a plugin loaded from elsewhere
+    /// gets none.
+    pub fn load(
+        content: &[u8],
+        source: &Path,
+        grants_for: impl FnOnce(&Caller) -> Grants,
+    ) -> Result<Self, LoadError> {
+        let module = Module::new(engine(), content)?;
+
+        // The info comes from an instance without any capability or
+        // storage, the plugin's grants are only known once it has told us
+        // its id
+        let (mut probe, info_json) = on_own_thread(|| -> Result<_, LoadError> {
+            let mut probe = Sandbox::new(&module, &Caller::new("", source), &Grants::default())?;
+            let info_json = probe.info()?;
+            Ok((probe, info_json))
+        })?;
+        let info: Plugin =
+            serde_json::from_str(&info_json).map_err(|e| LoadError::InvalidInfo(e.to_string()))?;
+
+        let caller = Caller::new(&info.id, source);
+        let grants = grants_for(&caller);
+        let sandbox = if grants == Grants::default() {
+            probe.store.data_mut().caller = caller.clone();
+            Some(probe)
+        } else {
+            None
+        };
+        Ok(Self {
+            runtime: Arc::new(WasmRuntime {
+                module,
+                caller,
+                grants,
+                sandbox: Mutex::new(sandbox),
+                config: Mutex::new(None),
+                context: Mutex::new(None),
+            }),
+            info,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PluginTrait for WasmPlugin {
+    fn get_info(&self) -> Plugin {
+        self.info.clone()
+    }
+
+    async fn search(&self, query: &str, context: &SearchContext) -> PluginSearchResult {
+        let runtime = self.runtime.clone();
+        let query = query.to_string();
+        let context = context.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            if context.is_cancelled() {
+                return Ok("[]".to_string());
+            }
+            runtime.call(|s| s.search(&query, &context))
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()));
+
+        result.unwrap_or_else(|e| {
+            eprintln!("Plugin {} search failed: {}", self.info.id, e);
+            PluginSearchResult::Results(vec![])
+        })
+    }
+
+    fn execute_action(&self, result_id: &str, action_id: &str) -> Result<ActionOutcome, String> {
+        let json = on_own_thread(|| self.runtime.call(|s| s.execute(result_id, action_id)))?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())?
+    }
+
+    fn configure(&self, values: &ConfigValues) {
+        let values = serde_json::to_string(values).unwrap_or_default();
+        if let Err(e) = on_own_thread(|| self.runtime.configure(values)) {
+            eprintln!("Failed to configure plugin {}: {}", self.info.id, e);
+        }
+    }
+
+    fn init(&self, context: &HostContext) {
+        let context = serde_json::to_string(context).unwrap_or_default();
+        if let Err(e) = on_own_thread(|| self.runtime.init(context)) {
+            eprintln!("Failed to initialize plugin {}: {}", self.info.id, e);
+        }
+    }
+
+    fn on_prefix_activate(&self) {
+        on_own_thread(|| self.runtime.hook(|s| s.prefix_activate.clone()))
+    }
+
+    fn on_window_shown(&self) {
+        on_own_thread(|| self.runtime.hook(|s| s.window_shown.clone()))
+    }
+
+    fn shutdown(&self) {
+        on_own_thread(|| self.runtime.hook(|s| s.shutdown.clone()))
+    }
+}
@@ -0,0 +1,74 @@
+use super::loader::plugin_source;
+use crate::commands::default::{get_loader, save_plugin_sources};
+use crate::constants::get_plugins_dir;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+// A build writes the library in several steps, wait for them to settle
+// before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the plugins directory and hot reloads libraries and stdio plugins
+/// that are added, changed or removed, then emits `plugins-changed` with the
+/// new plugin list.
+pub fn watch_plugins_dir(app: AppHandle) -> notify::Result<()> {
+    let dir = get_plugins_dir();
+    std::fs::create_dir_all(&dir)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+    std::thread::spawn(move || {
+        // Owned by the thread so the watcher lives as long as the app
+        let _watcher = watcher;
+        let mut changed: HashSet<PathBuf> = HashSet::new();
+
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(Ok(event)) => {
+                    // Reads (including our own shadow copies) must not trigger a reload
+                    let relevant =
+                        matches!(
+                            event.kind,
+                            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_)
+                        ) && !matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)));
+                    if relevant {
+                        changed.extend(event.paths.iter().filter_map(|p| plugin_source(&dir, p)));
+                    }
+                }
+                Ok(Err(e)) => eprintln!("Plugin watcher error: {}", e),
+                Err(RecvTimeoutError::Timeout) => {
+                    if !changed.is_empty() {
+                        reload_changed(&app, changed.drain().collect());
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn reload_changed(app: &AppHandle, paths: Vec<PathBuf>) {
+    let plugins = {
+        let mut loader = get_loader().write().unwrap();
+        for path in paths {
+            if let Err(e) = loader.reload_plugin(&path) {
+                eprintln!("Failed to reload plugin {:?}: {}", path, e);
+            }
+        }
+        loader.list_plugins()
+    };
+    save_plugin_sources(app);
+
+    if let Err(e) = app.emit("plugins-changed", plugins) {
+        eprintln!("Failed to emit plugins-changed event: {}", e);
+    }
+}
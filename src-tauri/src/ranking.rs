@@ -0,0 +1,170 @@
+// Orders the results of several plugins searched at once into one list.
+
+use crate::history;
+use crate::plugins::PluginResult;
+use dossier_plugin_sdk::matcher::fuzzy_match;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+/// A result together with the plugin that produced it, so its actions are
+/// run by the right plugin even when two plugins use the same result ids.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaggedResult {
+    pub plugin_id: String,
+    #[serde(flatten)]
+    pub result: PluginResult,
+}
+
+/// Fills in the score and title highlights of a result the plugin did not
+/// score itself. Results matching neither by title nor subtitle (a
+/// calculation, a web search) stay unscored and rank after the matches.
+pub fn annotate(query: &str, result: &mut PluginResult) {
+    if query.trim().is_empty() {
+        return;
+    }
+    if let Some(m) = fuzzy_match(query, &result.title) {
+        result.score.get_or_insert(m.score);
+        result.highlights.get_or_insert(m.ranges);
+    } else if let Some(m) = result
+        .subtitle
+        .as_deref()
+        .and_then(|subtitle| fuzzy_match(query, subtitle))
+    {
+        result.score.get_or_insert(m.score / 2);
+    }
+}
+
+/// Moves results picked before for similar queries to the top of a single
+/// plugin's results, most used and most recent first. The others keep the
+/// order the plugin chose.
+pub fn promote(query: &str, plugin_id: &str, results: &mut [PluginResult]) {
+    let frecencies = history::frecencies(query);
+    if frecencies.is_empty() {
+        return;
+    }
+    results.sort_by_cached_key(|result| {
+        let key = (plugin_id.to_string(), result.id.clone());
+        Reverse(frecencies.get(&key).copied().unwrap_or(0))
+    });
+}
+
+/// Sorts `results` by score, boosted by how often and how recently each was
+/// picked for similar queries, and drops duplicates: the same result reported
+/// twice by a plugin, or the same title and subtitle from several plugins, of
+/// which the best ranked one is kept. Equally relevant results keep their
+/// order. `frecencies` are those of [`history::frecencies`] for `query`.
+pub fn rank(
+    query: &str,
+    mut results: Vec<TaggedResult>,
+    frecencies: &HashMap<(String, String), u32>,
+) -> Vec<TaggedResult> {
+    for tagged in &mut results {
+        annotate(query, &mut tagged.result);
+    }
+    results.sort_by_cached_key(|tagged| {
+        let key = (tagged.plugin_id.clone(), tagged.result.id.clone());
+        let frecency = frecencies.get(&key).copied().unwrap_or(0);
+        Reverse(tagged.result.score.unwrap_or(0) + frecency)
+    });
+
+    let mut seen_ids = HashSet::new();
+    let mut seen_items = HashSet::new();
+    results
+        .into_iter()
+        .filter(|tagged| {
+            let id = (tagged.plugin_id.clone(), tagged.result.id.clone());
+            let item = (tagged.result.title.clone(), tagged.result.subtitle.clone());
+            seen_ids.insert(id) && seen_items.insert(item)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(plugin_id: &str, id: &str, title: &str, score: u32) -> TaggedResult {
+        TaggedResult {
+            plugin_id: plugin_id.to_string(),
+            result: PluginResult {
+                id: id.to_string(),
+                title: title.to_string(),
+                subtitle: None,
+                icon: None,
+                actions: None,
+                score: Some(score),
+                highlights: None,
+            },
+        }
+    }
+
+    fn ids(results: &[TaggedResult]) -> Vec<(&str, &str)> {
+        results
+            .iter()
+            .map(|tagged| (tagged.plugin_id.as_str(), tagged.result.id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn drops_results_reported_twice_by_a_plugin() {
+        let results = vec![
+            result("files", "a", "Notes", 10),
+            result("files", "a", "Notes again", 50),
+            result("apps", "a", "Notepad", 20),
+        ];
+        let ranked = rank("", results, &HashMap::new());
+        assert_eq!(ids(&ranked), [("files", "a"), ("apps", "a")]);
+        assert_eq!(ranked[0].result.title, "Notes again");
+    }
+
+    #[test]
+    fn keeps_the_best_ranked_of_the_same_item_from_several_plugins() {
+        let mut from_apps = result("apps", "firefox", "Firefox", 10);
+        let mut from_files = result("files", "/usr/bin/firefox", "Firefox", 30);
+        from_apps.result.subtitle = Some("/usr/bin/firefox".to_string());
+        from_files.result.subtitle = Some("/usr/bin/firefox".to_string());
+        let other = result("files", "/usr/bin/firefox.sh", "Firefox", 20);
+
+        let ranked = rank("", vec![from_apps, from_files, other], &HashMap::new());
+        assert_eq!(
+            ids(&ranked),
+            [
+                ("files", "/usr/bin/firefox"),
+                ("files", "/usr/bin/firefox.sh")
+            ]
+        );
+    }
+
+    #[test]
+    fn boosts_results_picked_before() {
+        let results = vec![
+            result("apps", "code", "Code", 30),
+            result("apps", "cool", "Cool Retro Term", 20),
+        ];
+        let frecencies = HashMap::from([(("apps".to_string(), "cool".to_string()), 100)]);
+        let ranked = rank("", results, &frecencies);
+        assert_eq!(ids(&ranked), [("apps", "cool"), ("apps", "code")]);
+    }
+
+    #[test]
+    fn keeps_the_order_of_ties() {
+        let results = vec![
+            result("calc", "sum", "4", 0),
+            result("google", "search", "Search the web", 0),
+            result("apps", "b", "B", 10),
+            result("apps", "a", "A", 10),
+        ];
+        let ranked = rank("", results, &HashMap::new());
+        assert_eq!(
+            ids(&ranked),
+            [
+                ("apps", "b"),
+                ("apps", "a"),
+                ("calc", "sum"),
+                ("google", "search")
+            ]
+        );
+    }
+}
@@ -0,0 +1,874 @@
+// The settings model: what `settings.json` holds, how older files are
+// upgraded and which values are accepted.
+
+pub mod watcher;
+
+use crate::constants::get_settings_path;
+use crate::files::write_atomically;
+use crate::plugins::permissions::Grants;
+use crate::plugins::signature::{self, SignatureSettings};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use tauri_plugin_global_shortcut::Shortcut;
+
+/// Upgrades settings of version `index` to `index + 1`. Files written before
+/// settings had a version are version 0.
+const MIGRATIONS: &[fn(&mut Value)] = &[move_enabled_plugins];
+
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+const MIN_SEARCH_TIMEOUT_MS: u64 = 100;
+
+// Earlier copies kept next to settings.json as settings.json.1 (the latest)
+// to settings.json.3, to recover from a file that no longer parses
+const BACKUPS: usize = 3;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub version: u32,
+    /// Opacity of the bar, from 0.1 to 1.
+    pub transparency: f64,
+    pub shortcuts: Shortcuts,
+    /// Proxy for the HTTP requests plugins make through the host, such as
+    /// `http://proxy:8080`. The environment's proxy is used if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Which plugin libraries must be signed, and by whom.
+    pub signatures: SignatureSettings,
+    pub plugins: BTreeMap<String, PluginSettings>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            transparency: 0.8,
+            shortcuts: Shortcuts::default(),
+            proxy: None,
+            signatures: SignatureSettings::default(),
+            plugins: BTreeMap::new(),
+        }
+    }
+}
+
+/// Global shortcuts, in the format of `tauri_plugin_global_shortcut`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Shortcuts {
+    pub toggle_window: String,
+    pub hide_window: String,
+    pub open_settings: String,
+}
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self {
+            toggle_window: "Ctrl+R".to_string(),
+            hide_window: "Escape".to_string(),
+            open_settings: "Ctrl+Comma".to_string(),
+        }
+    }
+}
+
+/// Options of one plugin, under `plugins.<id>`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PluginSettings {
+    pub enabled: bool,
+    /// Runs the plugin in a separate process.
+    pub isolated: bool,
+    /// Capabilities the user allowed or denied the plugin, whatever kind of
+    /// plugin it is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grants: Option<Grants>,
+    /// Plugin directory or file the plugin was first loaded from. The id,
+    /// with its grants and storage, belongs to the plugin found there; one
+    /// elsewhere claiming it is refused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// Results arriving later are dropped, 5000 if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_timeout_ms: Option<u64>,
+    /// Dangerous actions to run without asking first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skip_confirmation: Vec<String>,
+    /// Values of the settings the plugin declares itself.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub config: Map<String, Value>,
+    /// Options this version of Dossier does not know, kept as they are.
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+impl Default for PluginSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            isolated: false,
+            grants: None,
+            source: None,
+            search_timeout_ms: None,
+            skip_confirmation: Vec::new(),
+            config: Map::new(),
+            other: BTreeMap::new(),
+        }
+    }
+}
+
+/// A value that cannot be used, with the path of its field such as
+/// `shortcuts.toggleWindow`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+fn problem(field: impl Into<String>, message: impl Into<String>) -> Problem {
+    Problem {
+        field: field.into(),
+        message: message.into(),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("invalid settings: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Invalid(Vec<Problem>),
+    #[error("failed to read settings: {0}")]
+    Read(#[source] io::Error),
+    #[error("the settings file is missing, keeping the current settings until it is saved again")]
+    Missing,
+    #[error("failed to parse settings: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("settings version {0} is newer than this version of Dossier")]
+    TooNew(u32),
+    #[error("failed to write settings: {0}")]
+    Write(#[source] io::Error),
+    #[error("{0} can only be changed from the settings window")]
+    NotAllowed(&'static str),
+}
+
+impl SettingsError {
+    /// What is wrong, as problems the settings window can list.
+    pub fn problems(&self) -> Vec<Problem> {
+        match self {
+            Self::Invalid(problems) => problems.clone(),
+            _ => vec![problem("", self.to_string())],
+        }
+    }
+}
+
+// The settings window shows each problem next to its field
+impl Serialize for SettingsError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            message: String,
+            problems: &'a [Problem],
+        }
+        let problems = match self {
+            Self::Invalid(problems) => problems.as_slice(),
+            _ => &[],
+        };
+        Body {
+            message: self.to_string(),
+            problems,
+        }
+        .serialize(serializer)
+    }
+}
+
+fn valid_transparency(transparency: f64) -> bool {
+    (0.1..=1.0).contains(&transparency)
+}
+
+fn valid_proxy(proxy: &str) -> Result<(), String> {
+    ureq::Proxy::new(proxy)
+        .map(|_| ())
+        .map_err(|e| format!("{:?} is not a valid proxy: {}", proxy, e))
+}
+
+fn valid_shortcut(shortcut: &str) -> Result<(), String> {
+    Shortcut::from_str(shortcut)
+        .map(|_| ())
+        .map_err(|e| format!("{:?} is not a valid shortcut: {}", shortcut, e))
+}
+
+impl Settings {
+    /// Values that parse but cannot be used.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        if !valid_transparency(self.transparency) {
+            problems.push(problem(
+                "transparency",
+                format!("{} is not between 0.1 and 1", self.transparency),
+            ));
+        }
+        for (field, shortcut) in self.shortcuts.fields() {
+            if let Err(message) = valid_shortcut(shortcut) {
+                problems.push(problem(format!("shortcuts.{}", field), message));
+            }
+        }
+        if let Some(Err(message)) = self.proxy.as_deref().map(valid_proxy) {
+            problems.push(problem("proxy", message));
+        }
+        for trusted in &self.signatures.trusted_keys {
+            if let Err(message) = signature::parse_key(&trusted.key) {
+                problems.push(problem("signatures.trustedKeys", message));
+            }
+        }
+        for (id, plugin) in &self.plugins {
+            if let Some(timeout) = plugin.search_timeout_ms {
+                if timeout < MIN_SEARCH_TIMEOUT_MS {
+                    problems.push(problem(
+                        format!("plugins.{}.searchTimeoutMs", id),
+                        format!("must be at least {} ms", MIN_SEARCH_TIMEOUT_MS),
+                    ));
+                }
+            }
+            let grants = plugin.grants.iter();
+            for path in grants.flat_map(|g| g.read_paths.iter().chain(&g.write_paths)) {
+                if !path.is_absolute() {
+                    problems.push(problem(
+                        format!("plugins.{}.grants", id),
+                        format!("{} is not an absolute path", path.display()),
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Replaces the values [`validate`](Self::validate) rejects with their
+    /// defaults.
+    fn reset_invalid(&mut self) {
+        let defaults = Settings::default();
+        if !valid_transparency(self.transparency) {
+            self.transparency = defaults.transparency;
+        }
+        let default_shortcuts = defaults.shortcuts;
+        for (shortcut, default) in [
+            (
+                &mut self.shortcuts.toggle_window,
+                default_shortcuts.toggle_window,
+            ),
+            (
+                &mut self.shortcuts.hide_window,
+                default_shortcuts.hide_window,
+            ),
+            (
+                &mut self.shortcuts.open_settings,
+                default_shortcuts.open_settings,
+            ),
+        ] {
+            if valid_shortcut(shortcut).is_err() {
+                *shortcut = default;
+            }
+        }
+        if self
+            .proxy
+            .as_deref()
+            .is_some_and(|p| valid_proxy(p).is_err())
+        {
+            self.proxy = None;
+        }
+        self.signatures
+            .trusted_keys
+            .retain(|trusted| signature::parse_key(&trusted.key).is_ok());
+        for plugin in self.plugins.values_mut() {
+            if plugin
+                .search_timeout_ms
+                .is_some_and(|timeout| timeout < MIN_SEARCH_TIMEOUT_MS)
+            {
+                plugin.search_timeout_ms = None;
+            }
+            if let Some(grants) = &mut plugin.grants {
+                grants.read_paths.retain(|path| path.is_absolute());
+                grants.write_paths.retain(|path| path.is_absolute());
+            }
+        }
+    }
+
+    /// `self` with the grants, plugin sources and signature settings of
+    /// `current`. Those only change through their own commands, the loader
+    /// or by editing the file, not along with the rest of the settings.
+    pub fn keeping_trust_of(mut self, current: &Settings) -> Self {
+        self.signatures = current.signatures.clone();
+        for plugin in self.plugins.values_mut() {
+            plugin.grants = None;
+            plugin.source = None;
+        }
+        for (id, plugin) in &current.plugins {
+            if plugin.grants.is_some() || plugin.source.is_some() {
+                let options = self.plugins.entry(id.clone()).or_default();
+                options.grants = plugin.grants.clone();
+                options.source = plugin.source.clone();
+            }
+        }
+        self
+    }
+}
+
+impl Shortcuts {
+    fn fields(&self) -> [(&'static str, &str); 3] {
+        [
+            ("toggleWindow", &self.toggle_window),
+            ("hideWindow", &self.hide_window),
+            ("openSettings", &self.open_settings),
+        ]
+    }
+}
+
+/// Version 1 keeps everything about a plugin under `plugins.<id>`, including
+/// whether it is enabled, which used to live in `enabledPlugins`.
+fn move_enabled_plugins(settings: &mut Value) {
+    let Some(object) = settings.as_object_mut() else {
+        return;
+    };
+    let Some(Value::Object(enabled)) = object.remove("enabledPlugins") else {
+        return;
+    };
+    let plugins = object
+        .entry("plugins")
+        .or_insert_with(|| Value::Object(Default::default()));
+    let Some(plugins) = plugins.as_object_mut() else {
+        return;
+    };
+    for (id, enabled) in enabled {
+        let options = plugins
+            .entry(id)
+            .or_insert_with(|| Value::Object(Default::default()));
+        if let Some(options) = options.as_object_mut() {
+            options.insert("enabled".to_string(), enabled);
+        }
+    }
+}
+
+/// Brings `settings` up to [`CURRENT_VERSION`], returning whether anything
+/// had to be done.
+fn migrate(settings: &mut Value) -> Result<bool, SettingsError> {
+    let version = settings.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > CURRENT_VERSION {
+        return Err(SettingsError::TooNew(version));
+    }
+    for step in &MIGRATIONS[version as usize..] {
+        step(settings);
+    }
+    if let Some(object) = settings.as_object_mut() {
+        object.insert("version".to_string(), CURRENT_VERSION.into());
+    }
+    Ok(version < CURRENT_VERSION)
+}
+
+/// `path` with `suffix` appended, such as `settings.json.1`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    sibling(path, &format!(".{}", index))
+}
+
+// Backups are taken once per run, before the first save, so a session of
+// saves from the settings window keeps one copy instead of filling all of
+// them with near-identical ones
+static BACKED_UP: AtomicBool = AtomicBool::new(false);
+
+/// Shifts the backups by one and makes the current file the latest, unless
+/// it is not even JSON, which would push a good copy out, or is the latest
+/// backup already.
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    let Ok(content) = fs::read(path) else {
+        return Ok(());
+    };
+    if serde_json::from_slice::<Value>(&content).is_err()
+        || fs::read(backup_path(path, 1)).is_ok_and(|latest| latest == content)
+    {
+        return Ok(());
+    }
+    for index in (1..BACKUPS).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Writes `settings` to `path` atomically, backing up the file it replaces
+/// if it is the first save of this run.
+fn write(path: &Path, settings: &Settings) -> Result<(), SettingsError> {
+    let content = serde_json::to_string_pretty(settings)?;
+    if !BACKED_UP.swap(true, Ordering::Relaxed) {
+        if let Err(e) = rotate_backups(path) {
+            eprintln!("Failed to back up {}: {}", path.display(), e);
+        }
+    }
+    write_atomically(path, content.as_bytes()).map_err(SettingsError::Write)
+}
+
+/// Settings as read from a file.
+struct Read {
+    settings: Settings,
+    /// Whether they were upgraded from an older version.
+    migrated: bool,
+    /// Values of the wrong type, replaced by their defaults.
+    problems: Vec<Problem>,
+}
+
+fn parses(value: &Value) -> Result<(), serde_json::Error> {
+    Settings::deserialize(value).map(|_| ())
+}
+
+fn field_mut<'a>(root: &'a mut Value, path: &[String]) -> Option<&'a mut Map<String, Value>> {
+    path.iter()
+        .try_fold(root, |value, key| value.get_mut(key))?
+        .as_object_mut()
+}
+
+/// Adds `value` at `path` to the `accepted` settings if they still parse
+/// with it. Otherwise an object is added field by field, so one value of the
+/// wrong type does not take its neighbours with it, and anything else is
+/// left to its default.
+fn accept(accepted: &mut Value, path: &mut Vec<String>, value: Value, problems: &mut Vec<Problem>) {
+    let (key, parent) = path.split_last().expect("fields have a name");
+    let insert = |accepted: &mut Value, value: Value| {
+        if let Some(fields) = field_mut(accepted, parent) {
+            fields.insert(key.clone(), value);
+        }
+    };
+    insert(accepted, value.clone());
+    let Err(e) = parses(accepted) else {
+        return;
+    };
+    if let Value::Object(fields) = value {
+        insert(accepted, Value::Object(Map::new()));
+        if parses(accepted).is_ok() {
+            for (name, value) in fields {
+                path.push(name);
+                accept(accepted, path, value, problems);
+                path.pop();
+            }
+            return;
+        }
+    }
+    if let Some(fields) = field_mut(accepted, parent) {
+        fields.remove(key);
+    }
+    problems.push(problem(path.join("."), format!("{}, using the default", e)));
+}
+
+/// Deserializes `value`, replacing values of the wrong type, such as a
+/// string where a number belongs, by their defaults.
+fn deserialize_leniently(value: Value) -> (Settings, Vec<Problem>) {
+    if let Ok(settings) = Settings::deserialize(&value) {
+        return (settings, Vec::new());
+    }
+    let Value::Object(fields) = value else {
+        let message = "the settings are not a JSON object, using the defaults";
+        return (Settings::default(), vec![problem("", message)]);
+    };
+    let mut accepted = Value::Object(Map::new());
+    let mut problems = Vec::new();
+    for (name, value) in fields {
+        accept(&mut accepted, &mut vec![name], value, &mut problems);
+    }
+    let settings = Settings::deserialize(&accepted).unwrap_or_default();
+    (settings, problems)
+}
+
+/// Reads and upgrades the settings at `path`, `None` if there are none.
+/// Only a file that is not JSON at all fails to read.
+fn read(path: &Path) -> Result<Option<Read>, SettingsError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(SettingsError::Read(e)),
+    };
+    let mut value: Value = serde_json::from_str(&content)?;
+    let migrated = migrate(&mut value)?;
+    let (settings, problems) = deserialize_leniently(value);
+    Ok(Some(Read {
+        settings,
+        migrated,
+        problems,
+    }))
+}
+
+/// Reads the settings at `path` without repairing or rewriting anything,
+/// for processes that only look at them. Unusable values are replaced by
+/// their defaults.
+pub fn peek(path: &Path) -> Option<Settings> {
+    let mut settings = read(path).ok()??.settings;
+    settings.reset_invalid();
+    Some(settings)
+}
+
+/// The most recent backup that still reads, and where it comes from.
+fn recover(path: &Path) -> Option<(Settings, PathBuf)> {
+    (1..=BACKUPS).find_map(|index| {
+        let backup = backup_path(path, index);
+        match read(&backup) {
+            Ok(Some(read)) => Some((read.settings, backup)),
+            _ => None,
+        }
+    })
+}
+
+/// Reads the settings at `path`, upgrading them if they are from an older
+/// version. A file that is no longer JSON is replaced by its last good
+/// backup, and is kept as `settings.json.corrupt`. Values of the wrong type
+/// or that cannot be used are replaced by their defaults, field by field.
+/// All of this is reported with the returned problems, as is a file that
+/// cannot be used at all.
+pub fn load(path: &Path) -> (Settings, Vec<Problem>) {
+    let mut problems = Vec::new();
+    let (mut settings, rewrite) = match read(path) {
+        Ok(Some(read)) => {
+            problems.extend(read.problems);
+            (read.settings, read.migrated)
+        }
+        Ok(None) => (Settings::default(), false),
+        Err(e) => {
+            // Only a file that is no JSON anymore is worth replacing
+            let recovered = matches!(e, SettingsError::Parse(_))
+                .then(|| recover(path))
+                .flatten();
+            match recovered {
+                Some((settings, backup)) => {
+                    if let Err(e) = fs::rename(path, sibling(path, ".corrupt")) {
+                        eprintln!("Failed to move {} aside: {}", path.display(), e);
+                    }
+                    problems.push(problem(
+                        "",
+                        format!(
+                            "{}, restored the last good copy from {}",
+                            e,
+                            backup.display()
+                        ),
+                    ));
+                    (settings, true)
+                }
+                None => {
+                    problems.push(problem("", format!("{}, using the defaults", e)));
+                    (Settings::default(), false)
+                }
+            }
+        }
+    };
+    for problem in &problems {
+        eprintln!("{}: {}", path.display(), problem);
+    }
+
+    let invalid = settings.validate();
+    for problem in &invalid {
+        eprintln!("{}: {}, using the default", path.display(), problem);
+    }
+    settings.reset_invalid();
+    problems.extend(invalid);
+
+    if rewrite {
+        if let Err(e) = write(path, &settings) {
+            eprintln!("Failed to save settings: {}", e);
+        }
+    }
+    (settings, problems)
+}
+
+/// The settings in effect, managed as Tauri state so they are only read
+/// from disk once.
+pub struct SettingsState {
+    settings: RwLock<Settings>,
+    // Problems found when the settings were loaded, or when the file was
+    // edited by hand, until they are saved or the file is fixed
+    problems: RwLock<Vec<Problem>>,
+}
+
+impl SettingsState {
+    pub fn load() -> Self {
+        let (settings, problems) = load(&get_settings_path());
+        Self {
+            settings: RwLock::new(settings),
+            problems: RwLock::new(problems),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn problems(&self) -> Vec<Problem> {
+        self.problems.read().unwrap().clone()
+    }
+
+    /// Saves the settings in effect as changed by `change` if they are
+    /// still valid, returning them. The settings stay locked from reading
+    /// them to saving them, so concurrent changes are all kept.
+    pub fn update(&self, change: impl FnOnce(&mut Settings)) -> Result<Settings, SettingsError> {
+        let mut current = self.settings.write().unwrap();
+        let mut settings = current.clone();
+        change(&mut settings);
+        let problems = settings.validate();
+        if !problems.is_empty() {
+            return Err(SettingsError::Invalid(problems));
+        }
+        write(&get_settings_path(), &settings)?;
+        *current = settings.clone();
+        self.problems.write().unwrap().clear();
+        Ok(settings)
+    }
+
+    /// Re-reads the settings file after it changed on disk, returning the
+    /// new settings if they differ from the ones in effect. Settings that do
+    /// not parse or validate are not applied, their problems are kept until
+    /// the file is fixed. A removed file is not taken for the defaults,
+    /// which would drop grants and trusted keys; editors may remove it for
+    /// a moment while saving.
+    pub fn reload(&self) -> Result<Option<Settings>, SettingsError> {
+        let read = read(&get_settings_path()).and_then(|read| {
+            let Read {
+                settings,
+                mut problems,
+                ..
+            } = read.ok_or(SettingsError::Missing)?;
+            problems.extend(settings.validate());
+            if problems.is_empty() {
+                Ok(settings)
+            } else {
+                Err(SettingsError::Invalid(problems))
+            }
+        });
+        let settings = match read {
+            Ok(settings) => settings,
+            Err(e) => {
+                *self.problems.write().unwrap() = e.problems();
+                return Err(e);
+            }
+        };
+
+        self.problems.write().unwrap().clear();
+        let mut current = self.settings.write().unwrap();
+        // Saving from the settings window changes the file too
+        if *current == settings {
+            return Ok(None);
+        }
+        *current = settings.clone();
+        Ok(Some(settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// An empty directory of its own for a test's settings file.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dossier-settings-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn moves_enabled_plugins_of_unversioned_settings() {
+        let mut value = json!({
+            "transparency": 0.5,
+            "enabledPlugins": { "calc": false, "files": true },
+            "plugins": { "calc": { "isolated": true } }
+        });
+        assert!(migrate(&mut value).unwrap());
+        assert_eq!(value["version"], json!(CURRENT_VERSION));
+        assert!(value.get("enabledPlugins").is_none());
+
+        let (settings, problems) = deserialize_leniently(value);
+        assert!(problems.is_empty());
+        assert_eq!(settings.transparency, 0.5);
+        let calc = &settings.plugins["calc"];
+        assert!(!calc.enabled);
+        assert!(calc.isolated);
+        assert!(settings.plugins["files"].enabled);
+    }
+
+    #[test]
+    fn leaves_current_settings_alone() {
+        let mut value = json!({ "version": CURRENT_VERSION, "transparency": 0.5 });
+        let before = value.clone();
+        assert!(!migrate(&mut value).unwrap());
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn refuses_settings_of_a_newer_version() {
+        let mut value = json!({ "version": CURRENT_VERSION + 1 });
+        assert!(matches!(
+            migrate(&mut value),
+            Err(SettingsError::TooNew(version)) if version == CURRENT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn does_not_downgrade_a_newer_file() {
+        let dir = temp_dir("newer");
+        let path = dir.join("settings.json");
+        let content = json!({
+            "version": CURRENT_VERSION + 1,
+            "transparency": 0.5,
+            "somethingNew": true
+        })
+        .to_string();
+        fs::write(&path, &content).unwrap();
+
+        let (settings, problems) = load(&path);
+        assert_eq!(settings, Settings::default());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("newer"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_valid_fields_next_to_ones_of_the_wrong_type() {
+        let (settings, problems) = deserialize_leniently(json!({
+            "version": CURRENT_VERSION,
+            "transparency": "high",
+            "shortcuts": { "toggleWindow": 3, "hideWindow": "Ctrl+H" },
+            "proxy": "http://proxy:8080",
+            "plugins": {
+                "calc": { "enabled": false, "searchTimeoutMs": "soon", "future": 1 }
+            }
+        }));
+        let mut fields: Vec<_> = problems.iter().map(|p| p.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            [
+                "plugins.calc.searchTimeoutMs",
+                "shortcuts.toggleWindow",
+                "transparency"
+            ]
+        );
+
+        assert_eq!(settings.transparency, Settings::default().transparency);
+        assert_eq!(settings.shortcuts.toggle_window, "Ctrl+R");
+        assert_eq!(settings.shortcuts.hide_window, "Ctrl+H");
+        assert_eq!(settings.proxy.as_deref(), Some("http://proxy:8080"));
+        let calc = &settings.plugins["calc"];
+        assert!(!calc.enabled);
+        assert_eq!(calc.search_timeout_ms, None);
+        assert_eq!(calc.other["future"], json!(1));
+    }
+
+    #[test]
+    fn resets_values_that_parse_but_cannot_be_used() {
+        let mut settings = Settings {
+            transparency: 3.0,
+            ..Settings::default()
+        };
+        settings.shortcuts.open_settings = "Ctrl+Nope".to_string();
+        let fields: Vec<_> = settings.validate().into_iter().map(|p| p.field).collect();
+        assert_eq!(fields, ["transparency", "shortcuts.openSettings"]);
+
+        settings.reset_invalid();
+        assert!(settings.validate().is_empty());
+        assert_eq!(settings.transparency, 0.8);
+        assert_eq!(settings.shortcuts.open_settings, "Ctrl+Comma");
+    }
+
+    #[test]
+    fn writes_into_a_missing_directory_without_leaving_a_temporary_file() {
+        let dir = temp_dir("write");
+        let path = dir.join("config").join("settings.json");
+        let settings = Settings {
+            transparency: 0.5,
+            ..Settings::default()
+        };
+        write(&path, &settings).unwrap();
+
+        assert_eq!(read(&path).unwrap().unwrap().settings, settings);
+        assert!(!sibling(&path, ".tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_backups_of_files_that_parse() {
+        let dir = temp_dir("backups");
+        let path = dir.join("settings.json");
+        for content in [
+            "{\"transparency\":0.5}",
+            "{\"transparency\":0.6}",
+            "{\"transparency\":0.6}",
+            "{",
+        ] {
+            fs::write(&path, content).unwrap();
+            rotate_backups(&path).unwrap();
+        }
+
+        let backup = |index| fs::read_to_string(backup_path(&path, index)).ok();
+        assert_eq!(backup(1).as_deref(), Some("{\"transparency\":0.6}"));
+        assert_eq!(backup(2).as_deref(), Some("{\"transparency\":0.5}"));
+        assert_eq!(backup(3), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recovers_a_corrupt_file_from_its_latest_good_backup() {
+        let dir = temp_dir("recover");
+        let path = dir.join("settings.json");
+        fs::write(&path, "{\"transparency\": 0.").unwrap();
+        fs::write(backup_path(&path, 1), "not json either").unwrap();
+        fs::write(backup_path(&path, 2), "{\"transparency\": 0.5}").unwrap();
+
+        let (settings, problems) = load(&path);
+        assert_eq!(settings.transparency, 0.5);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("settings.json.2"));
+        assert_eq!(
+            fs::read_to_string(sibling(&path, ".corrupt")).unwrap(),
+            "{\"transparency\": 0."
+        );
+        assert_eq!(read(&path).unwrap().unwrap().settings, settings);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_the_defaults_without_a_good_backup() {
+        let dir = temp_dir("unrecoverable");
+        let path = dir.join("settings.json");
+        fs::write(&path, "{").unwrap();
+
+        let (settings, problems) = load(&path);
+        assert_eq!(settings, Settings::default());
+        assert_eq!(problems.len(), 1);
+        // Kept for the user to repair rather than overwritten
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
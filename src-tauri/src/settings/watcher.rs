@@ -0,0 +1,77 @@
+use super::SettingsState;
+use crate::constants::get_settings_path;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+// Editors save in several steps (and settings are written to a temporary file
+// renamed over settings.json), wait for them to settle before reading.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches `settings.json` for edits made outside the settings window and
+/// applies them with `settings-changed`, like a save from the settings
+/// window would. Edits that cannot be applied are reported with
+/// `settings-problems` instead.
+pub fn watch_settings_file(app: AppHandle) -> notify::Result<()> {
+    let path = get_settings_path();
+    // The directory is watched rather than the file, which is replaced on
+    // every save
+    let dir = path
+        .parent()
+        .expect("the settings file is in the config directory");
+    std::fs::create_dir_all(dir)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    std::thread::spawn(move || {
+        // Owned by the thread so the watcher lives as long as the app
+        let _watcher = watcher;
+        let mut changed = false;
+
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(Ok(event)) => {
+                    let relevant =
+                        matches!(
+                            event.kind,
+                            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_)
+                        ) && !matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)));
+                    if relevant && event.paths.contains(&path) {
+                        changed = true;
+                    }
+                }
+                Ok(Err(e)) => eprintln!("Settings watcher error: {}", e),
+                Err(RecvTimeoutError::Timeout) => {
+                    if changed {
+                        changed = false;
+                        reload(&app);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn reload(app: &AppHandle) {
+    match app.state::<SettingsState>().reload() {
+        Ok(Some(settings)) => {
+            if let Err(e) = app.emit("settings-changed", settings) {
+                eprintln!("Failed to emit settings-changed event: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Not applying edited settings: {}", e);
+            if let Err(e) = app.emit("settings-problems", e.problems()) {
+                eprintln!("Failed to emit settings-problems event: {}", e);
+            }
+        }
+    }
+}
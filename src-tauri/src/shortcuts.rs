@@ -1,4 +1,4 @@
-use crate::commands::settings::get_settings;
+use crate::settings::SettingsState;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use tauri::{App, AppHandle, Listener, Manager};
@@ -6,23 +6,11 @@ use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 
 static REGISTERED_SHORTCUTS: std::sync::OnceLock<Arc<Mutex<Vec<Shortcut>>>> = std::sync::OnceLock::new();
 
-fn get_shortcuts_from_settings() -> (Shortcut, Shortcut, Shortcut) {
-    let settings = get_settings().unwrap_or_else(|_| serde_json::json!({}));
-    let default_shortcuts = serde_json::json!({});
-    let shortcuts = settings.get("shortcuts").unwrap_or(&default_shortcuts);
-
-    let toggle_key = shortcuts
-        .get("toggleWindow")
-        .and_then(|v| v.as_str())
-        .unwrap_or("Ctrl+R");
-    let hide_key = shortcuts
-        .get("hideWindow")
-        .and_then(|v| v.as_str())
-        .unwrap_or("Escape");
-    let settings_key = shortcuts
-        .get("openSettings")
-        .and_then(|v| v.as_str())
-        .unwrap_or("Ctrl+Comma");
+fn get_shortcuts_from_settings(app: &AppHandle) -> (Shortcut, Shortcut, Shortcut) {
+    let shortcuts = app.state::<SettingsState>().get().shortcuts;
+    let toggle_key = shortcuts.toggle_window.as_str();
+    let hide_key = shortcuts.hide_window.as_str();
+    let settings_key = shortcuts.open_settings.as_str();
 
     let toggle_shortcut = Shortcut::from_str(toggle_key).unwrap_or_else(|_| Shortcut::from_str("Ctrl+R").unwrap());
     let hide_shortcut = Shortcut::from_str(hide_key).unwrap_or_else(|_| Shortcut::from_str("Escape").unwrap());
@@ -51,7 +39,7 @@ fn register_shortcuts(app: &AppHandle, toggle_shortcut: Shortcut, hide_shortcut:
 }
 
 pub fn setup_shortcuts(app: &App) -> Result<(), Box<dyn std::error::Error>> {
-    let (toggle_shortcut, hide_shortcut, settings_shortcut) = get_shortcuts_from_settings();
+    let (toggle_shortcut, hide_shortcut, settings_shortcut) = get_shortcuts_from_settings(app.handle());
     let window = app.get_webview_window("main").unwrap();
     let app_handle = app.handle().clone();
 
@@ -59,7 +47,7 @@ pub fn setup_shortcuts(app: &App) -> Result<(), Box<dyn std::error::Error>> {
         tauri_plugin_global_shortcut::Builder::new()
             .with_handler(move |_app, _shortcut, event| {
                 if event.state() == ShortcutState::Pressed {
-                    let (current_toggle, current_hide, current_settings) = get_shortcuts_from_settings();
+                    let (current_toggle, current_hide, current_settings) = get_shortcuts_from_settings(_app);
                     if _shortcut == &current_toggle {
                         let is_visible = window.is_visible().unwrap_or(false);
                         if is_visible {
@@ -81,7 +69,7 @@ pub fn setup_shortcuts(app: &App) -> Result<(), Box<dyn std::error::Error>> {
     register_shortcuts(&app.handle(), toggle_shortcut, hide_shortcut, settings_shortcut)?;
 
     app.handle().listen("settings-changed", move |_| {
-        let (new_toggle, new_hide, new_settings) = get_shortcuts_from_settings();
+        let (new_toggle, new_hide, new_settings) = get_shortcuts_from_settings(&app_handle);
         let _ = register_shortcuts(&app_handle, new_toggle, new_hide, new_settings);
     });
 
@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
-import { loadPlugins, executePluginAction, type Plugin, type PluginResult } from '$lib/plugins'
+import { loadPlugins, loadPermissionRequests, loadDiagnostics, executePluginAction, searchAll, type ActionOutcome, type PermissionRequest, type Plugin, type PluginAction, type PluginDiagnostic, type PluginResult } from '$lib/plugins'
+import { settingsStore } from '$lib/stores/settings.svelte';
 
 import { listen, TauriEvent } from '@tauri-apps/api/event';
 
@@ -10,6 +11,54 @@ export const preventDefault = <T extends Event>(fn: (e: T) => void): ((e: T) =>
 	};
 };
 
+// Names a key press the way the backend normalizes action shortcuts, e.g. Ctrl+Shift+O
+export const keyCombo = (e: KeyboardEvent): string => {
+	const key = e.code === 'NumpadEnter' ? 'Enter' : e.code.replace(/^(Key|Digit)/, '');
+	const modifiers = [
+		e.ctrlKey && 'Ctrl',
+		e.altKey && 'Alt',
+		e.shiftKey && 'Shift',
+		e.metaKey && 'Meta'
+	].filter(Boolean);
+	return [...modifiers, key].join('+');
+};
+
+const diagnosticIcons: Record<PluginDiagnostic['status'], string> = {
+	loaded: '✅',
+	disabled: '⏸️',
+	awaitingPermission: '🔒',
+	permissionDenied: '🚫',
+	failed: '❌',
+	ignored: '➖'
+};
+
+// One line about how loading an entry of the plugins directory went
+const describeDiagnostic = (diagnostic: PluginDiagnostic): string => {
+	const details = [
+		diagnostic.error ?? {
+			loaded: `Loaded in ${diagnostic.loadTimeMs} ms`,
+			disabled: 'Disabled',
+			awaitingPermission: 'Waiting for permissions',
+			permissionDenied: 'Permissions denied',
+			failed: 'Failed',
+			ignored: 'Ignored'
+		}[diagnostic.status],
+		diagnostic.abiVersion != null && `ABI ${diagnostic.abiVersion}`
+	];
+	switch (diagnostic.signature?.status) {
+		case 'signed':
+			details.push(`signed by ${diagnostic.signature.signer}`);
+			break;
+		case 'unsigned':
+			details.push('not signed');
+			break;
+		case 'invalid':
+			details.push(`invalid signature: ${diagnostic.signature.reason}`);
+			break;
+	}
+	return details.filter(Boolean).join(' · ');
+};
+
 export class GlobalState {
 	query = $state('')
 	results = $state<PluginResult[]>([])
@@ -21,11 +70,38 @@ export class GlobalState {
 	inputElement: HTMLInputElement | undefined = $state(undefined);
 	resultsElement: HTMLUListElement | undefined = $state(undefined);
 	resultElements: (HTMLLIElement | undefined)[] = $state([]);
+	// Message shown at the bottom of the bar after an action
+	toast = $state<string | null>(null)
+	// Panel listing every action of the selected result, opened with Tab
+	actionPickerOpen = $state(false)
+	actionPickerIndex = $state(0)
+	// Dangerous action waiting for the user to confirm it with Enter
+	pendingConfirmation = $state<{ pluginId: string; result: PluginResult; action: PluginAction } | null>(null)
+	skipConfirmationNextTime = $state(false)
+	// Plugins waiting for the user to allow what they declared, asked about one at a time
+	permissionRequests = $state<PermissionRequest[]>([])
+	// Increases with every query, so responses to older ones are ignored
+	private queryId = 0;
+	// Bumped to search the same query again
+	private refreshCount = $state(0);
+	// Lists and pages shown before the ones plugin actions pushed, restored by Escape
+	private views: { results: PluginResult[]; htmlContent: string | null }[] = [];
+	private toastTimeout: ReturnType<typeof setTimeout> | undefined;
 
 	constructor() {
 		loadPlugins().then(plugins => {
 			this.plugins = plugins;
 		});
+		loadPermissionRequests().then(requests => {
+			this.permissionRequests = requests;
+		});
+
+		// Plugins were rebuilt, added or removed on disk and hot reloaded, or
+		// switched on or allowed in the settings
+		listen('plugins-changed', async () => {
+			this.plugins = await loadPlugins();
+			this.permissionRequests = await loadPermissionRequests();
+		});
 
 		listen(TauriEvent.WINDOW_BLUR, async () => {
 			await invoke('set_is_window_shown', { shown: false });
@@ -48,20 +124,33 @@ export class GlobalState {
 
 		// Search across plugins
 		$effect(() => {
+			this.refreshCount;
+			this.views = [];
+			this.actionPickerOpen = false;
+			this.pendingConfirmation = null;
 			if (!this.query.trim()) {
+				this.queryId++;
 				this.results = [];
 				this.htmlContent = null;
 				this.selectedIndex = 0;
 				return;
 			}
 
+			const queryId = ++this.queryId;
+			const isStale = () => queryId !== this.queryId;
+
 			(async () => {
 				this.isLoading = true;
 
 				try {
 					if (this.activePlugin) {
 						// Search only in active plugin
-						const pluginResults = await this.activePlugin.search(this.searchQuery);
+						const pluginResults = await this.activePlugin.search(this.searchQuery, queryId, (results) => {
+							if (isStale()) return;
+							this.results = results;
+							this.htmlContent = null;
+						});
+						if (isStale()) return;
 						if (Array.isArray(pluginResults)) {
 							this.results = pluginResults;
 							this.htmlContent = null;
@@ -73,9 +162,13 @@ export class GlobalState {
 						// Check for built-in commands first
 						const builtInResults = this.searchBuiltInCommands(this.query);
 
-						// Search across all plugins
-						const allResults = await Promise.all(this.plugins.map((plugin) => plugin.search(this.query)));
-						const flatResults = allResults.filter(r => Array.isArray(r)).flat() as PluginResult[];
+						// Search across all plugins, showing what arrives early while the rest is still searching
+						const flatResults = await searchAll(this.query, queryId, (results) => {
+							if (isStale()) return;
+							this.results = [...builtInResults, ...results];
+							this.htmlContent = null;
+						});
+						if (isStale()) return;
 
 						// Combine built-in and plugin results
 						const combinedResults = [...builtInResults, ...flatResults];
@@ -84,7 +177,8 @@ export class GlobalState {
 						if (combinedResults.length === 0) {
 							const googlePlugin = this.plugins.find(p => p.id === 'google');
 							if (googlePlugin) {
-								const googleResults = await googlePlugin.search(this.query);
+								const googleResults = await googlePlugin.search(this.query, queryId);
+								if (isStale()) return;
 								if (Array.isArray(googleResults)) {
 									this.results = googleResults;
 									this.htmlContent = null;
@@ -103,11 +197,14 @@ export class GlobalState {
 					}
 					this.selectedIndex = 0;
 				} catch (error) {
+					if (isStale()) return;
 					console.error('[v0] Search error:', error);
 					this.results = [];
 					this.htmlContent = null;
 				} finally {
-					this.isLoading = false;
+					if (!isStale()) {
+						this.isLoading = false;
+					}
 				}
 			})()
 		});
@@ -148,6 +245,28 @@ export class GlobalState {
 
 	// Keyboard navigation
 	handleKeyDown(e: KeyboardEvent) {
+		if (this.pendingConfirmation) {
+			if (e.key === 'Enter') {
+				e.preventDefault();
+				this.confirmAction();
+			} else if (e.key === 'Escape') {
+				e.preventDefault();
+				this.pendingConfirmation = null;
+			}
+			return;
+		}
+		if (this.actionPickerOpen) {
+			this.handleActionPickerKeyDown(e);
+			return;
+		}
+		// Secondary actions are reachable through the shortcuts they declare
+		const action = this.selectedResult?.actions?.find((a) => a.shortcut === keyCombo(e));
+		if (action) {
+			e.preventDefault();
+			this.executeAction(this.selectedResult, action);
+			return;
+		}
+
 		if (e.key === 'ArrowDown') {
 			e.preventDefault();
 			this.selectedIndex = Math.min(this.selectedIndex + 1, this.results.length - 1);
@@ -159,9 +278,57 @@ export class GlobalState {
 		} else if (e.key === 'Enter') {
 			e.preventDefault();
 			this.executeSelectedAction();
+		} else if (e.key === 'Tab') {
+			e.preventDefault();
+			this.openActionPicker();
 		} else if (e.key === 'Escape') {
 			e.preventDefault();
-			this.handleBackdropClick();
+			const view = this.views.pop();
+			if (view) {
+				this.results = view.results;
+				this.htmlContent = view.htmlContent;
+				this.selectedIndex = 0;
+			} else {
+				this.handleBackdropClick();
+			}
+		}
+	}
+
+	openActionPicker() {
+		if (this.selectedResult?.actions?.length) {
+			this.actionPickerIndex = 0;
+			this.actionPickerOpen = true;
+		}
+	}
+
+	private handleActionPickerKeyDown(e: KeyboardEvent) {
+		const actions = this.selectedResult?.actions ?? [];
+		if (e.key === 'ArrowDown') {
+			e.preventDefault();
+			this.actionPickerIndex = Math.min(this.actionPickerIndex + 1, actions.length - 1);
+		} else if (e.key === 'ArrowUp') {
+			e.preventDefault();
+			this.actionPickerIndex = Math.max(this.actionPickerIndex - 1, 0);
+		} else if (e.key === 'Enter') {
+			e.preventDefault();
+			this.pickAction(this.actionPickerIndex);
+		} else if (e.key === 'Escape' || e.key === 'Tab') {
+			e.preventDefault();
+			this.actionPickerOpen = false;
+		} else {
+			const index = actions.findIndex((a) => a.shortcut === keyCombo(e));
+			if (index >= 0) {
+				e.preventDefault();
+				this.pickAction(index);
+			}
+		}
+	}
+
+	pickAction(index: number) {
+		const action = this.selectedResult?.actions?.[index];
+		this.actionPickerOpen = false;
+		if (action) {
+			this.executeAction(this.selectedResult, action);
 		}
 	}
 
@@ -198,6 +365,13 @@ export class GlobalState {
 				subtitle: 'Open Dossier settings',
 				icon: 'settings',
 				actions: [{ id: 'open', label: 'Open' }]
+			},
+			{
+				id: 'plugin-diagnostics',
+				title: 'Dossier: Plugin diagnostics',
+				subtitle: 'Show which plugins loaded, and why the others did not',
+				icon: 'activity',
+				actions: [{ id: 'open', label: 'Show' }]
 			}
 		];
 
@@ -210,47 +384,114 @@ export class GlobalState {
 	// Execute selected action
 	async executeSelectedAction() {
 		const selected = this.results[this.selectedIndex];
-		if (selected && (selected.actions?.length ?? 0) > 0) {
-			const primaryAction = selected.actions?.[0];
-			if (primaryAction) {
-				// Handle built-in commands
-				if (selected.id === 'settings' && primaryAction.id === 'open') {
-					try {
-						await invoke('open_settings_window');
-						await this.handleBackdropClick();
-					} catch (error) {
-						console.error('Failed to open settings:', error);
-					}
-					return;
-				}
+		const primaryAction = selected?.actions?.[0];
+		if (primaryAction) {
+			await this.executeAction(selected, primaryAction);
+		}
+	}
 
-				// Determine plugin ID - use active plugin or find by result
-				let pluginId = this.activePlugin?.id;
-				if (!pluginId) {
-					// Find plugin that generated this result
-					for (const plugin of this.plugins) {
-						const pluginResults = await plugin.search(this.query);
-						if (pluginResults.some(r => r.id === selected.id)) {
-							pluginId = plugin.id;
-							break;
-						}
-					}
-				}
+	private async runAction(pluginId: string, result: PluginResult, action: PluginAction, confirmed = false) {
+		if (action.dangerous && !confirmed && !settingsStore.isConfirmationSkipped(pluginId, action.id)) {
+			this.skipConfirmationNextTime = false;
+			this.pendingConfirmation = { pluginId, result, action };
+			return;
+		}
+		try {
+			const outcome = await executePluginAction(pluginId, result.id, action.id, this.searchQuery, confirmed);
+			this.applyOutcome(pluginId, outcome);
+		} catch (error) {
+			console.error('Action execution failed:', error);
+			this.showToast(String(error));
+		}
+	}
 
-				if (pluginId) {
-					try {
-						await executePluginAction(pluginId, selected.id, primaryAction.id);
-					} catch (error) {
-						console.error('Action execution failed:', error);
-					}
-				}
-			}
+	async confirmAction() {
+		const pending = this.pendingConfirmation;
+		if (!pending) return;
+		this.pendingConfirmation = null;
+		if (this.skipConfirmationNextTime) {
+			settingsStore.setConfirmationSkipped(pending.pluginId, pending.action.id, true);
 		}
+		await this.runAction(pending.pluginId, pending.result, pending.action, true);
+	}
+
+	// The backend loads the plugin once allowed, or keeps it switched off
+	answerPermissionRequest(allowed: boolean) {
+		const [request, ...rest] = this.permissionRequests;
+		if (!request) return;
+		this.permissionRequests = rest;
+		settingsStore.answerPermissionRequest(request.plugin.id, allowed);
+	}
+
+	// Closing the window was already done by the backend
+	private applyOutcome(pluginId: string, outcome: ActionOutcome) {
+		if (typeof outcome === 'string') {
+			return;
+		}
+		switch (outcome.type) {
+			case 'setQuery':
+				this.query = outcome.query;
+				break;
+			case 'refresh':
+				this.refreshCount++;
+				break;
+			case 'toast':
+				this.showToast(outcome.message);
+				break;
+			case 'pushResults':
+				this.views.push({ results: this.results, htmlContent: this.htmlContent });
+				this.results = outcome.results.map((result) => ({ ...result, pluginId }));
+				this.htmlContent = null;
+				this.selectedIndex = 0;
+				break;
+			case 'showDetail':
+				// Rendered by PluginHtml in a sandboxed frame, never in the bar itself
+				this.views.push({ results: this.results, htmlContent: this.htmlContent });
+				this.htmlContent = outcome.html;
+				break;
+		}
+	}
+
+	// Lists every entry of the plugins directory, Escape goes back
+	async showPluginDiagnostics() {
+		let diagnostics: PluginDiagnostic[];
+		try {
+			diagnostics = await loadDiagnostics();
+		} catch (error) {
+			console.error('Failed to load plugin diagnostics:', error);
+			this.showToast(String(error));
+			return;
+		}
+		if (diagnostics.length === 0) {
+			this.showToast('Nothing was found in the plugins directory');
+			return;
+		}
+		this.views.push({ results: this.results, htmlContent: this.htmlContent });
+		this.results = diagnostics.map((diagnostic) => {
+			const fileName = diagnostic.path.split(/[\\/]/).pop() ?? diagnostic.path;
+			return {
+				id: diagnostic.path,
+				title: diagnostic.pluginId ? `${diagnostic.pluginId} (${fileName})` : fileName,
+				subtitle: describeDiagnostic(diagnostic),
+				icon: diagnosticIcons[diagnostic.status],
+				actions: []
+			};
+		});
+		this.htmlContent = null;
+		this.selectedIndex = 0;
+	}
+
+	showToast(message: string) {
+		clearTimeout(this.toastTimeout);
+		this.toast = message;
+		this.toastTimeout = setTimeout(() => (this.toast = null), 3000);
 	}
 
 	// Mouse hover handler
 	handleMouseEnter(index: number) {
-		this.selectedIndex = index;
+		if (!this.actionPickerOpen) {
+			this.selectedIndex = index;
+		}
 	}
 
 
@@ -271,13 +512,26 @@ export class GlobalState {
 
 	// Execute specific action
 	async executeAction(result: PluginResult, action: any) {
-		const pluginId = this.activePlugin?.id;
-		if (pluginId) {
+		// Handle built-in commands
+		if (result.id === 'settings' && action.id === 'open') {
 			try {
-				await executePluginAction(pluginId, result.id, action.id);
+				await invoke('open_settings_window');
+				await this.handleBackdropClick();
 			} catch (error) {
-				console.error('Action execution failed:', error);
+				console.error('Failed to open settings:', error);
 			}
+			return;
+		}
+
+		if (result.id === 'plugin-diagnostics' && action.id === 'open') {
+			await this.showPluginDiagnostics();
+			return;
+		}
+
+		// Results of a global search name their plugin
+		const pluginId = result.pluginId ?? this.activePlugin?.id;
+		if (pluginId) {
+			await this.runAction(pluginId, result, action);
 		}
 	}
 
@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
 
 export interface Plugin {
   id: string
@@ -7,44 +8,183 @@ export interface Plugin {
   prefix: string
   icon: string
   config?: PluginConfig
-  search: (query: string) => Promise<PluginResult[] | PluginHtmlResult>
+  // Declared in the plugin's info and manifest, see PluginGrants
+  capabilities?: Capability[]
+  search: (
+    query: string,
+    queryId?: number,
+    onBatch?: (results: PluginResult[]) => void
+  ) => Promise<PluginResult[] | PluginHtmlResult>
   onPrefixActivate?: () => void
 }
 
+// Contents of a plugin's plugin.toml, available without loading the plugin
+export interface PluginManifest {
+  id: string
+  name: string
+  version: string
+  author?: string
+  description: string
+  prefix: string
+  icon: string
+  abi_version?: number
+  capabilities: Capability[]
+  min_host_version?: string
+  library?: string
+  command?: string
+  args: string[]
+}
+
+// Something a plugin needs to be allowed to do beyond computing results
+export type Capability =
+  | "clipboard"
+  | "open-url"
+  | "network"
+  | "filesystem-read"
+  | "filesystem-write"
+  | "spawn-process"
+  | "kill-process"
+  | "notifications"
+
+// Shown when asking for a capability and in the settings
+export const capabilityDescriptions: Record<Capability, string> = {
+  "clipboard": "Read and change the clipboard",
+  "open-url": "Open links and files",
+  "network": "Access the internet",
+  "filesystem-read": "Read your files",
+  "filesystem-write": "Change your files",
+  "spawn-process": "Start programs",
+  "kill-process": "Stop running programs",
+  "notifications": "Show notifications"
+}
+
+// Key of the capability in plugins.<id>.grants, e.g. openUrl for open-url
+export function grantKey(capability: Capability): string {
+  return capability.replace(/-(\w)/g, (_, letter: string) => letter.toUpperCase())
+}
+
+// Capabilities the user has not allowed or denied yet; the backend does not
+// load a plugin until every capability it declares has been allowed
+export function undecidedCapabilities(
+  plugin: { capabilities?: Capability[] },
+  grants: Record<string, unknown> | undefined
+): Capability[] {
+  return (plugin.capabilities ?? []).filter(capability => grants?.[grantKey(capability)] === undefined)
+}
+
+// Whether the user allowed everything the plugin declares, so it is loaded
+export function capabilitiesAllowed(
+  plugin: { capabilities?: Capability[] },
+  grants: Record<string, unknown> | undefined
+): boolean {
+  return (plugin.capabilities ?? []).every(capability => grants?.[grantKey(capability)] === true)
+}
+
+// A plugin waiting for the user to allow or deny what it declared
+export interface PermissionRequest {
+  plugin: Pick<Plugin, "id" | "name" | "icon">
+  capabilities: Capability[]
+}
+
+// How a plugin library's signature checked out
+export type SignatureStatus =
+  | { status: "notChecked" }
+  | { status: "signed", signer: string }
+  | { status: "unsigned" }
+  | { status: "invalid", reason: string }
+
+// From list_plugin_signatures; pluginId is missing for a refused library without a manifest
+export type SignatureReport = {
+  library: string
+  pluginId?: string
+} & SignatureStatus
+
+// How loading went for an entry of the plugins directory, from get_plugin_diagnostics
+export interface PluginDiagnostic {
+  path: string
+  // Missing when a library without a manifest could not be opened
+  pluginId?: string
+  status: "loaded" | "disabled" | "awaitingPermission" | "permissionDenied" | "failed" | "ignored"
+  error?: string
+  abiVersion?: number
+  // Milliseconds since the Unix epoch
+  loadedAt: number
+  loadTimeMs: number
+  signature?: SignatureStatus
+}
+
 export interface PluginHtmlResult {
   html: string
 }
 
+// Settings a plugin declares, shown as a form in the settings window
 export interface PluginConfig {
-  [key: string]: any
+  fields: ConfigField[]
 }
 
+export type ConfigField = {
+  key: string
+  label: string
+  description?: string
+  default: unknown
+} & (
+  | { type: "string" }
+  | { type: "integer" | "number"; min?: number; max?: number }
+  | { type: "boolean" }
+  | { type: "enum"; options: string[] }
+)
+
 export interface PluginResult {
+  // Set on results of search_all, the plugin that runs the result's actions
+  pluginId?: string
   id: string
   title: string
   subtitle?: string
   icon?: string
   actions: PluginAction[]
+  // Relevance from the fuzzy matcher, higher is better
+  score?: number
+  // Matched parts of the title as [start, end) character indices
+  highlights?: [number, number][]
+}
+
+// Sent by stream_search_plugin for every batch of results, the last one has done set
+export interface SearchResultsEvent {
+  queryId: number
+  pluginId: string
+  result: PluginResult[] | PluginHtmlResult
+  done: boolean
 }
 
 export interface PluginAction {
   id: string
   label: string
   shortcut?: string
+  // Asked about before running, unless turned off in the settings
+  dangerous?: boolean
+  confirmation?: string
 }
 
 export async function loadPlugins(): Promise<Plugin[]> {
   try {
     const availablePlugins = await invoke<Plugin[]>("list_plugins")
     const settings = await invoke("get_settings") as any
-    const enabledPlugins = settings?.enabledPlugins || {}
     
     return availablePlugins
-      .filter(plugin => enabledPlugins[plugin.id] !== false)
+      .filter(plugin => settings?.plugins?.[plugin.id]?.enabled !== false)
+      .filter(plugin => capabilitiesAllowed(plugin, settings?.plugins?.[plugin.id]?.grants))
       .map(pluginInfo => ({
         ...pluginInfo,
-        search: async (query: string) => {
-          return await invoke<PluginResult[] | PluginHtmlResult>("search_plugin", { pluginId: pluginInfo.id, query })
+        // Passing a queryId lets the backend cancel this plugin's search for an older query
+        search: async (query: string, queryId?: number, onBatch?: (results: PluginResult[]) => void) => {
+          if (queryId !== undefined && onBatch) {
+            return await streamSearch(pluginInfo.id, query, queryId, onBatch)
+          }
+          return await invoke<PluginResult[] | PluginHtmlResult>("search_plugin", { pluginId: pluginInfo.id, query, queryId })
+        },
+        onPrefixActivate: () => {
+          invoke("plugin_prefix_activated", { pluginId: pluginInfo.id })
+            .catch(error => console.error(`Failed to activate ${pluginInfo.id}:`, error))
         }
       }))
   } catch (error) {
@@ -53,6 +193,119 @@ export async function loadPlugins(): Promise<Plugin[]> {
   }
 }
 
-export async function executePluginAction(pluginId: string, resultId: string, actionId: string): Promise<string> {
-  return await invoke<string>("execute_plugin_action", { pluginId, resultId, actionId })
+export async function loadDiagnostics(): Promise<PluginDiagnostic[]> {
+  return await invoke<PluginDiagnostic[]>("get_plugin_diagnostics")
+}
+
+export async function loadPermissionRequests(): Promise<PermissionRequest[]> {
+  try {
+    const availablePlugins = await invoke<Plugin[]>("list_plugins")
+    const settings = await invoke("get_settings") as any
+
+    return availablePlugins
+      .filter(plugin => settings?.plugins?.[plugin.id]?.enabled !== false)
+      .map(plugin => ({
+        plugin: { id: plugin.id, name: plugin.name, icon: plugin.icon },
+        capabilities: undecidedCapabilities(plugin, settings?.plugins?.[plugin.id]?.grants)
+      }))
+      .filter(request => request.capabilities.length > 0)
+  } catch (error) {
+    console.error("Failed to load permission requests:", error)
+    return []
+  }
+}
+
+interface SearchStream {
+  results: PluginResult[]
+  onBatch: (results: PluginResult[]) => void
+  resolve: (result: PluginResult[] | PluginHtmlResult) => void
+}
+
+const searchStreams = new Map<string, SearchStream>()
+let searchResultsListener: Promise<UnlistenFn> | null = null
+
+function listenForSearchResults(): Promise<UnlistenFn> {
+  searchResultsListener ??= listen<SearchResultsEvent>("search-results", (event) => {
+    const { queryId, pluginId, result, done } = event.payload
+    const key = `${queryId}:${pluginId}`
+    const stream = searchStreams.get(key)
+    if (!stream) return
+
+    if (!Array.isArray(result)) {
+      // HTML replaces the results rather than adding to them
+      searchStreams.delete(key)
+      stream.resolve(result)
+    } else if (done) {
+      searchStreams.delete(key)
+      stream.resolve([...stream.results, ...result])
+    } else {
+      stream.results = [...stream.results, ...result]
+      stream.onBatch(stream.results)
+    }
+  })
+  return searchResultsListener
+}
+
+// Resolves with all of the query's results once the plugin is done, calling
+// onBatch with the results so far whenever the plugin sends some early
+export async function streamSearch(
+  pluginId: string,
+  query: string,
+  queryId: number,
+  onBatch: (results: PluginResult[]) => void
+): Promise<PluginResult[] | PluginHtmlResult> {
+  await listenForSearchResults()
+  return new Promise((resolve, reject) => {
+    const key = `${queryId}:${pluginId}`
+    searchStreams.set(key, { results: [], onBatch, resolve })
+    invoke("stream_search_plugin", { pluginId, query, queryId }).catch((error) => {
+      searchStreams.delete(key)
+      reject(error)
+    })
+  })
+}
+
+// Ranked by search_all while slower plugins are still searching
+export interface SearchAllResultsEvent {
+  queryId: number
+  results: PluginResult[]
+}
+
+// Searches every enabled plugin and resolves with one ranked list, calling
+// onProgress with the ranking of the results found so far until then
+export async function searchAll(
+  query: string,
+  queryId: number,
+  onProgress: (results: PluginResult[]) => void
+): Promise<PluginResult[]> {
+  let finished = false
+  const unlisten = await listen<SearchAllResultsEvent>("search-all-results", (event) => {
+    if (!finished && event.payload.queryId === queryId) {
+      onProgress(event.payload.results)
+    }
+  })
+  try {
+    return await invoke<PluginResult[]>("search_all", { query, queryId })
+  } finally {
+    finished = true
+    unlisten()
+  }
+}
+
+// What the bar does once an action has run. A plain string is a status
+// message and closes the bar, as returned by older plugins
+export type ActionOutcome =
+  | { type: "close" }
+  | { type: "keepOpen" }
+  | { type: "setQuery", query: string }
+  | { type: "refresh" }
+  | { type: "toast", message: string }
+  | { type: "pushResults", results: PluginResult[] }
+  | { type: "showDetail", html: string }
+  | string
+
+// query is what was typed to find the result, remembered to rank it higher next time
+// Dangerous actions are refused unless confirmed is set
+export async function executePluginAction(pluginId: string, resultId: string, actionId: string, query?: string, confirmed?: boolean): Promise<ActionOutcome> {
+  return await invoke<ActionOutcome>("execute_plugin_action", { pluginId, resultId, actionId, query, confirmed })
 }
\ No newline at end of file
@@ -1,29 +1,95 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
-import type { Plugin } from '$lib/plugins';
+import {
+	grantKey,
+	type Capability,
+	type ConfigField,
+	type Plugin,
+	type PluginConfig,
+	type PluginManifest,
+	type SignatureReport
+} from '$lib/plugins';
 
 export interface Settings {
+	// Set by the backend, which upgrades settings written by older versions
+	version?: number;
 	transparency: number;
-	enabledPlugins: Record<string, boolean>;
 	shortcuts: {
 		toggleWindow: string;
 		hideWindow: string;
 		openSettings: string;
 	};
+	// Proxy for the HTTP requests plugins make, the environment's is used if unset
+	proxy?: string;
+	signatures?: SignatureSettings;
+	plugins: Record<string, PluginSettings>;
+}
+
+// Libraries not signed by a trusted key are loaded and reported (warn),
+// refused (enforce) or not checked at all (off)
+export type SignaturePolicy = 'off' | 'warn' | 'enforce';
+
+export interface SignatureSettings {
+	policy?: SignaturePolicy;
+	// Ed25519 public keys, base64 encoded
+	trustedKeys?: { name: string; key: string }[];
+}
+
+export interface PluginSettings {
+	enabled?: boolean;
+	isolated?: boolean;
+	grants?: PluginGrants;
+	// Set by the backend, the plugin directory or file the id belongs to
+	source?: string;
+	// Results arriving later are dropped, defaults to 5000
+	searchTimeoutMs?: number;
+	// Dangerous actions run without asking first
+	skipConfirmation?: string[];
+	// Values of the settings the plugin declares, see get_plugin_settings_schema
+	config?: Record<string, unknown>;
+}
+
+// A settings value the backend could not use, such as an invalid shortcut
+export interface SettingsProblem {
+	field: string;
+	message: string;
+}
+
+// Capabilities the user allowed or denied a plugin, keyed by grantKey; unset
+// ones were never asked about and are denied
+export interface PluginGrants {
+	clipboard?: boolean;
+	openUrl?: boolean;
+	network?: boolean;
+	filesystemRead?: boolean;
+	filesystemWrite?: boolean;
+	spawnProcess?: boolean;
+	killProcess?: boolean;
+	notifications?: boolean;
+	// Directories a WebAssembly plugin can read
+	readPaths?: string[];
+	// Directories a WebAssembly plugin can read and write
+	writePaths?: string[];
 }
 
 class SettingsStore {
 	settings = $state<Settings>({ 
 		transparency: 0.8, 
-		enabledPlugins: {},
 		shortcuts: {
 			toggleWindow: 'Ctrl+R',
 			hideWindow: 'Escape',
 			openSettings: 'Ctrl+Comma'
-		}
+		},
+		plugins: {}
 	});
+	// Why the last load or save fell back to defaults or was refused
+	problems = $state<SettingsProblem[]>([]);
 	loaded = $state(false);
 	allPlugins = $state<Plugin[]>([]);
+	manifests = $state<Record<string, PluginManifest>>({});
+	// Settings each loaded plugin declares, by plugin id
+	schemas = $state<Record<string, ConfigField[]>>({});
+	signatureReports = $state<SignatureReport[]>([]);
 	private saveTimeout: NodeJS.Timeout | null = null;
 	private initialized = false;
 
@@ -37,6 +103,18 @@ class SettingsStore {
 			this.load()
 		});
 
+		// settings.json was edited by hand in a way that cannot be applied
+		listen<SettingsProblem[]>('settings-problems', (event) => {
+			this.problems = event.payload;
+		});
+
+		listen<Plugin[]>('plugins-changed', (event) => {
+			this.allPlugins = event.payload;
+			this.loadManifests();
+			this.loadSchemas();
+			this.loadSignatureReports();
+		});
+
 		// Auto-save when settings change (debounced)
 		$effect(() => {
 			if (this.loaded) {
@@ -53,39 +131,75 @@ class SettingsStore {
 
 	async load() {
 		try {
-			const settings = await invoke('get_settings') as any;
-			this.settings = {
-				transparency: settings.transparency || 0.8,
-				enabledPlugins: settings.enabledPlugins || {},
-				shortcuts: settings.shortcuts || {
-					toggleWindow: 'Ctrl+R',
-					hideWindow: 'Escape',
-					openSettings: 'Ctrl+Comma'
-				}
-			};
+			// Already migrated, validated and filled with defaults by the backend
+			this.settings = await invoke<Settings>('get_settings');
+			this.problems = await invoke<SettingsProblem[]>('get_settings_problems');
 			this.allPlugins = await invoke('list_plugins');
+			await this.loadManifests();
+			await this.loadSchemas();
+			await this.loadSignatureReports();
 			this.loaded = true;
 		} catch (error) {
 			console.error('Failed to load settings:', error);
 			this.settings = { 
 				transparency: 0.8, 
-				enabledPlugins: {},
 				shortcuts: {
 					toggleWindow: 'Ctrl+R',
 					hideWindow: 'Escape',
 					openSettings: 'Ctrl+Comma'
-				}
+				},
+				plugins: {}
 			};
 			this.loaded = true;
 		}
 	}
 
+	async loadManifests() {
+		try {
+			const manifests = await invoke<PluginManifest[]>('list_plugin_manifests');
+			this.manifests = Object.fromEntries(manifests.map((manifest) => [manifest.id, manifest]));
+		} catch (error) {
+			console.error('Failed to load plugin manifests:', error);
+		}
+	}
+
+	async loadSchemas() {
+		const schemas: Record<string, ConfigField[]> = {};
+		for (const plugin of this.allPlugins) {
+			try {
+				const schema = await invoke<PluginConfig>('get_plugin_settings_schema', {
+					pluginId: plugin.id
+				});
+				schemas[plugin.id] = schema.fields;
+			} catch {
+				// Disabled plugins are not loaded, their settings are unknown
+			}
+		}
+		this.schemas = schemas;
+	}
+
+	async loadSignatureReports() {
+		try {
+			this.signatureReports = await invoke<SignatureReport[]>('list_plugin_signatures');
+		} catch (error) {
+			console.error('Failed to load plugin signatures:', error);
+		}
+	}
+
+	// Grants and signature settings are not saved along, they have their
+	// own commands only the settings window may use
 	async save() {
+		await this.saveWith('set_settings', { settings: this.settings });
+	}
+
+	private async saveWith(command: string, args: Record<string, unknown>) {
 		try {
-			await invoke('set_settings', { settings: this.settings });
+			await invoke(command, args);
+			this.problems = [];
 		} catch (error) {
 			console.error('Failed to save settings:', error);
-			throw error;
+			const { message, problems } = error as { message: string; problems: SettingsProblem[] };
+			this.problems = problems?.length ? problems : [{ field: '', message: message ?? String(error) }];
 		}
 	}
 
@@ -93,12 +207,108 @@ class SettingsStore {
 		return this.settings.transparency;
 	}
 
+	setProxy(proxy: string) {
+		this.settings.proxy = proxy.trim() || undefined;
+		this.save();
+	}
+
+	get signaturePolicy(): SignaturePolicy {
+		return this.settings.signatures?.policy ?? 'warn';
+	}
+
+	setSignaturePolicy(policy: SignaturePolicy) {
+		this.setSignatures({ ...this.settings.signatures, policy });
+	}
+
+	get trustedKeys() {
+		return this.settings.signatures?.trustedKeys ?? [];
+	}
+
+	addTrustedKey(name: string, key: string) {
+		const trustedKeys = [...this.trustedKeys, { name: name.trim(), key: key.trim() }];
+		this.setSignatures({ ...this.settings.signatures, trustedKeys });
+	}
+
+	removeTrustedKey(index: number) {
+		const trustedKeys = this.trustedKeys.filter((_, i) => i !== index);
+		this.setSignatures({ ...this.settings.signatures, trustedKeys });
+	}
+
+	private setSignatures(signatures: SignatureSettings) {
+		this.settings.signatures = signatures;
+		this.saveWith('set_signature_settings', { signatures });
+	}
+
 	isPluginEnabled(pluginId: string): boolean {
-		return this.settings.enabledPlugins[pluginId] !== false;
+		return this.settings.plugins[pluginId]?.enabled !== false;
 	}
 
 	togglePlugin(pluginId: string, enabled: boolean) {
-		this.settings.enabledPlugins[pluginId] = enabled;
+		this.settings.plugins[pluginId] = { ...this.settings.plugins[pluginId], enabled };
+		this.save();
+	}
+
+	isPluginIsolated(pluginId: string): boolean {
+		return this.settings.plugins[pluginId]?.isolated === true;
+	}
+
+	setPluginIsolated(pluginId: string, isolated: boolean) {
+		this.settings.plugins[pluginId] = { ...this.settings.plugins[pluginId], isolated };
+		this.save();
+	}
+
+	getPluginSearchTimeout(pluginId: string): number | undefined {
+		return this.settings.plugins[pluginId]?.searchTimeoutMs;
+	}
+
+	setPluginSearchTimeout(pluginId: string, searchTimeoutMs: number | undefined) {
+		this.settings.plugins[pluginId] = { ...this.settings.plugins[pluginId], searchTimeoutMs };
+		this.save();
+	}
+
+	getPluginConfigValue(pluginId: string, field: ConfigField): unknown {
+		return this.settings.plugins[pluginId]?.config?.[field.key] ?? field.default;
+	}
+
+	setPluginConfigValue(pluginId: string, key: string, value: unknown) {
+		const config = { ...this.settings.plugins[pluginId]?.config, [key]: value };
+		this.settings.plugins[pluginId] = { ...this.settings.plugins[pluginId], config };
+		this.save();
+	}
+
+	isCapabilityAllowed(pluginId: string, capability: Capability): boolean {
+		const grants = this.settings.plugins[pluginId]?.grants as Record<string, unknown> | undefined;
+		return grants?.[grantKey(capability)] === true;
+	}
+
+	setCapabilityAllowed(pluginId: string, capability: Capability, allowed: boolean) {
+		const grants = { ...this.settings.plugins[pluginId]?.grants, [grantKey(capability)]: allowed };
+		this.settings.plugins[pluginId] = { ...this.settings.plugins[pluginId], grants };
+		this.saveWith('set_plugin_grants', { pluginId, grants });
+	}
+
+	// Answers the question asked before a plugin is first loaded; the
+	// backend decides the capabilities it was not asked about yet, and
+	// switches a denied plugin off, it can be switched on again in the
+	// settings
+	async answerPermissionRequest(pluginId: string, allowed: boolean) {
+		try {
+			await invoke('answer_permission_request', { pluginId, allowed });
+		} catch (error) {
+			console.error('Failed to answer permission request:', error);
+		}
+	}
+
+	isConfirmationSkipped(pluginId: string, actionId: string): boolean {
+		return this.settings.plugins[pluginId]?.skipConfirmation?.includes(actionId) ?? false;
+	}
+
+	setConfirmationSkipped(pluginId: string, actionId: string, skipped: boolean) {
+		const current = this.settings.plugins[pluginId]?.skipConfirmation ?? [];
+		const skipConfirmation = skipped
+			? [...new Set([...current, actionId])]
+			: current.filter((id) => id !== actionId);
+		this.settings.plugins[pluginId] = { ...this.settings.plugins[pluginId], skipConfirmation };
 		this.save();
 	}
 
@@ -110,6 +320,24 @@ class SettingsStore {
 		}
 	}
 
+	// Forgets which results were picked, so they no longer rank higher
+	async clearHistory() {
+		try {
+			await invoke('clear_history');
+		} catch (error) {
+			console.error('Failed to clear history:', error);
+		}
+	}
+
+	// The backend asks where to save it, so the webview never names a path
+	async exportHistory() {
+		try {
+			await invoke<boolean>('export_history');
+		} catch (error) {
+			console.error('Failed to export history:', error);
+		}
+	}
+
 	async openSettings() {
 		try {
 			await invoke('open_settings_window');